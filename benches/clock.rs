@@ -0,0 +1,62 @@
+//! Instructions per second of `Cpu::clock` on a tight loop, with and
+//! without the decode cache, and of `Cpu::run_translated`. Run with
+//! `cargo bench`.
+use std::time::Instant;
+use etd3200::emulator::{Cpu, Flags, Instruction, Op, UnknownCpu};
+use etd3200::emulator::op::{Imm12, Imm22};
+
+const INSTRUCTIONS: usize = 2_000_000;
+
+/// Adds, stores and loads in a loop that never ends
+fn looping_cpu() -> Cpu {
+    let mut cpu = Cpu::new_blank();
+    cpu.trace = false;
+    cpu.program_counter = 0;
+    let one = Imm12::from_value(1).unwrap();
+    let program = [
+        Op::AddRi { d: 1, x: 1, i: one },
+        Op::Store8Bo { t: 1, base: 0, off: 64 },
+        Op::Load8Bo { t: 2, base: 0, off: 64 },
+        Op::AddRd { d: 3, x: 3, y: 2, pad: 0 },
+        Op::JumpI { target: Imm22::from_value(0).unwrap() },
+    ];
+    for (address, op) in (0..).step_by(4).zip(program) {
+        cpu.load_instruction(address, &Instruction::new(Flags::new(), op)).unwrap();
+    }
+    cpu
+}
+
+fn instructions_per_second(decode_cache: bool) -> f64 {
+    let mut cpu = looping_cpu();
+    cpu.cache_decoded(decode_cache);
+    let start = Instant::now();
+    for _ in 0..INSTRUCTIONS {
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => cpu,
+            UnknownCpu::Inter(cpu) => panic!("Unexpected interrupt {}", cpu),
+        };
+    }
+    INSTRUCTIONS as f64 / start.elapsed().as_secs_f64()
+}
+
+fn translated_per_second() -> f64 {
+    let mut cpu = looping_cpu();
+    cpu.translate_blocks(true);
+    let start = Instant::now();
+    if let UnknownCpu::Inter(cpu) = cpu.run_translated(INSTRUCTIONS) {
+        panic!("Unexpected interrupt {}", cpu);
+    }
+    INSTRUCTIONS as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    // Warm up
+    instructions_per_second(false);
+    let without = instructions_per_second(false);
+    let with = instructions_per_second(true);
+    let translated = translated_per_second();
+    println!("without decode cache: {:>12.0} instructions/s", without);
+    println!("with decode cache:    {:>12.0} instructions/s", with);
+    println!("translated blocks:    {:>12.0} instructions/s", translated);
+    println!("speed up:             {:>12.2}x, {:.2}x translated", with / without, translated / without);
+}
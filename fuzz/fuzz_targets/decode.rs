@@ -0,0 +1,10 @@
+#![no_main]
+//! Any word decodes, disassembles and encodes back to itself
+use libfuzzer_sys::fuzz_target;
+use etd3200::emulator::{Extensions, Instruction, IsaProfile};
+
+fuzz_target!(|word: u32| {
+    let instruction = Instruction::decode(word);
+    assert_eq!(Ok(word), instruction.try_encode());
+    let _ = IsaProfile::v0_1_0().disassemble(&instruction, &Extensions::new()).to_string();
+});
@@ -0,0 +1,15 @@
+#![no_main]
+//! Any memory image runs without panicking, the same on every engine.
+//! The first 4 bytes are the starting program counter, the rest memory
+//! from address 0.
+use libfuzzer_sys::fuzz_target;
+use etd3200::emulator::{Cpu, Differential, Engine};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((pc, image)) = data.split_first_chunk::<4>() else { return };
+    let pc = u32::from_le_bytes(*pc);
+    let mut differential = Differential::new(&Engine::all(), || Cpu::with_image(pc, image)).unwrap();
+    if let Err(divergence) = differential.run(1000) {
+        panic!("{divergence}");
+    }
+});
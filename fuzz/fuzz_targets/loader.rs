@@ -0,0 +1,10 @@
+#![no_main]
+//! Any text loads or is rejected with an error
+use libfuzzer_sys::fuzz_target;
+use etd3200::program_loader::{parse_labels, parse_machine_code, parse_source_lines};
+
+fuzz_target!(|text: &str| {
+    let _ = parse_machine_code(text.to_string());
+    let _ = parse_labels(text);
+    let _ = parse_source_lines(text);
+});
@@ -0,0 +1,99 @@
+/* C interface to the etd3200 emulator, implemented in src/ffi.rs.
+ * Build the library with `cargo build --release`, which leaves
+ * libetd3200.so (or .dylib/.dll) in target/release.
+ *
+ * Functions returning int give one of the ETD_ codes below unless
+ * documented otherwise. Pointers may be null, which gives ETD_NULL.
+ * Functions are only added, and ETD_ABI_VERSION goes up when they are.
+ */
+#ifndef ETD3200_H
+#define ETD3200_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define ETD_ABI_VERSION 1
+
+#define ETD_OK 0
+/* The instruction interrupted the cpu, by a fault or an interrupt */
+#define ETD_INTERRUPTED 1
+#define ETD_NULL (-1)
+/* An address or register outside the machine */
+#define ETD_OUT_OF_RANGE (-2)
+/* Text that isn't valid machine code, or an instruction that can't be
+ * encoded */
+#define ETD_INVALID (-3)
+/* The emulator panicked while stepping and the cpu was lost with it. Every
+ * later call on that cpu gives this, it can only be freed. */
+#define ETD_PANICKED (-4)
+
+typedef struct EtdCpu EtdCpu;
+
+/* Architectural state as a testbench sees it */
+typedef struct EtdState {
+    uint32_t pc;
+    /* Carry 8, greater 4, zero 2, less 1 */
+    uint8_t flags;
+    /* Indexed by register number, register 0 is always zero */
+    uint8_t registers[32];
+} EtdState;
+
+uint32_t etd_abi_version(void);
+
+/* A zeroed cpu with tracing off */
+EtdCpu *etd_cpu_new(void);
+void etd_cpu_free(EtdCpu *cpu);
+
+/* Writes count instruction words into memory from address */
+int etd_cpu_load(EtdCpu *cpu, uint32_t address, const uint32_t *words, size_t count);
+/* Loads machine code text, a line of 32 bits per instruction, into memory
+ * from address. The number of instructions goes in count if it isn't null. */
+int etd_cpu_load_machine_code(EtdCpu *cpu, uint32_t address, const char *text, size_t *count);
+int etd_cpu_write_memory(EtdCpu *cpu, uint32_t address, const uint8_t *bytes, size_t len);
+/* Writes to register 0 and the reserved register are ignored */
+int etd_cpu_write_register(EtdCpu *cpu, uint8_t reg, uint8_t value);
+
+int etd_cpu_set_pc(EtdCpu *cpu, uint32_t pc);
+/* Runs one instruction, giving ETD_PANICKED if the emulator panics */
+int etd_cpu_step(EtdCpu *cpu);
+/* Steps until the cpu is interrupted or has spent budget cycles, giving
+ * ETD_OK if the budget ran out. The instructions run go in steps if it
+ * isn't null. */
+int etd_cpu_run(EtdCpu *cpu, uint64_t budget, uint64_t *steps);
+/* Cycles taken by every step so far, 0 if cpu is null or was lost to a
+ * panic */
+uint64_t etd_cpu_cycles(const EtdCpu *cpu);
+
+/* Why the last step was interrupted: 0 for none or a software interrupt,
+ * 1 invalid register, 2 unmapped, 3 misaligned, 4 unknown opcode. The
+ * register, address or opcode goes in detail if it isn't null. */
+int etd_cpu_fault(const EtdCpu *cpu, uint32_t *detail);
+
+int etd_cpu_read_register(const EtdCpu *cpu, uint8_t reg, uint8_t *value);
+/* Copies len bytes of memory from address into out */
+int etd_cpu_read_memory(const EtdCpu *cpu, uint32_t address, uint8_t *out, size_t len);
+
+int etd_cpu_state(const EtdCpu *cpu, EtdState *state);
+/* 0 if the cpu matches state, otherwise the first difference: 1 pc,
+ * 2 flags or 0x100 plus the register number */
+int etd_cpu_compare(const EtdCpu *cpu, const EtdState *state);
+
+/* Packs flags (as in EtdState), a 6 bit opcode and 22 operand bits into an
+ * instruction word */
+int etd_instruction_encode(uint8_t flags, uint8_t opcode, uint32_t operands, uint32_t *word);
+/* Splits a word into the fields etd_instruction_encode takes, skipping
+ * any that are null */
+int etd_instruction_decode(uint32_t word, uint8_t *flags, uint8_t *opcode, uint32_t *operands);
+/* Writes the instruction's assembly into out, cut short if it doesn't fit,
+ * and gives the length of the whole text like snprintf */
+int etd_instruction_disassemble(uint32_t word, char *out, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,190 @@
+"""ctypes bindings for the emulator's C ABI (include/etd3200.h).
+
+Build the library first with `cargo build --release`. It's looked for in
+$ETD3200_LIB, then target/release and target/debug of this repository.
+
+    cpu = Cpu()
+    cpu.load_machine_code(open("sample_code/1-10.mc").read())
+    cpu.run(cycles=1000)
+    print(cpu.registers()[1], cpu.memory(64, 4))
+"""
+import ctypes
+import os
+import sys
+from ctypes import POINTER, c_char_p, c_int32, c_size_t, c_uint8, c_uint32, c_uint64, c_void_p
+from pathlib import Path
+
+ABI_VERSION = 1
+
+OK = 0
+INTERRUPTED = 1
+NULL = -1
+OUT_OF_RANGE = -2
+INVALID = -3
+PANICKED = -4
+
+FAULTS = {1: "invalid register", 2: "unmapped", 3: "misaligned", 4: "unknown opcode"}
+
+
+class State(ctypes.Structure):
+    _fields_ = [("pc", c_uint32), ("flags", c_uint8), ("registers", c_uint8 * 32)]
+
+
+class EmulatorError(Exception):
+    pass
+
+
+def _library_name():
+    if sys.platform == "win32":
+        return "etd3200.dll"
+    if sys.platform == "darwin":
+        return "libetd3200.dylib"
+    return "libetd3200.so"
+
+
+def _load():
+    if "ETD3200_LIB" in os.environ:
+        return ctypes.CDLL(os.environ["ETD3200_LIB"])
+    root = Path(__file__).resolve().parent.parent
+    for profile in ("release", "debug"):
+        path = root / "target" / profile / _library_name()
+        if path.exists():
+            return ctypes.CDLL(str(path))
+    raise EmulatorError("libetd3200 not found, run cargo build --release or set ETD3200_LIB")
+
+
+_lib = _load()
+_signatures = {
+    "etd_abi_version": ([], c_uint32),
+    "etd_cpu_new": ([], c_void_p),
+    "etd_cpu_free": ([c_void_p], None),
+    "etd_cpu_load": ([c_void_p, c_uint32, POINTER(c_uint32), c_size_t], c_int32),
+    "etd_cpu_load_machine_code": ([c_void_p, c_uint32, c_char_p, POINTER(c_size_t)], c_int32),
+    "etd_cpu_write_memory": ([c_void_p, c_uint32, POINTER(c_uint8), c_size_t], c_int32),
+    "etd_cpu_write_register": ([c_void_p, c_uint8, c_uint8], c_int32),
+    "etd_cpu_set_pc": ([c_void_p, c_uint32], c_int32),
+    "etd_cpu_step": ([c_void_p], c_int32),
+    "etd_cpu_run": ([c_void_p, c_uint64, POINTER(c_uint64)], c_int32),
+    "etd_cpu_cycles": ([c_void_p], c_uint64),
+    "etd_cpu_fault": ([c_void_p, POINTER(c_uint32)], c_int32),
+    "etd_cpu_read_register": ([c_void_p, c_uint8, POINTER(c_uint8)], c_int32),
+    "etd_cpu_read_memory": ([c_void_p, c_uint32, POINTER(c_uint8), c_size_t], c_int32),
+    "etd_cpu_state": ([c_void_p, POINTER(State)], c_int32),
+    "etd_cpu_compare": ([c_void_p, POINTER(State)], c_int32),
+    "etd_instruction_encode": ([c_uint8, c_uint8, c_uint32, POINTER(c_uint32)], c_int32),
+    "etd_instruction_decode": ([c_uint32, POINTER(c_uint8), POINTER(c_uint8), POINTER(c_uint32)], c_int32),
+    "etd_instruction_disassemble": ([c_uint32, ctypes.c_char_p, c_size_t], c_int32),
+}
+for _name, (_arguments, _result) in _signatures.items():
+    _function = getattr(_lib, _name)
+    _function.argtypes = _arguments
+    _function.restype = _result
+
+if _lib.etd_abi_version() < ABI_VERSION:
+    raise EmulatorError("libetd3200 is older than these bindings, rebuild it")
+
+
+def _check(code):
+    if code < 0:
+        raise EmulatorError({NULL: "null pointer", OUT_OF_RANGE: "out of range", INVALID: "invalid input", PANICKED: "emulator panicked"}[code])
+    return code
+
+
+def encode(flags, opcode, operands):
+    """Packs flags (carry 8, greater 4, zero 2, less 1), opcode and operands into a word"""
+    word = c_uint32()
+    _check(_lib.etd_instruction_encode(flags, opcode, operands, ctypes.byref(word)))
+    return word.value
+
+
+def decode(word):
+    """Splits a word into (flags, opcode, operands)"""
+    flags, opcode, operands = c_uint8(), c_uint8(), c_uint32()
+    _lib.etd_instruction_decode(word, ctypes.byref(flags), ctypes.byref(opcode), ctypes.byref(operands))
+    return flags.value, opcode.value, operands.value
+
+
+def disassemble(word):
+    length = _lib.etd_instruction_disassemble(word, None, 0)
+    text = ctypes.create_string_buffer(length + 1)
+    _lib.etd_instruction_disassemble(word, text, len(text))
+    return text.value.decode()
+
+
+class Cpu:
+    """A zeroed cpu. Steps and runs return True if the cpu was interrupted."""
+
+    def __init__(self):
+        self._cpu = _lib.etd_cpu_new()
+
+    def __del__(self):
+        if getattr(self, "_cpu", None):
+            _lib.etd_cpu_free(self._cpu)
+            self._cpu = None
+
+    def load(self, words, address=0):
+        words = (c_uint32 * len(words))(*words)
+        _check(_lib.etd_cpu_load(self._cpu, address, words, len(words)))
+
+    def load_machine_code(self, text, address=0):
+        """Loads machine code text and gives the number of instructions"""
+        count = c_size_t()
+        _check(_lib.etd_cpu_load_machine_code(self._cpu, address, text.encode(), ctypes.byref(count)))
+        return count.value
+
+    @property
+    def pc(self):
+        return self.state().pc
+
+    @pc.setter
+    def pc(self, pc):
+        _check(_lib.etd_cpu_set_pc(self._cpu, pc))
+
+    @property
+    def cycles(self):
+        return _lib.etd_cpu_cycles(self._cpu)
+
+    @property
+    def flags(self):
+        return self.state().flags
+
+    def step(self):
+        return _check(_lib.etd_cpu_step(self._cpu)) == INTERRUPTED
+
+    def run(self, cycles):
+        """Runs until interrupted or `cycles` have been spent, giving
+        (interrupted, instructions run)"""
+        steps = c_uint64()
+        code = _check(_lib.etd_cpu_run(self._cpu, cycles, ctypes.byref(steps)))
+        return code == INTERRUPTED, steps.value
+
+    def fault(self):
+        """(kind, register/address/opcode) of the last fault, or None"""
+        detail = c_uint32()
+        kind = _check(_lib.etd_cpu_fault(self._cpu, ctypes.byref(detail)))
+        return (FAULTS[kind], detail.value) if kind else None
+
+    def register(self, register):
+        value = c_uint8()
+        _check(_lib.etd_cpu_read_register(self._cpu, register, ctypes.byref(value)))
+        return value.value
+
+    def set_register(self, register, value):
+        _check(_lib.etd_cpu_write_register(self._cpu, register, value))
+
+    def registers(self):
+        return list(self.state().registers)
+
+    def memory(self, address, length):
+        out = (c_uint8 * length)()
+        _check(_lib.etd_cpu_read_memory(self._cpu, address, out, length))
+        return bytes(out)
+
+    def write_memory(self, address, data):
+        data = (c_uint8 * len(data))(*data)
+        _check(_lib.etd_cpu_write_memory(self._cpu, address, data, len(data)))
+
+    def state(self):
+        state = State()
+        _check(_lib.etd_cpu_state(self._cpu, ctypes.byref(state)))
+        return state
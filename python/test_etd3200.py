@@ -0,0 +1,47 @@
+"""Run with `python3 -m unittest discover python` after `cargo build`"""
+import unittest
+from pathlib import Path
+
+import etd3200
+
+SAMPLE = Path(__file__).resolve().parent.parent / "sample_code" / "1-10.mc"
+
+
+class CpuTest(unittest.TestCase):
+    def test_runs_the_sample(self):
+        cpu = etd3200.Cpu()
+        self.assertGreater(cpu.load_machine_code(SAMPLE.read_text()), 0)
+        interrupted, steps = cpu.run(cycles=10_000)
+        self.assertTrue(interrupted)
+        self.assertGreater(steps, 0)
+        self.assertIsNone(cpu.fault())
+        self.assertGreater(cpu.cycles, 0)
+
+    def test_registers_and_memory(self):
+        cpu = etd3200.Cpu()
+        cpu.set_register(3, 7)
+        self.assertEqual(7, cpu.registers()[3])
+        cpu.write_memory(100, b"\x01\x02")
+        self.assertEqual(b"\x01\x02", cpu.memory(100, 2))
+        with self.assertRaises(etd3200.EmulatorError):
+            cpu.memory(254, 2)
+
+    def test_budget_runs_out(self):
+        cpu = etd3200.Cpu()
+        # Puts 5 in r1 then jumps back to the start
+        cpu.load_machine_code("0000-001100-00001 00000 00000 0000101\n0000-011000-00000 00000 00000 0000000\n")
+        interrupted, steps = cpu.run(cycles=10)
+        self.assertFalse(interrupted)
+        self.assertGreater(steps, 1)
+        self.assertGreaterEqual(cpu.cycles, 10)
+
+    def test_encoding(self):
+        word = etd3200.encode(8, 0b001100, 5)
+        self.assertEqual((8, 0b001100, 5), etd3200.decode(word))
+        self.assertTrue(etd3200.disassemble(word))
+        with self.assertRaises(etd3200.EmulatorError):
+            etd3200.encode(0, 64, 0)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -1,14 +1,54 @@
 #![allow(dead_code)]
 pub mod instruction;
-mod memory;
+pub mod op;
+pub mod memory;
+pub mod fault;
+pub mod extension;
+pub mod profile;
+pub mod snapshot;
+pub mod history;
+pub mod breakpoint;
+pub mod timing;
+pub mod pipeline;
+pub mod cache;
+pub mod predictor;
+pub mod profiler;
+pub mod coverage;
+pub mod decode;
+pub mod translate;
+pub mod differential;
+pub mod vectors;
+pub mod builder;
+pub mod handle;
 mod flags;
 
 use std::fmt;
-use rand;
 pub use instruction::Instruction;
+pub use op::Op;
+use op::{Imm12, Imm22};
+pub use fault::Fault;
+pub use extension::Extensions;
+pub use profile::IsaProfile;
+pub use snapshot::Snapshot;
+pub use history::History;
+pub use breakpoint::{Breakpoints, Hit};
+use breakpoint::{Access, Location};
+pub use timing::CostTable;
+pub use pipeline::Pipeline;
+pub use cache::{CacheConfig, CachedMemory};
+pub use decode::DecodeCache;
+pub use translate::Translation;
+pub use differential::{Differential, Engine};
+pub use vectors::Vectors;
+pub use builder::CpuBuilder;
+pub use handle::{Handle, Stepped};
 use memory::Memory;
 use memory::SimpleMemory;
-use flags::Flags;
+use memory::MisalignedAccess;
+pub use flags::Flags;
+use history::Step;
+use translate::Block;
+use timing::Class;
 
 #[must_use]
 pub enum UnknownCpu {
@@ -16,13 +56,59 @@ pub enum UnknownCpu {
     Ok(Cpu)
 }
 
+impl UnknownCpu {
+    pub fn cpu(&self) -> &Cpu {
+        match self {
+            UnknownCpu::Inter(cpu) | UnknownCpu::Ok(cpu) => cpu,
+        }
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        match self {
+            UnknownCpu::Inter(cpu) | UnknownCpu::Ok(cpu) => cpu,
+        }
+    }
+
+    pub fn into_cpu(self) -> Cpu {
+        match self {
+            UnknownCpu::Inter(cpu) | UnknownCpu::Ok(cpu) => cpu,
+        }
+    }
+}
+
 
 pub struct Cpu {
-    general_purpose: [u8;29],
+    general_purpose: Vec<u8>,
     stack_pointer: u8,
-    pub program_counter: u8,
+    pub program_counter: u32,
     flags: Flags,
     pub memory: Box<dyn Memory>,
+    /// What multi-byte loads and stores do with unaligned addresses
+    pub misaligned: MisalignedAccess,
+    /// Why the last clock was interrupted, None for a software interrupt
+    pub fault: Option<Fault>,
+    /// Instructions installed into the reserved opcodes
+    pub extensions: Extensions,
+    profile: IsaProfile,
+    /// Undo log, None unless turned on with record_history
+    history: Option<History>,
+    /// Checked by step and run, clock ignores them
+    pub breakpoints: Breakpoints,
+    /// Registers and memory accessed by the running instruction with the
+    /// value read or written, only kept while stepping with watchpoints set
+    /// or recording vectors
+    accesses: Option<Vec<(Location, Access, u8)>>,
+    /// Cycles taken by every clock so far, as costed by `timing`
+    pub cycles: u64,
+    pub timing: CostTable,
+    /// None unless turned on with cache_decoded
+    decoded: Option<DecodeCache>,
+    /// None unless turned on with translate_blocks
+    translated: Option<Translation>,
+    /// Print each instruction and its operands as it runs
+    pub trace: bool,
+    /// What CpuBuilder filled registers and memory from, None otherwise
+    seed: Option<builder::Seed>,
 }
 
 impl fmt::Display for Cpu {
@@ -31,11 +117,14 @@ impl fmt::Display for Cpu {
         writeln!(fmt, "StackPointer {:#?}", self.stack_pointer)?;
         writeln!(fmt, "Program Counter {:#?}", self.program_counter)?;
         writeln!(fmt, "Flag Register {}", self.flags)?;
+        if let Some(seed) = self.seed() {
+            writeln!(fmt, "Power-on Seed {seed}")?;
+        }
 
 
         writeln!(fmt, "Current Instructions")?;
         // Current place in instructions
-        let first = i8::max(0, (self.program_counter as i8)/4 - 4) as u8;
+        let first = (self.program_counter / 4).saturating_sub(4).min(u32::MAX / 4 - 8);
         for i in first..first + 9{
             let pc = i * 4;
                 write!(fmt, "{pc:3}||")?;
@@ -44,7 +133,10 @@ impl fmt::Display for Cpu {
             } else {
                 write!(fmt, "   ")?;
             }
-            writeln!(fmt,"{}", self.instruction_at(pc))?
+            match self.fetch(pc) {
+                Ok(instruction) => writeln!(fmt, "{}", self.profile.disassemble(&instruction, &self.extensions))?,
+                Err(fault) => writeln!(fmt, "|{}", fault)?,
+            }
         }
         
         // Print General Registers
@@ -59,27 +151,93 @@ impl fmt::Display for Cpu {
     }
 }
 
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu::new()
+    }
+}
+
 impl Cpu {
-    const fn is_valid_register(&self, value: u8) -> bool {
-        return value < 31
+    /// The register above the general purpose ones is reserved, it reads
+    /// as zero and ignores writes
+    fn is_valid_register(&self, value: u8) -> bool {
+        value <= self.profile.general_purpose + 1
     }
     
-    fn copy_from_memory(&mut self, from: u8, to:u8) -> Result<(),&'static str> {
-        if !self.is_valid_register(to) {
-            return Err("Invalid register to write too");
+    /// Reads `width` bytes of memory, failing if any of them is unmapped
+    fn read_memory(&self, address: u32, width: u8) -> Result<Vec<u8>, Fault> {
+        (address..address + width as u32)
+            .map(|address| u8::try_from(address).ok()
+                .and_then(|addr| self.memory.read(addr))
+                .ok_or(Fault::Unmapped(address)))
+            .collect()
+    }
+
+    /// The addresses of `width` bytes from `address`, failing if any of
+    /// them can't be written
+    fn check_memory(&self, address: u32, width: u8) -> Result<Vec<u8>, Fault> {
+        (address..address + width as u32)
+            .map(|address| u8::try_from(address).ok()
+                .filter(|addr| self.memory.contains(*addr))
+                .ok_or(Fault::Unmapped(address)))
+            .collect()
+    }
+
+    /// Writes `values` to memory, only once every address is known to be mapped
+    fn write_memory(&mut self, address: u32, values: &[u8]) -> Result<(), Fault> {
+        self.write_memory_with(address, values, |memory, addr, value| memory.write(addr, value))
+    }
+
+    fn write_memory_with(&mut self, address: u32, values: &[u8], write: fn(&mut dyn Memory, u8, u8) -> Result<(), &'static str>) -> Result<(), Fault> {
+        let width = u8::try_from(values.len()).map_err(|_| Fault::Unmapped(address))?;
+        let addresses = self.check_memory(address, width)?;
+        for (addr, value) in addresses.into_iter().zip(values) {
+            if let (Some(history), Some(old)) = (&mut self.history, self.memory.peek(addr)) {
+                history.memory(addr, old);
+            }
+            write(&mut *self.memory, addr, *value)
+                .map_err(|_| Fault::Unmapped(addr as u32))?;
+            self.code_written(addr);
         }
-        let value = self.memory.read(from)
-            .ok_or("Invalid read from memory")?;
-        self.write(to, value);
         Ok(())
     }
 
-    fn copy_to_memory(&mut self, from: u8, to:u8) -> Result<(),&'static str> {
-        if !self.is_valid_register(from) {
-            return Err("Invalid register to write to");
+    /// Puts a byte into memory from the host, without it costing cycles or
+    /// counting in cache statistics
+    fn poke(&mut self, address: u8, value: u8) -> Result<(), &'static str> {
+        self.memory.poke(address, value)?;
+        self.code_written(address);
+        // In case the backend can't help charging for it
+        self.memory.take_penalty();
+        Ok(())
+    }
+
+    /// Reads registers first, first + 1... failing if any of them is invalid
+    fn read_registers(&self, first: u8, width: u8) -> Result<Vec<u8>, Fault> {
+        (first..first + width)
+            .map(|register| if self.is_valid_register(register) {
+                Ok(self.read(register))
+            } else {
+                Err(Fault::InvalidRegister(register))
+            })
+            .collect()
+    }
+
+    /// Fails with the last of registers first, first + 1... if it's invalid
+    fn check_registers(&self, first: u8, width: u8) -> Result<(), Fault> {
+        let last = first + width - 1;
+        match self.is_valid_register(last) {
+            true => Ok(()),
+            false => Err(Fault::InvalidRegister(last)),
+        }
+    }
+
+    /// Writes registers first, first + 1... only if all of them are valid
+    fn write_registers(&mut self, first: u8, values: &[u8]) -> Result<(), Fault> {
+        self.check_registers(first, values.len() as u8)?;
+        for (register, value) in (first..).zip(values) {
+            self.write(register, *value);
         }
-        let value = self.read(from);
-        self.memory.write(to, value)?;
         Ok(())
     }
 
@@ -88,118 +246,507 @@ impl Cpu {
         result
     }
 
-    /// Creates a new cpu with random values all values
+    /// Creates a new cpu with random values all values, see CpuBuilder to
+    /// choose the seed
     pub fn new() -> Cpu {
-        use rand::Fill;
-        let mut rng = rand::thread_rng();
-        let mut cpu = Cpu {
-            general_purpose: [0;29],
-            stack_pointer: 0,
-            program_counter: 1,
-            flags: Flags::new(),
-            memory: Box::new(SimpleMemory::new())
-        };
-        cpu.general_purpose.try_fill(&mut rng)
-            .expect("Failed to create random values on Cpu creation");
-        cpu
+        CpuBuilder::new().build().expect("The default profile can be run")
     }
+
     /// Creates a new zero'd cpu
     pub fn new_blank() -> Cpu {
+        Cpu::blank(IsaProfile::v0_1_0())
+    }
+
+    /// Creates a new zero'd cpu, with tracing off, running `image` from
+    /// address 0 at `program_counter`. Bytes past the end of memory are
+    /// left out.
+    pub fn with_image(program_counter: u32, image: &[u8]) -> Cpu {
+        let mut cpu = Cpu::new_blank();
+        cpu.trace = false;
+        cpu.program_counter = program_counter;
+        for (address, value) in (0..=u8::MAX).zip(image) {
+            cpu.memory.write(address, *value).ok();
+        }
+        cpu
+    }
+
+    /// Creates a new zero'd cpu implementing a revision of the ISA, failing
+    /// if the profile can't be run
+    pub fn with_profile(profile: IsaProfile) -> Result<Cpu, &'static str> {
+        profile.check()?;
+        Ok(Cpu::blank(profile))
+    }
+
+    /// A zero'd cpu for a profile that's already been checked
+    pub(crate) fn blank(profile: IsaProfile) -> Cpu {
         Cpu {
-            general_purpose: [0;29],
+            general_purpose: vec![0; profile.general_purpose as usize],
             stack_pointer: 0,
-            program_counter: 1,
+            program_counter: 0,
             flags: Flags::new(),
             memory: Box::new(SimpleMemory::new_blank()),
+            misaligned: MisalignedAccess::default(),
+            fault: None,
+            extensions: Extensions::new(),
+            profile,
+            history: None,
+            breakpoints: Breakpoints::new(),
+            accesses: None,
+            cycles: 0,
+            timing: CostTable::default(),
+            decoded: None,
+            translated: None,
+            trace: true,
+            seed: None,
+        }
+    }
+
+    /// Turns the decode cache on, emptying it, or off
+    pub fn cache_decoded(&mut self, on: bool) {
+        self.decoded = on.then(DecodeCache::new);
+    }
+
+    pub fn decode_cache(&self) -> Option<&DecodeCache> {
+        self.decoded.as_ref()
+    }
+
+    /// Turns block translation for run_translated on, emptying it, or off
+    pub fn translate_blocks(&mut self, on: bool) {
+        self.translated = on.then(Translation::new);
+    }
+
+    pub fn translation(&self) -> Option<&Translation> {
+        self.translated.as_ref()
+    }
+
+    /// Empties the decode cache and translation, for when what the code
+    /// decodes to may have changed
+    fn forget_decoded(&mut self) {
+        if let Some(decoded) = &mut self.decoded {
+            decoded.clear();
+        }
+        if let Some(translated) = &mut self.translated {
+            translated.clear();
         }
     }
 
+    fn code_written(&mut self, address: u8) {
+        if let Some(decoded) = &mut self.decoded {
+            decoded.invalidate(address as u32);
+        }
+        if let Some(translated) = &mut self.translated {
+            translated.invalidate(address as u32);
+        }
+    }
+
+    pub fn profile(&self) -> &IsaProfile {
+        &self.profile
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    /// Installs an extension at an opcode the cpu's profile leaves free
+    pub fn register_extension(&mut self, opcode: u8, mnemonic: &'static str, handler: extension::Handler) -> Result<(), &'static str> {
+        self.extensions.register(&self.profile, opcode, mnemonic, handler)?;
+        self.forget_decoded();
+        Ok(())
+    }
+
+    /// Switches to another revision of the ISA with as many registers
+    pub fn set_profile(&mut self, profile: IsaProfile) -> Result<(), &'static str> {
+        if profile.general_purpose != self.profile.general_purpose {
+            return Err("Profile has a different number of registers");
+        }
+        self.profile = profile;
+        self.forget_decoded();
+        Ok(())
+    }
+
+    /// The seed the cpu was built from, to build it again
+    pub fn seed(&self) -> Option<u64> {
+        self.seed.as_ref().map(|seed| seed.0)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::take(self)
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), &'static str> {
+        snapshot.restore(self)
+    }
+
     pub fn read(&self, addr: u8) -> u8 {
         match addr {
             0 => 0,
-            1..=29 => self.general_purpose[(addr - 1) as usize],
-            30.. => 0
+            addr => self.general_purpose.get(addr as usize - 1).copied().unwrap_or(0),
         }
     }
 
     pub fn write(&mut self, addr: u8, value: u8) {
-        match addr {
-            0 => (),
-            1..=29 => self.general_purpose[(addr - 1) as usize] = value,
-            30.. => ()
-        };
+        if addr == 0 {
+            return;
+        }
+        if let Some(register) = self.general_purpose.get_mut(addr as usize - 1) {
+            if let Some(history) = &mut self.history {
+                history.register(addr, *register);
+            }
+            if let Some(accesses) = &mut self.accesses {
+                accesses.push((Location::Register(addr), Access::Write, value));
+            }
+            *register = value;
+        }
     }
 
-    pub fn current_instruction(&self) -> Instruction {
-        self.instruction_at(self.program_counter)
+    fn log_memory(&mut self, address: u32, values: &[u8], access: Access) {
+        if let Some(accesses) = &mut self.accesses {
+            for (address, value) in (address..).zip(values) {
+                accesses.push((Location::Memory(address as u8), access, *value));
+            }
+        }
     }
 
-    fn instruction_at(&self, i:u8) -> Instruction {
-        Instruction::decode(self.memory.read_u32(i).unwrap())
+    /// Runs one instruction, reporting any watchpoint it fired and any
+    /// breakpoint at the instruction it moved on to
+    pub fn step(mut self) -> (UnknownCpu, Vec<Hit>) {
+        let mut accesses = Vec::new();
+        if self.breakpoints.has_watchpoints() {
+            if let Ok(instruction) = self.fetch(self.program_counter) {
+                if Flags::instruction_can_run(&self.flags, &instruction.flags) {
+                    accesses.extend(self.profile.op(&instruction).sources().into_iter()
+                        .map(|register| (Location::Register(register), Access::Read)));
+                }
+            }
+            self.accesses = Some(Vec::new());
+        }
+
+        let mut result = self.clock();
+        let cpu = result.cpu_mut();
+        let mut hits = Vec::new();
+        if let Some(logged) = cpu.accesses.take() {
+            accesses.extend(logged.into_iter().map(|(location, access, _)| (location, access)));
+            hits = cpu.breakpoints.watchpoints_hit(&accesses);
+        }
+        if let UnknownCpu::Ok(cpu) = &result {
+            hits.extend(cpu.breakpoints.breakpoints_hit(cpu));
+        }
+        (result, hits)
     }
 
-    pub fn load_instruction(&mut self, location: u8, instruction: &Instruction) {
-            println!("Loading into {}: {}", location, instruction);
-        //TODO Add check for write...
-        match self.memory.write_u32(location, instruction.encode()) {
-            Ok(_) => (),
-            Err(_) => panic!("Instruction failed to be loaded into memory"),
+    /// Clocks the cpu through `clock`, which can look at each instruction
+    /// as it runs, until it's interrupted or `limit` instructions have run
+    pub fn run_with(self, limit: usize, mut clock: impl FnMut(Cpu) -> UnknownCpu) -> UnknownCpu {
+        let mut cpu = self;
+        for _ in 0..limit {
+            match clock(cpu) {
+                UnknownCpu::Ok(next) => cpu = next,
+                interrupted => return interrupted,
+            }
+        }
+        UnknownCpu::Ok(cpu)
+    }
 
+    /// Steps until a breakpoint or watchpoint fires, the cpu is interrupted
+    /// or `limit` instructions have run. A breakpoint on the first
+    /// instruction doesn't stop it, so a run can carry on from a breakpoint.
+    pub fn run(self, limit: usize) -> (UnknownCpu, Vec<Hit>) {
+        let mut cpu = self;
+        for _ in 0..limit {
+            match cpu.step() {
+                (UnknownCpu::Ok(next), hits) if hits.is_empty() => cpu = next,
+                stopped => return stopped,
+            }
         }
+        (UnknownCpu::Ok(cpu), Vec::new())
+    }
+
+    /// Starts keeping an undo log of the last `limit` clocks, clearing any
+    /// log already kept
+    pub fn record_history(&mut self, limit: usize) {
+        self.history = Some(History::new(limit));
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Undoes up to `n` clocks, returning how many were undone
+    pub fn step_back(&mut self, n: usize) -> usize {
+        let mut undone = 0;
+        while undone < n {
+            let Some(step) = self.history.as_mut().and_then(History::pop) else {
+                break;
+            };
+            self.undo(step);
+            undone += 1;
+        }
+        undone
+    }
+
+    /// Undoes clocks until just before the most recent one that wrote to
+    /// `address`, returning how many were undone. Does nothing if no write
+    /// to the address is in the log.
+    pub fn step_back_to_write(&mut self, address: u8) -> Option<usize> {
+        let steps_ago = self.history.as_ref()?
+            .steps()
+            .rev()
+            .position(|step| step.wrote(address))?;
+        Some(self.step_back(steps_ago + 1))
+    }
+
+    fn undo(&mut self, step: Step) {
+        for (address, old) in step.memory.into_iter().rev() {
+            self.poke(address, old).ok();
+        }
+        for (register, old) in step.registers.into_iter().rev() {
+            self.general_purpose[register as usize - 1] = old;
+        }
+        self.flags = step.flags;
+        self.program_counter = step.program_counter;
+        self.cycles = step.cycles;
+        self.fault = None;
+    }
+
+    /// Fails if the program counter is outside of memory
+    pub fn current_instruction(&self) -> Result<Instruction, Fault> {
+        self.fetch(self.program_counter)
+    }
+
+    /// Looks at the instruction at `address` without it counting as a fetch
+    pub(crate) fn fetch(&self, address: u32) -> Result<Instruction, Fault> {
+        self.fetch_with(address, |memory, addr| memory.peek(addr))
+    }
+
+    /// The fetch made when running an instruction, through any instruction cache
+    fn fetch_instruction(&self, address: u32) -> Result<Instruction, Fault> {
+        self.fetch_with(address, |memory, addr| memory.fetch(addr))
+    }
+
+    fn fetch_with(&self, address: u32, read: fn(&dyn Memory, u8) -> Option<u8>) -> Result<Instruction, Fault> {
+        let mut word = 0;
+        for offset in 0..4 {
+            let byte = address.checked_add(offset)
+                .and_then(|address| u8::try_from(address).ok())
+                .and_then(|addr| read(&*self.memory, addr))
+                .ok_or(Fault::Unmapped(address))?;
+            word |= (byte as u32) << (8 * offset);
+        }
+        Ok(Instruction::decode(word))
+    }
+
+    /// Checks a jump target is word aligned and has an instruction to fetch
+    fn jump_target(&self, target: i64) -> Result<u32, Fault> {
+        let target = u32::try_from(target)
+            .map_err(|_| Fault::Unmapped(target as u32))?;
+        if target % 4 != 0 {
+            return Err(Fault::Misaligned { address: target, width: 4 });
+        }
+        self.fetch(target)?;
+        Ok(target)
+    }
+
+    /// Fails, leaving memory alone, if the instruction doesn't encode or
+    /// doesn't fit in memory at `location`
+    pub fn load_instruction(&mut self, location: u8, instruction: &Instruction) -> Result<(), &'static str> {
+        if self.trace {
+            println!("Loading into {}: {}", location, instruction);
+        }
+        let word = instruction.try_encode()?;
+        self.load_bytes(location as u32, &word.to_le_bytes())
+            .map_err(|_| "Instruction failed to be loaded into memory")
+    }
+
+    /// Writes `values` to memory from the host, so unlike a store it costs
+    /// no cycles, even when it misses a cache
+    pub fn load_bytes(&mut self, address: u32, values: &[u8]) -> Result<(), Fault> {
+        self.write_memory_with(address, values, |memory, addr, value| memory.poke(addr, value))?;
+        self.memory.take_penalty();
+        Ok(())
     }
 
     /// Simulates a rising edge on the clock 
-    pub fn clock(mut self) -> UnknownCpu {
-        let instruction = self.current_instruction();
+    pub fn clock(self) -> UnknownCpu {
+        self.clocked(|mut cpu| match cpu.decode(cpu.program_counter) {
+            Ok((instruction, op)) => cpu.execute(instruction, Class::of(&op), |cpu| cpu.dispatch(instruction, op)),
+            Err(fault) => {
+                if cpu.trace {
+                    println!("{fault}");
+                }
+                cpu.fault = Some(fault);
+                UnknownCpu::Inter(cpu)
+            }
+        })
+    }
+
+    /// Wraps one clock's work in what every clock does: keeping history,
+    /// clearing the last fault and charging memory penalties and faults
+    fn clocked(mut self, step: impl FnOnce(Cpu) -> UnknownCpu) -> UnknownCpu {
+        if let Some(history) = &mut self.history {
+            history.begin(self.program_counter, self.flags, self.cycles);
+        }
+        self.fault = None;
+        let mut result = step(self);
+        let cpu = result.cpu_mut();
+        cpu.cycles += cpu.memory.take_penalty();
+        if cpu.fault.is_some() {
+            cpu.cycles += cpu.timing.fault;
+        }
+        if let Some(history) = &mut cpu.history {
+            history.end();
+        }
+        result
+    }
+
+    /// Clocks until the cpu is interrupted or `limit` instructions have
+    /// run, running translated blocks when translation is on.
+    ///
+    /// Registers, flags, memory, faults, history and cycles end up just as
+    /// clocking would leave them, including the fetches an instruction
+    /// cache sees.
+    pub fn run_translated(self, limit: usize) -> UnknownCpu {
+        let mut cpu = self;
+        let mut count = 0;
+        while count < limit {
+            let (result, ran) = cpu.run_block(limit - count);
+            count += ran;
+            cpu = match result {
+                UnknownCpu::Ok(next) => next,
+                interrupted => return interrupted,
+            };
+        }
+        UnknownCpu::Ok(cpu)
+    }
+
+    /// Runs up to `limit` instructions of the block at the program counter,
+    /// translating it first if it's new, or clocks once if there's no
+    /// block there. Gives the number of instructions run.
+    fn run_block(mut self, limit: usize) -> (UnknownCpu, usize) {
+        let block = match &mut self.translated {
+            Some(translated) => match translated.get(self.program_counter) {
+                Some(block) => Some(block),
+                None => Block::translate(&self, self.program_counter)
+                    .map(|block| self.translated.as_mut().unwrap().insert(block)),
+            },
+            None => None,
+        };
+        let Some(block) = block else {
+            return (self.clock(), 1);
+        };
+        if let Some(translated) = &mut self.translated {
+            translated.stale = false;
+        }
+        let mut cpu = self;
+        let mut count = 0;
+        for micro_op in block.ops.iter().take(limit) {
+            count += 1;
+            let address = cpu.program_counter;
+            let result = cpu.clocked(|mut cpu| {
+                cpu.refetch(address);
+                cpu.execute(micro_op.instruction, micro_op.class, &*micro_op.run)
+            });
+            cpu = match result {
+                UnknownCpu::Ok(next) => next,
+                interrupted => return (interrupted, count),
+            };
+            let stale = cpu.translated.as_ref().is_some_and(|translated| translated.stale);
+            if stale || cpu.program_counter != address.wrapping_add(4) {
+                break;
+            }
+        }
+        (UnknownCpu::Ok(cpu), count)
+    }
+
+    /// Fetches the instruction at `address` as clocking would, so an
+    /// instruction cache sees it, without decoding it again
+    fn refetch(&mut self, address: u32) {
+        if self.decoded.as_mut().is_some_and(|decoded| decoded.get(address).is_some()) {
+            return;
+        }
+        for address in address..address.saturating_add(4) {
+            if let Ok(address) = u8::try_from(address) {
+                self.memory.fetch(address);
+            }
+        }
+    }
+
+    /// Fetches and decodes the instruction at `address`, from the decode
+    /// cache if it's on
+    fn decode(&mut self, address: u32) -> Result<(Instruction, Op), Fault> {
+        if let Some(decoded) = self.decoded.as_mut().and_then(|decoded| decoded.get(address)) {
+            return Ok(decoded);
+        }
+        let instruction = self.fetch_instruction(address)?;
+        let op = self.profile.op(&instruction);
+        if let Some(decoded) = &mut self.decoded {
+            decoded.insert(address, instruction, op);
+        }
+        Ok((instruction, op))
+    }
+
+    /// Runs an instruction decoded at the program counter with `run`,
+    /// charging its cycles whether or not its flags let it run
+    fn execute(mut self, instruction: Instruction, class: Class, run: impl FnOnce(Cpu) -> UnknownCpu) -> UnknownCpu {
+        let ran = Flags::instruction_can_run(&self.flags, &instruction.flags);
+        self.cycles += self.timing.class_cycles(class, ran);
         // Check flags
         //println!("Cpu flags {}, instruction flags {}", self.flags, instruction.flags);
-        if !Flags::instruction_can_run(&self.flags, &instruction.flags) {
-            println!("Skipping instruction");
-            self.program_counter += 4;
+        if !ran {
+            if self.trace {
+                println!("Skipping instruction");
+            }
+            self.program_counter = self.program_counter.wrapping_add(4);
             return UnknownCpu::Ok(self);
-        } else {
+        } else if self.trace {
             println!("Not skipping instruction");
         }
 
-        println!("Running instruction at {}: {instruction}", self.program_counter);
-        match instruction.opcode {
-            0 => InstSet::logical_left_shift_rd(self),
-            1 => InstSet::logical_left_shift_ri(self),
-            2 => InstSet::logical_right_shift_rd(self),
-            3 => InstSet::logical_right_shift_ri(self),
-            4 => InstSet::logical_and_rd(self),
-            5 => InstSet::logical_and_ri(self),
-            6 => InstSet::logical_or_rd(self),
-            7 => InstSet::logical_or_ri(self),
-            8 => InstSet::logical_xor_rd(self),
-            9 => InstSet::logical_xor_ri(self),
-            10 => InstSet::logical_not_rd(self),
-            11 => InstSet::logical_add_rd(self),
-            12 => InstSet::logical_add_ri(self),
-            13 => InstSet::sub_rd(self),
-            14 => InstSet::sub_ri(self),
-            15 => InstSet::multiply_rd(self),
-            16 => InstSet::multiply_ri(self),
-            17 => InstSet::load_8_bo(self),
-            18 => InstSet::load_8_bi(self),
-            19 => InstSet::load_16_bo(self),
-            20 => InstSet::load_16_bi(self),
-            21 => InstSet::load_32_bo(self),
-            22 => InstSet::load_32_bi(self),
-            23 => InstSet::store_8_bo(self),
-            24 => InstSet::store_8_bi(self),
-            25 => InstSet::store_16_bo(self),
-            26 => InstSet::store_16_bi(self),
-            27 => InstSet::store_32_bo(self),
-            28 => InstSet::store_32_bi(self),
-            29 => InstSet::jump_offset(self),
-            30 => InstSet::jump_to_rd(self),
-            31 => InstSet::jump_to_i(self),
-            32 => InstSet::trigger_interupt(self),
-            opcode => {
-                println!("Instruction {opcode} not implemented yet\nInstruction {}\n cpu: {}",instruction, self.show());
-                UnknownCpu::Inter(self)
-            }
+        if self.trace {
+            println!("Running instruction at {}: {}", self.program_counter, self.profile.disassemble(&instruction, &self.extensions));
+        }
+        run(self)
+    }
+
+    /// Carries out an instruction whose flags let it run
+    fn dispatch(self, instruction: Instruction, op: Op) -> UnknownCpu {
+        match op {
+            Op::LslRd { d, x, y, .. } => InstSet::logical_left_shift_rd(self, d, x, y),
+            Op::LslRi { d, x, i } => InstSet::logical_left_shift_ri(self, d, x, i),
+            Op::LsrRd { d, x, y, .. } => InstSet::logical_right_shift_rd(self, d, x, y),
+            Op::LsrRi { d, x, i } => InstSet::logical_right_shift_ri(self, d, x, i),
+            Op::AndRd { d, x, y, .. } => InstSet::logical_and_rd(self, d, x, y),
+            Op::AndRi { d, x, i } => InstSet::logical_and_ri(self, d, x, i),
+            Op::OrRd { d, x, y, .. } => InstSet::logical_or_rd(self, d, x, y),
+            Op::OrRi { d, x, i } => InstSet::logical_or_ri(self, d, x, i),
+            Op::XorRd { d, x, y, .. } => InstSet::logical_xor_rd(self, d, x, y),
+            Op::XorRi { d, x, i } => InstSet::logical_xor_ri(self, d, x, i),
+            Op::NotRd { d, x, y, .. } => InstSet::logical_not_rd(self, d, x, y),
+            Op::AddRd { d, x, y, .. } => InstSet::logical_add_rd(self, d, x, y),
+            Op::AddRi { d, x, i } => InstSet::logical_add_ri(self, d, x, i),
+            Op::SubRd { d, x, y, .. } => InstSet::sub_rd(self, d, x, y),
+            Op::SubRi { d, x, i } => InstSet::sub_ri(self, d, x, i),
+            Op::MulRd { d, x, y, .. } => InstSet::multiply_rd(self, d, x, y),
+            Op::MulRi { d, x, i } => InstSet::multiply_ri(self, d, x, i),
+            Op::Load8Bo { t, base, off } => InstSet::load_8_bo(self, t, base, off),
+            Op::Load8Bi { t, base, index, .. } => InstSet::load_8_bi(self, t, base, index),
+            Op::Load16Bo { t, base, off } => InstSet::load_16_bo(self, t, base, off),
+            Op::Load16Bi { t, base, index, .. } => InstSet::load_16_bi(self, t, base, index),
+            Op::Load32Bo { t, base, off } => InstSet::load_32_bo(self, t, base, off),
+            Op::Load32Bi { t, base, index, .. } => InstSet::load_32_bi(self, t, base, index),
+            Op::Store8Bo { t, base, off } => InstSet::store_8_bo(self, t, base, off),
+            Op::Store8Bi { t, base, index, .. } => InstSet::store_8_bi(self, t, base, index),
+            Op::Store16Bo { t, base, off } => InstSet::store_16_bo(self, t, base, off),
+            Op::Store16Bi { t, base, index, .. } => InstSet::store_16_bi(self, t, base, index),
+            Op::Store32Bo { t, base, off } => InstSet::store_32_bo(self, t, base, off),
+            Op::Store32Bi { t, base, index, .. } => InstSet::store_32_bi(self, t, base, index),
+            Op::JumpOffset { offset } => InstSet::jump_offset(self, offset),
+            Op::JumpRd { d, .. } => InstSet::jump_to_rd(self, d),
+            Op::JumpI { target } => InstSet::jump_to_i(self, target),
+            Op::Interrupt { .. } => InstSet::trigger_interupt(self),
+            Op::Reserved { opcode, .. } => match self.extensions.get(opcode) {
+                Some(extension) => (extension.handler)(self, &instruction),
+                None => InstSet::fault(self, Fault::UnknownOpcode(opcode)),
+            },
 
         }
         //return UnknownCpu::Ok(self)
@@ -207,314 +754,260 @@ impl Cpu {
 
 }
 
+/// Shifts of 8 or more clear the value, negative shifts leave it alone
+fn shift(value: u8, by: i16, op: fn(u8, u32) -> Option<u8>) -> u8 {
+    match u32::try_from(by) {
+        Ok(by) => op(value, by).unwrap_or(0),
+        Err(_) => value,
+    }
+}
+
 struct InstSet {}
 impl InstSet {
-    fn apply_rd_function<F>(mut cpu:Cpu, op:F) -> UnknownCpu 
+    fn apply_rd_function<F>(mut cpu:Cpu, d: u8, x: u8, y: u8, op:F) -> UnknownCpu 
         where F: Fn(u8, u8) -> (u8, bool) {
-            let instruction = cpu.current_instruction();
-            let x = cpu.read(instruction.r_x());
-            let y = cpu.read(instruction.r_y());
-            println!("{x}, {y}");
+            let x = cpu.read(x);
+            let y = cpu.read(y);
+            if cpu.trace {
+                println!("{x}, {y}");
+            }
             let (result, carry) = op(x,y);
-            cpu.flags.carry = carry;
-            cpu.flags.greater = result > 0;
-            // cpu.flags.less = result < 0;
-            cpu.flags.zero = result == 0;
-            println!("{result}, {result}");
-            cpu.write(
-                instruction.r_dest(),
-                result
-                );
+            cpu.profile.flags.update(&mut cpu.flags, result, carry);
+            if cpu.trace {
+                println!("{result}, {result}");
+            }
+            cpu.write(d, result);
             cpu.program_counter += 4;
             UnknownCpu::Ok(cpu)
         }
 
-    fn apply_ri_function<F>(mut cpu:Cpu, op:F) -> UnknownCpu 
+    fn apply_ri_function<F>(mut cpu:Cpu, d: u8, x: u8, i: Imm12, op:F) -> UnknownCpu 
         where F: Fn(u8, i16) -> (u8, bool) {
-            let instruction = cpu.current_instruction();
-            let x = cpu.read(instruction.r_x());
-            let y = instruction.i_y();
-            println!("RI: {x}, {y}");
-            println!("{instruction}");
-            let (result, carry) = op(x,y);
-            println!("{result}, {carry}");
-            cpu.flags.carry = carry;
-            cpu.flags.greater = result > 0;
-            //TODO Review the meaning of less than zero, Does the ALU assume signned ints?
-            // cpu.flags.less = result < 0;
-            cpu.flags.zero = result == 0;
-            if result > u8::MAX.into() { 
-                panic!("What should I do with a result value too large for target?");
+            let x = cpu.read(x);
+            let y = i.value() as i16;
+            if cpu.trace {
+                println!("RI: {x}, {y}");
             }
-            if result < u8::MIN.into() { 
-                panic!("What should I do with a result value too small for target?");
+            let (result, carry) = op(x,y);
+            if cpu.trace {
+                println!("{result}, {carry}");
             }
-            cpu.write(
-                instruction.r_dest(),
-                result
-                );
+            cpu.profile.flags.update(&mut cpu.flags, result, carry);
+            cpu.write(d, result);
             cpu.program_counter += 4;
             UnknownCpu::Ok(cpu)
         }
 
     /// Operations
-    fn logical_right_shift_rd(cpu:Cpu) -> UnknownCpu {
-        InstSet::apply_rd_function(cpu, |value, shift|((value >> shift) & 0xFF, false))
+    fn logical_right_shift_rd(cpu:Cpu, d: u8, x: u8, y: u8) -> UnknownCpu {
+        InstSet::apply_rd_function(cpu, d, x, y, |value, by|(shift(value, by as i16, u8::checked_shr), false))
     }
 
-    fn logical_right_shift_ri(cpu:Cpu) -> UnknownCpu {
-         InstSet::apply_ri_function(cpu, |value, shift|((value >> shift) & 0xFF, false))
+    fn logical_right_shift_ri(cpu:Cpu, d: u8, x: u8, i: Imm12) -> UnknownCpu {
+         InstSet::apply_ri_function(cpu, d, x, i, |value, by|(shift(value, by, u8::checked_shr), false))
     }
 
-    fn logical_left_shift_rd(cpu:Cpu) -> UnknownCpu {
-        InstSet::apply_rd_function(cpu, |value, shift|((value << shift) & 0xFF, false))
+    fn logical_left_shift_rd(cpu:Cpu, d: u8, x: u8, y: u8) -> UnknownCpu {
+        InstSet::apply_rd_function(cpu, d, x, y, |value, by|(shift(value, by as i16, u8::checked_shl), false))
     }
 
-    fn logical_left_shift_ri(cpu:Cpu) -> UnknownCpu {
-         InstSet::apply_ri_function(cpu, |value, shift|((value << shift) & 0xFF, false))
+    fn logical_left_shift_ri(cpu:Cpu, d: u8, x: u8, i: Imm12) -> UnknownCpu {
+         InstSet::apply_ri_function(cpu, d, x, i, |value, by|(shift(value, by, u8::checked_shl), false))
     }
 
-    fn logical_and_ri(cpu:Cpu) -> UnknownCpu {
-         InstSet::apply_ri_function(cpu, |x, y|(x & y as u8, false))
+    fn logical_and_ri(cpu:Cpu, d: u8, x: u8, i: Imm12) -> UnknownCpu {
+         InstSet::apply_ri_function(cpu, d, x, i, |x, y|(x & y as u8, false))
     }
 
-    fn logical_and_rd(cpu:Cpu) -> UnknownCpu {
-        InstSet::apply_rd_function(cpu, |x, y|(x & y, false))
+    fn logical_and_rd(cpu:Cpu, d: u8, x: u8, y: u8) -> UnknownCpu {
+        InstSet::apply_rd_function(cpu, d, x, y, |x, y|(x & y, false))
     }
 
-    fn logical_or_ri(cpu:Cpu) -> UnknownCpu {
-         InstSet::apply_ri_function(cpu, |x, y| (x | y as u8, false))
+    fn logical_or_ri(cpu:Cpu, d: u8, x: u8, i: Imm12) -> UnknownCpu {
+         InstSet::apply_ri_function(cpu, d, x, i, |x, y| (x | y as u8, false))
     }
 
-    fn logical_or_rd(cpu:Cpu) -> UnknownCpu {
-        InstSet::apply_rd_function(cpu, |x, y| (x | y, false))
+    fn logical_or_rd(cpu:Cpu, d: u8, x: u8, y: u8) -> UnknownCpu {
+        InstSet::apply_rd_function(cpu, d, x, y, |x, y| (x | y, false))
     }
 
-    fn logical_xor_ri(cpu:Cpu) -> UnknownCpu {
-         InstSet::apply_ri_function(cpu, |x, y|(x ^ y as u8, false))
+    fn logical_xor_ri(cpu:Cpu, d: u8, x: u8, i: Imm12) -> UnknownCpu {
+         InstSet::apply_ri_function(cpu, d, x, i, |x, y|(x ^ y as u8, false))
     }
 
-    fn logical_xor_rd(cpu:Cpu) -> UnknownCpu {
-        InstSet::apply_rd_function(cpu, |x, y|(x ^ y, false))
+    fn logical_xor_rd(cpu:Cpu, d: u8, x: u8, y: u8) -> UnknownCpu {
+        InstSet::apply_rd_function(cpu, d, x, y, |x, y|(x ^ y, false))
     }
 
-    fn logical_not_rd(cpu:Cpu) -> UnknownCpu {
-        InstSet::apply_rd_function(cpu, |x, _|(!x, false))
+    fn logical_not_rd(cpu:Cpu, d: u8, x: u8, y: u8) -> UnknownCpu {
+        InstSet::apply_rd_function(cpu, d, x, y, |x, _|(!x, false))
     }
         
-    fn logical_add_ri(cpu:Cpu) -> UnknownCpu {
-         InstSet::apply_ri_function(cpu, |x, y|x.overflowing_add(y as u8))
+    fn logical_add_ri(cpu:Cpu, d: u8, x: u8, i: Imm12) -> UnknownCpu {
+         InstSet::apply_ri_function(cpu, d, x, i, |x, y|x.overflowing_add(y as u8))
     }
 
-    fn logical_add_rd(cpu:Cpu) -> UnknownCpu {
-        InstSet::apply_rd_function(cpu, |x, y|x.overflowing_add(y))
+    fn logical_add_rd(cpu:Cpu, d: u8, x: u8, y: u8) -> UnknownCpu {
+        InstSet::apply_rd_function(cpu, d, x, y, |x, y|x.overflowing_add(y))
     }
 
-    fn sub_rd(cpu:Cpu) -> UnknownCpu {
-        InstSet::apply_rd_function(cpu, |x, y|x.overflowing_sub(y))
+    fn sub_rd(cpu:Cpu, d: u8, x: u8, y: u8) -> UnknownCpu {
+        InstSet::apply_rd_function(cpu, d, x, y, |x, y|x.overflowing_sub(y))
     }
 
-    fn sub_ri(cpu:Cpu) -> UnknownCpu {
-        InstSet::apply_ri_function(cpu, 
-                                   |x, y| match y {
-                                       y if y < 0 => x.overflowing_sub(y as u8),
-                                       _ => x.overflowing_sub(y as u8)
-                                   })
+    fn sub_ri(cpu:Cpu, d: u8, x: u8, i: Imm12) -> UnknownCpu {
+        InstSet::apply_ri_function(cpu, d, x, i, |x, y| x.overflowing_sub(y as u8))
     }
 
-    fn multiply_rd(cpu:Cpu) -> UnknownCpu {
-        InstSet::apply_rd_function(cpu, |x, y|x.overflowing_mul(y))
+    fn multiply_rd(cpu:Cpu, d: u8, x: u8, y: u8) -> UnknownCpu {
+        InstSet::apply_rd_function(cpu, d, x, y, |x, y|x.overflowing_mul(y))
     }
 
-    fn multiply_ri(cpu:Cpu) -> UnknownCpu {
-        if cpu.current_instruction().i_y() < 0 {
-            panic!("Don't know how to handle negative multiply right now")
-        }
-        InstSet::apply_ri_function(cpu, |x, y| x.overflowing_mul(y as u8))
+    /// Negative immediates multiply as their two's complement byte, like add
+    fn multiply_ri(cpu:Cpu, d: u8, x: u8, i: Imm12) -> UnknownCpu {
+        InstSet::apply_ri_function(cpu, d, x, i, |x, y| x.overflowing_mul(y as u8))
     }
     ///Memory
-    fn load_8_bi(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        let index = cpu.read(instruction.r_index());
-        let memory_address = base + index;
-        match cpu.copy_from_memory(memory_address, instruction.r_dest()) {
-            Ok(()) => UnknownCpu::Ok(cpu),
-            Err(msg) => {
-                println!("{msg}"); 
-                cpu.program_counter += 4;
-                return UnknownCpu::Inter(cpu);
-            }
-        }
+    fn load_8_bi(cpu: Cpu, t: u8, base: u8, index: u8) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + cpu.read(index) as u32;
+        InstSet::load(cpu, t, address, 1)
     }
 
-    fn load_8_bo(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        let memory_address = base + instruction.i_offset() as u8;
-        match cpu.copy_from_memory(memory_address, instruction.r_dest()) {
-            Ok(()) => UnknownCpu::Ok(cpu),
-            Err(msg) => {
-                println!("{msg}"); 
-                cpu.program_counter += 4;
-                return UnknownCpu::Inter(cpu);
-            }
-        }
+    fn load_8_bo(cpu: Cpu, t: u8, base: u8, off: u16) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + off as u32;
+        InstSet::load(cpu, t, address, 1)
     }
 
-    fn load_16_bi(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        let index = cpu.read(instruction.r_index());
-        // TODO Add check for overflow
-        let memory_address = base + index;
-        //TODO Add a check that this can all be done before hand. ie make atomic
-        for i in 0..2 {
-            match cpu.copy_from_memory(memory_address + i, instruction.r_dest() + i) {
-                Ok(()) => (),
-                Err(_msg) => return UnknownCpu::Inter(cpu),
-            }
-        }
-        cpu.program_counter += 4;
-        return UnknownCpu::Ok(cpu)
-    }
-
-    fn load_16_bo(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        // TODO Add check for overflow
-        let memory_address = base + instruction.i_offset() as u8;
-        //TODO Add a check that this can all be done before hand. ie make atomic
-        for i in 0..2 {
-            match cpu.copy_from_memory(memory_address + i, instruction.r_dest() + i) {
-                Ok(()) => (),
-                Err(_msg) => return UnknownCpu::Inter(cpu),
-            }
-        }
-        cpu.program_counter += 4;
-        return UnknownCpu::Ok(cpu)
-    }
-
-    fn load_32_bi(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        let index = cpu.read(instruction.r_index());
-        let memory_address = base + index;
-        //TODO Add a check that this can all be done before hand. ie make atomic
-        for i in 0..4 {
-            match cpu.copy_from_memory(memory_address + i, instruction.r_dest() + i) {
-                Ok(()) => (),
-                Err(_msg) => return UnknownCpu::Inter(cpu),
-            }
-        }
-        cpu.program_counter += 4;
-        UnknownCpu::Ok(cpu)
+    fn load_16_bi(cpu: Cpu, t: u8, base: u8, index: u8) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + cpu.read(index) as u32;
+        InstSet::load(cpu, t, address, 2)
     }
 
-    fn load_32_bo(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        let memory_address = base + instruction.i_offset() as u8;
-        //TODO Add a check that this can all be done before hand. ie make atomic
-        for i in 0..4 {
-            match cpu.copy_from_memory(memory_address + i, instruction.r_dest() + i) {
-                Ok(()) => (),
-                Err(_msg) => return UnknownCpu::Inter(cpu),
-            }
-        }
-        cpu.program_counter += 4;
-        UnknownCpu::Ok(cpu)
+    fn load_16_bo(cpu: Cpu, t: u8, base: u8, off: u16) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + off as u32;
+        InstSet::load(cpu, t, address, 2)
     }
 
-    fn store_8_bo(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        let memory_address = base + instruction.i_offset() as u8;
-        println!("STORE|FROM:{}, TO:{}", instruction.r_target(), memory_address);
-        match cpu.copy_to_memory(instruction.r_target(), memory_address) {
-            Ok(()) => (),
-            Err(_msg) => return UnknownCpu::Inter(cpu),
-        }
-        cpu.program_counter += 4;
-        UnknownCpu::Ok(cpu)
+    fn load_32_bi(cpu: Cpu, t: u8, base: u8, index: u8) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + cpu.read(index) as u32;
+        InstSet::load(cpu, t, address, 4)
     }
 
-    fn store_8_bi(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        let offset = cpu.read(instruction.r_index());
-        let memory_address = base + offset;
-        println!("STORE|FROM:{}, TO:{}", instruction.r_target(), memory_address);
-        match cpu.copy_to_memory(instruction.r_target(), memory_address) {
-            Ok(()) => (),
-            Err(_msg) => return UnknownCpu::Inter(cpu),
-        }
-        cpu.program_counter += 4;
-        UnknownCpu::Ok(cpu)
+    fn load_32_bo(cpu: Cpu, t: u8, base: u8, off: u16) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + off as u32;
+        InstSet::load(cpu, t, address, 4)
+    }
+
+    fn store_8_bo(cpu: Cpu, t: u8, base: u8, off: u16) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + off as u32;
+        InstSet::store(cpu, t, address, 1)
+    }
+
+    fn store_8_bi(cpu: Cpu, t: u8, base: u8, index: u8) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + cpu.read(index) as u32;
+        InstSet::store(cpu, t, address, 1)
+    }
+
+    fn store_16_bo(cpu: Cpu, t: u8, base: u8, off: u16) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + off as u32;
+        InstSet::store(cpu, t, address, 2)
+    }
+
+    fn store_16_bi(cpu: Cpu, t: u8, base: u8, index: u8) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + cpu.read(index) as u32;
+        InstSet::store(cpu, t, address, 2)
+    }
+
+    fn store_32_bi(cpu: Cpu, t: u8, base: u8, index: u8) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + cpu.read(index) as u32;
+        InstSet::store(cpu, t, address, 4)
+    }
+
+    fn store_32_bo(cpu: Cpu, t: u8, base: u8, off: u16) -> UnknownCpu {
+        let address = cpu.read(base) as u32 + off as u32;
+        InstSet::store(cpu, t, address, 4)
     }
 
-    fn store_16_bo(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        let memory_address = base + instruction.i_offset() as u8;
-        println!("STORE|FROM:{}, TO:{}", instruction.r_target(), memory_address);
-        //TODO Make atomic
-        for i in 0..2 {
-            match cpu.copy_to_memory(instruction.r_target() + i, memory_address + i) {
-                Ok(()) => (),
-                Err(_msg) => return UnknownCpu::Inter(cpu),
+    /// Splits an access of `width` bytes into the (offset, width) parts to
+    /// carry out, following the cpu's misaligned access policy
+    fn access_parts(cpu: &Cpu, address: u32, width: u8) -> Result<Vec<(u8, u8)>, Fault> {
+        let misaligned = address % width as u32;
+        if misaligned == 0 {
+            return Ok(vec![(0, width)]);
+        }
+        match cpu.misaligned {
+            MisalignedAccess::Allow => Ok(vec![(0, width)]),
+            MisalignedAccess::Trap => Err(Fault::Misaligned { address, width }),
+            MisalignedAccess::Split => {
+                let first = width - misaligned as u8;
+                Ok(vec![(0, first), (first, width - first)])
             }
         }
-        cpu.program_counter += 4;
-        UnknownCpu::Ok(cpu)
     }
 
-    fn store_16_bi(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        let offset = cpu.read(instruction.r_index());
-        let memory_address = base + offset;
-        println!("STORE|FROM:{}, TO:{}", instruction.r_target(), memory_address);
-        for i in 0..2 {
-            match cpu.copy_to_memory(instruction.r_target() + i, memory_address + i) {
-                Ok(()) => (),
-                Err(_msg) => return UnknownCpu::Inter(cpu),
+    /// Loads `width` bytes at `address` into registers t, t+1...
+    /// Every part of the access is checked before any register is written
+    fn load(mut cpu: Cpu, t: u8, address: u32, width: u8) -> UnknownCpu {
+        let parts = match InstSet::access_parts(&cpu, address, width) {
+            Ok(parts) => parts,
+            Err(fault) => return InstSet::fault(cpu, fault),
+        };
+        let values = parts.iter()
+            .map(|&(offset, width)| cpu.read_memory(address + offset as u32, width)
+                .and_then(|values| cpu.check_registers(t + offset, width).map(|_| values)))
+            .collect::<Result<Vec<_>, Fault>>();
+        let values = match values {
+            Ok(values) => values,
+            Err(fault) => return InstSet::fault(cpu, fault),
+        };
+        for (&(offset, _), values) in parts.iter().zip(values) {
+            // Already checked, so this can't fail part way through
+            if let Err(fault) = cpu.write_registers(t + offset, &values) {
+                return InstSet::fault(cpu, fault);
             }
+            cpu.log_memory(address + offset as u32, &values, Access::Read);
         }
         cpu.program_counter += 4;
         UnknownCpu::Ok(cpu)
     }
 
-    fn store_32_bi(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        let offset = cpu.read(instruction.r_index());
-        let memory_address = base + offset;
-        println!("STORE|FROM:{}, TO:{}", instruction.r_target(), memory_address);
-        for i in 0..4 {
-            match cpu.copy_to_memory(instruction.r_target() + i, memory_address + i) {
-                Ok(()) => (),
-                Err(_msg) => return UnknownCpu::Inter(cpu),
+    /// Stores registers t, t+1... into `width` bytes at `address`
+    /// Every part of the access is checked before any memory is written
+    fn store(mut cpu: Cpu, t: u8, address: u32, width: u8) -> UnknownCpu {
+        let parts = match InstSet::access_parts(&cpu, address, width) {
+            Ok(parts) => parts,
+            Err(fault) => return InstSet::fault(cpu, fault),
+        };
+        if cpu.trace {
+            println!("STORE|FROM:{}, TO:{}", t, address);
+        }
+        let values = parts.iter()
+            .map(|&(offset, width)| cpu.read_registers(t + offset, width)
+                .and_then(|values| cpu.check_memory(address + offset as u32, width).map(|_| values)))
+            .collect::<Result<Vec<_>, Fault>>();
+        let values = match values {
+            Ok(values) => values,
+            Err(fault) => return InstSet::fault(cpu, fault),
+        };
+        for (&(offset, _), values) in parts.iter().zip(values) {
+            // Already checked, so this can't fail part way through
+            if let Err(fault) = cpu.write_memory(address + offset as u32, &values) {
+                return InstSet::fault(cpu, fault);
             }
+            cpu.log_memory(address + offset as u32, &values, Access::Write);
         }
         cpu.program_counter += 4;
         UnknownCpu::Ok(cpu)
     }
 
-    fn store_32_bo(mut cpu: Cpu) -> UnknownCpu {
-        let instruction = cpu.current_instruction();
-        let base = cpu.read(instruction.r_base());
-        let memory_address = base + instruction.i_offset() as u8;
-        println!("STORE|FROM:{}, TO:{}", instruction.r_target(), memory_address);
-        //TODO Make atomic
-        for i in 0..4 {
-            match cpu.copy_to_memory(instruction.r_target() + i, memory_address + i) {
-                Ok(()) => (),
-                Err(_msg) => return UnknownCpu::Inter(cpu),
-            }
+    /// Abandons the current instruction
+    fn fault(mut cpu: Cpu, fault: Fault) -> UnknownCpu {
+        if cpu.trace {
+            println!("{fault}");
         }
-        cpu.program_counter += 4;
-        UnknownCpu::Ok(cpu)
+        cpu.fault = Some(fault);
+        cpu.program_counter = cpu.program_counter.wrapping_add(4);
+        UnknownCpu::Inter(cpu)
     }
 
     /// Flow Control
@@ -522,31 +1015,31 @@ impl InstSet {
         UnknownCpu::Inter(cpu)
     }
 
-    fn jump_offset(mut cpu: Cpu) -> UnknownCpu {
-        // TODO what happens when jump is too larg?
-        cpu.program_counter += cpu
-            .current_instruction()
-            .i() as u8;
-        UnknownCpu::Ok(cpu)
+    /// Jumps relative to the address of this instruction
+    fn jump_offset(cpu: Cpu, offset: Imm22) -> UnknownCpu {
+        let target = cpu.program_counter as i64 + offset.value() as i64;
+        InstSet::jump(cpu, target)
     }
 
-    fn jump_to_rd(mut cpu: Cpu) -> UnknownCpu {
-        let jump_to = cpu.read(cpu.current_instruction().r_dest());
-        cpu.program_counter = jump_to;
-        UnknownCpu::Ok(cpu)
+    fn jump_to_rd(cpu: Cpu, d: u8) -> UnknownCpu {
+        let target = cpu.read(d) as i64;
+        InstSet::jump(cpu, target)
     }
 
-    fn jump_to_i(mut cpu: Cpu) -> UnknownCpu {
-        let jump_to = cpu.current_instruction().i().try_into();
-        match jump_to {
-            Err(_) =>{
-                println!("Can't handle program counter of {}", cpu.current_instruction().i());
-                UnknownCpu::Inter(cpu)
-            },
-            Ok(to) => {
-                cpu.program_counter = to;
+    /// Negative targets are sign extended, which puts them at the top of
+    /// the address space
+    fn jump_to_i(cpu: Cpu, target: Imm22) -> UnknownCpu {
+        let target = target.value() as u32 as i64;
+        InstSet::jump(cpu, target)
+    }
+
+    fn jump(mut cpu: Cpu, target: i64) -> UnknownCpu {
+        match cpu.jump_target(target) {
+            Ok(target) => {
+                cpu.program_counter = target;
                 UnknownCpu::Ok(cpu)
             }
+            Err(fault) => InstSet::fault(cpu, fault),
         }
     }
 }
@@ -561,28 +1054,27 @@ mod tests {
     #[test]
     fn test_program_counter_inc() {
         let mut cpu = Cpu::new_blank();
-        cpu.program_counter = 1;
         // Load in jump + 1 commands
         let mut jump_1 = Instruction::from_opcode(29);
 
         jump_1.i_set(4);
-        cpu.load_instruction(1, &jump_1);
-        cpu.load_instruction(5, &jump_1);
-        cpu.load_instruction(9, &jump_1);
+        cpu.load_instruction(0, &jump_1).unwrap();
+        cpu.load_instruction(4, &jump_1).unwrap();
+        cpu.load_instruction(8, &jump_1).unwrap();
         let pc = cpu.program_counter;
         cpu = match cpu.clock() {
             UnknownCpu::Inter(_) => panic!("Software interupt called"),
             UnknownCpu::Ok(cpu) => cpu,
         };
         assert_eq!(pc + 4, cpu.program_counter);
-        println!("SEOND, {:?}", cpu.current_instruction());
+        println!("SEOND, {:?}", cpu.current_instruction().unwrap());
 
         cpu = match cpu.clock() {
             UnknownCpu::Inter(_) => panic!("Software interupt called"),
             UnknownCpu::Ok(cpu) => cpu,
         };
         assert_eq!(pc + 8, cpu.program_counter);
-        println!("Third, {:?}", cpu.current_instruction());
+        println!("Third, {:?}", cpu.current_instruction().unwrap());
 
         cpu = match cpu.clock() {
             UnknownCpu::Inter(_) => panic!("Software interupt called"),
@@ -629,7 +1121,7 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         cpu.program_counter = 1;
         let throw_interupt = Instruction::from_opcode(32);
-        cpu.load_instruction(1, &throw_interupt);
+        cpu.load_instruction(1, &throw_interupt).unwrap();
         match cpu.clock() {
             UnknownCpu::Inter(_) => (),
             UnknownCpu::Ok(cpu) => panic!("Cpu should be in interupted state {}", cpu.show())
@@ -642,15 +1134,115 @@ mod tests {
         // Load in jump + 1 commands
         let mut jump_1 = Instruction::from_opcode(29);
         jump_1.i_set(4);
-        cpu.load_instruction(1, &jump_1);
-        cpu.load_instruction(5, &jump_1);
-        cpu.load_instruction(9, &jump_1);
+        cpu.load_instruction(1, &jump_1).unwrap();
+        cpu.load_instruction(5, &jump_1).unwrap();
+        cpu.load_instruction(9, &jump_1).unwrap();
         cpu.program_counter = 1;
-        assert_eq!(jump_1, cpu.current_instruction());
+        assert_eq!(jump_1, cpu.current_instruction().unwrap());
+
+    }
 
+    #[test]
+    fn test_load_instruction_out_of_memory() {
+        let mut cpu = Cpu::new_blank();
+        assert!(cpu.load_instruction(253, &Instruction::from_opcode(29)).is_err());
+        assert_eq!(Some(0), cpu.memory.read(253));
+        assert!(cpu.load_instruction(0, &Instruction::from_opcode(64)).is_err());
+        cpu.program_counter = 253;
+        assert_eq!(Err(Fault::Unmapped(253)), cpu.current_instruction());
     }
 
-    //TODO Add test for out of bounds jumps
+    #[test]
+    fn test_jump_offset_backwards() {
+        let mut cpu = Cpu::new_blank();
+        cpu.program_counter = 20;
+        let mut jump_back = Instruction::from_opcode(29);
+        jump_back.i_set(-12);
+        cpu.load_instruction(20, &jump_back).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Inter(cpu) => panic!("Unepected interupt {}", cpu),
+            UnknownCpu::Ok(cpu) => cpu,
+        };
+        assert_eq!(8, cpu.program_counter);
+    }
+
+    #[test]
+    fn test_jump_offset_below_zero() {
+        let mut cpu = Cpu::new_blank();
+        cpu.program_counter = 8;
+        let mut jump_back = Instruction::from_opcode(29);
+        jump_back.i_set(-12);
+        cpu.load_instruction(8, &jump_back).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Inter(cpu) => cpu,
+            UnknownCpu::Ok(cpu) => panic!("Jump should have faulted {}", cpu),
+        };
+        assert_eq!(Some(Fault::Unmapped(-4_i32 as u32)), cpu.fault);
+        assert_eq!(12, cpu.program_counter);
+    }
+
+    #[test]
+    fn test_jump_misaligned() {
+        let mut cpu = Cpu::new_blank();
+        let mut jump = Instruction::from_opcode(29);
+        jump.i_set(6);
+        cpu.load_instruction(0, &jump).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Inter(cpu) => cpu,
+            UnknownCpu::Ok(cpu) => panic!("Jump should have faulted {}", cpu),
+        };
+        assert_eq!(Some(Fault::Misaligned { address: 6, width: 4 }), cpu.fault);
+        assert_eq!(4, cpu.program_counter);
+    }
+
+    #[test]
+    fn test_jump_out_of_memory() {
+        // The last whole word of memory
+        let last = (MEMORY_SIZE as u32 - 4) & !3;
+        let mut cpu = Cpu::new_blank();
+        let mut jump = Instruction::from_opcode(31);
+        jump.i_set(last as i32);
+        cpu.load_instruction(0, &jump).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Inter(cpu) => panic!("Unepected interupt {}", cpu),
+            UnknownCpu::Ok(cpu) => cpu,
+        };
+        assert_eq!(last, cpu.program_counter);
+
+        cpu.program_counter = 0;
+        jump.i_set(last as i32 + 4);
+        cpu.load_instruction(0, &jump).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Inter(cpu) => cpu,
+            UnknownCpu::Ok(cpu) => panic!("Jump should have faulted {}", cpu),
+        };
+        assert_eq!(Some(Fault::Unmapped(last + 4)), cpu.fault);
+    }
+
+    #[test]
+    fn test_jump_i_negative() {
+        let mut cpu = Cpu::new_blank();
+        let mut jump = Instruction::from_opcode(31);
+        jump.i_set(-8);
+        cpu.load_instruction(0, &jump).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Inter(cpu) => cpu,
+            UnknownCpu::Ok(cpu) => panic!("Jump should have faulted {}", cpu),
+        };
+        assert_eq!(Some(Fault::Unmapped(0xFFFF_FFF8)), cpu.fault);
+    }
+
+    #[test]
+    fn test_fetch_outside_memory() {
+        let mut cpu = Cpu::new_blank();
+        cpu.program_counter = 0x1_0000;
+        cpu = match cpu.clock() {
+            UnknownCpu::Inter(cpu) => cpu,
+            UnknownCpu::Ok(cpu) => panic!("Fetch should have faulted {}", cpu),
+        };
+        assert_eq!(Some(Fault::Unmapped(0x1_0000)), cpu.fault);
+        assert_eq!(0x1_0000, cpu.program_counter);
+    }
 
     #[test]
     fn test_jump_i_instruction() {
@@ -659,48 +1251,47 @@ mod tests {
         for i in 0..15 {
             let mut intrupt = Instruction::from_opcode(32);
             intrupt.opcode = 21;
-            cpu.load_instruction(i * 4 + 1, &intrupt);
+            cpu.load_instruction(i * 4, &intrupt).unwrap();
         }
-        let mut jump_to_1 = Instruction::from_opcode(31);
-        jump_to_1.i_set(1);
-        let mut jump_to_21 = Instruction::from_opcode(31);
-        jump_to_21.i_set(21);
-        let mut jump_to_9 = Instruction::from_opcode(31);
-        jump_to_9.i_set(9);
+        let mut jump_to_0 = Instruction::from_opcode(31);
+        jump_to_0.i_set(0);
+        let mut jump_to_20 = Instruction::from_opcode(31);
+        jump_to_20.i_set(20);
+        let mut jump_to_8 = Instruction::from_opcode(31);
+        jump_to_8.i_set(8);
 
-        cpu.load_instruction( 1, &jump_to_9);
-        cpu.load_instruction(9, &jump_to_21);
-        cpu.load_instruction(21, &jump_to_1);
+        cpu.load_instruction(0, &jump_to_8).unwrap();
+        cpu.load_instruction(8, &jump_to_20).unwrap();
+        cpu.load_instruction(20, &jump_to_0).unwrap();
 
-        cpu.program_counter = 1;
+        cpu.program_counter = 0;
         cpu = match cpu.clock() {
             UnknownCpu::Inter(cpu) => panic!("Unepected interupt {}", cpu),
             UnknownCpu::Ok(cpu) => cpu,
         };
-        assert_eq!(9, cpu.program_counter);
+        assert_eq!(8, cpu.program_counter);
 
         cpu = match cpu.clock() {
             UnknownCpu::Inter(cpu) => panic!("Unepected interupt {}", cpu),
             UnknownCpu::Ok(cpu) => cpu,
         };
-        assert_eq!(21, cpu.program_counter);
+        assert_eq!(20, cpu.program_counter);
 
         cpu = match cpu.clock() {
             UnknownCpu::Inter(cpu) => panic!("Unepected interupt {}", cpu),
             UnknownCpu::Ok(cpu) => cpu,
         };
-        assert_eq!(1, cpu.program_counter);
+        assert_eq!(0, cpu.program_counter);
     }
 
     #[test]
     fn test_jump_rd_instruction() {
         let mut cpu = Cpu::new_blank();
-        cpu.program_counter = 0;
         // Fill with Intrupts
         for i in 0..15 {
             let mut intrupt = Instruction::from_opcode(32);
             intrupt.i_set(47);
-            cpu.load_instruction(i * 4, &intrupt);
+            cpu.load_instruction(i * 4, &intrupt).unwrap();
         }
 
         let mut jump_to_0 = Instruction::from_opcode(30);
@@ -708,20 +1299,20 @@ mod tests {
         let mut jump_to_2 = Instruction::from_opcode(30);
         println!("Set 1");
         // We will jump to the value in register 10
-        jump_to_0.r_dest_set(1);
+        jump_to_0.r_dest_set(1).unwrap();
         // We set the value of register 10 to 1
         cpu.write(1, 0);
         // So this instruction will just to instruction 1
         println!("Set 21");
-        jump_to_6.r_dest_set(2);
+        jump_to_6.r_dest_set(2).unwrap();
         cpu.write(2, 24);
         println!("Set 9");
-        jump_to_2.r_dest_set(3);
+        jump_to_2.r_dest_set(3).unwrap();
         cpu.write(3, 8);
 
-        cpu.load_instruction(0, &jump_to_2);
-        cpu.load_instruction(8, &jump_to_6);
-        cpu.load_instruction(24, &jump_to_0);
+        cpu.load_instruction(0, &jump_to_2).unwrap();
+        cpu.load_instruction(8, &jump_to_6).unwrap();
+        cpu.load_instruction(24, &jump_to_0).unwrap();
 
         println!("Before\n{cpu}");
 
@@ -775,8 +1366,8 @@ mod tests {
         let mut rng = rand::thread_rng();
         for _ in 0..10 { 
             let mut values = [43;(MEMORY_SIZE - 1) as usize];
-            for i in 0..values.len() {
-                values[i] = i as u8
+            for (i, value) in values.iter_mut().enumerate() {
+                *value = i as u8
             }
             values.try_fill(&mut rng).unwrap();
             for (value, address) in values.iter().zip(0..) {
@@ -787,17 +1378,17 @@ mod tests {
             for address in 10..31 {
                 println!("{}", address);
                 let mut instruction = Instruction::from_opcode(17);
-                instruction.r_target_set(1);
+                instruction.r_target_set(1).unwrap();
                 instruction.r_base_set(5);
-                instruction.i_offset_set(0);
+                instruction.i_offset_set(0).unwrap();
                 cpu.write(5, address);
-                cpu.load_instruction(1, &instruction);
+                cpu.load_instruction(0, &instruction).unwrap();
                 println!("|>|>{}\n", instruction);
                 cpu = match cpu.clock() {
                     UnknownCpu::Ok(cpu) => cpu,
                     UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
                 };
-                cpu.program_counter = 1;
+                cpu.program_counter = 0;
 
                 assert_eq!(values[address as usize], cpu.read(1), "{}", instruction);
             }
@@ -809,9 +1400,9 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         let mut rng = rand::thread_rng();
         for _ in 0..10 { 
-            let mut values = [43; 32 as usize];
-            for i in 0..values.len() {
-                values[i] = i as u8
+            let mut values = [43; 32];
+            for (i, value) in values.iter_mut().enumerate() {
+                *value = i as u8
             }
             values.try_fill(&mut rng).unwrap();
             for (value, address) in values.iter().zip(0..) {
@@ -822,17 +1413,17 @@ mod tests {
             for address in 6..31 {
                 println!("{}", address);
                 let mut instruction = Instruction::from_opcode(17);
-                instruction.r_target_set(1);
+                instruction.r_target_set(1).unwrap();
                 instruction.r_base_set(5);
-                instruction.i_offset_set(address as u32);
+                instruction.i_offset_set(address as u32).unwrap();
                 cpu.write(5, 0);
-                cpu.load_instruction(1, &instruction);
+                cpu.load_instruction(0, &instruction).unwrap();
                 println!("|>|>{}\n", instruction);
                 cpu = match cpu.clock() {
                     UnknownCpu::Ok(cpu) => cpu,
                     UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
                 };
-                cpu.program_counter = 1;
+                cpu.program_counter = 0;
 
                 assert_eq!(values[address as usize], cpu.read(1), "{}", instruction);
             }
@@ -846,8 +1437,8 @@ mod tests {
         let mut rng = rand::thread_rng();
         for _ in 0..10 { 
             let mut values = [43; MEMORY_SIZE as usize];
-            for i in 0..values.len() {
-                values[i] = i as u8
+            for (i, value) in values.iter_mut().enumerate() {
+                *value = i as u8
             }
             values.try_fill(&mut rng).unwrap();
             for (value, address) in values.iter().zip(0..) {
@@ -858,12 +1449,12 @@ mod tests {
             for address in 6..31 {
                 println!("{}", address);
                 let mut instruction = Instruction::from_opcode(19);
-                instruction.r_target_set(1);
+                instruction.r_target_set(1).unwrap();
                 instruction.r_base_set(5);
                 cpu.write(5, address);
-                instruction.i_offset_set(0);
-                cpu.load_instruction(1, &instruction);
-                cpu.program_counter = 1;
+                instruction.i_offset_set(0).unwrap();
+                cpu.load_instruction(0, &instruction).unwrap();
+                cpu.program_counter = 0;
                 println!("|>|>{}\n", instruction);
                 cpu = match cpu.clock() {
                     UnknownCpu::Ok(cpu) => cpu,
@@ -883,8 +1474,8 @@ mod tests {
         let mut rng = rand::thread_rng();
         for _ in 0..10 { 
             let mut values = [43;MEMORY_SIZE as usize];
-            for i in 0..values.len() {
-                values[i] = i as u8
+            for (i, value) in values.iter_mut().enumerate() {
+                *value = i as u8
             }
             values.try_fill(&mut rng).unwrap();
             for (value, address) in values.iter().zip(0..) {
@@ -895,12 +1486,12 @@ mod tests {
             for address in 5..31 {
                 println!("{}", address);
                 let mut instruction = Instruction::from_opcode(21);
-                instruction.r_target_set(1);
+                instruction.r_target_set(1).unwrap();
                 instruction.r_base_set(5);
                 cpu.write(5, address);
-                instruction.i_offset_set(0);
-                cpu.load_instruction(1, &instruction);
-                cpu.program_counter = 1;
+                instruction.i_offset_set(0).unwrap();
+                cpu.load_instruction(0, &instruction).unwrap();
+                cpu.program_counter = 0;
                 println!("|>|>{}\n", instruction);
                 cpu = match cpu.clock() {
                     UnknownCpu::Ok(cpu) => cpu,
@@ -930,15 +1521,15 @@ mod tests {
             for address in 8..31 {
                 println!("{}", address);
                 let mut instruction = Instruction::from_opcode(23);
-                instruction.r_target_set(5);
+                instruction.r_target_set(5).unwrap();
                 instruction.r_base_set(6);
-                instruction.i_offset_set(0);
+                instruction.i_offset_set(0).unwrap();
                 let rand_value = rng.gen();
                 cpu.write(5, rand_value);
                 cpu.write(6, address);
 
-                cpu.load_instruction(1, &instruction);
-                cpu.program_counter = 1;
+                cpu.load_instruction(0, &instruction).unwrap();
+                cpu.program_counter = 0;
                 cpu = match cpu.clock() {
                     UnknownCpu::Ok(cpu) => cpu,
                     UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
@@ -958,15 +1549,15 @@ mod tests {
             for address in 8..31 {
                 println!("{}", address);
                 let mut instruction = Instruction::from_opcode(23);
-                instruction.r_target_set(5);
+                instruction.r_target_set(5).unwrap();
                 instruction.r_base_set(6);
-                instruction.i_offset_set(address as u32);
+                instruction.i_offset_set(address as u32).unwrap();
                 let rand_value = rng.gen();
                 cpu.write(5, rand_value);
                 cpu.write(6, 0);
 
-                cpu.load_instruction(1, &instruction);
-                cpu.program_counter = 1;
+                cpu.load_instruction(0, &instruction).unwrap();
+                cpu.program_counter = 0;
                 cpu = match cpu.clock() {
                     UnknownCpu::Ok(cpu) => cpu,
                     UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
@@ -986,7 +1577,7 @@ mod tests {
             for address in 8..31 {
                 println!("{}", address);
                 let mut instruction = Instruction::from_opcode(24);
-                instruction.r_target_set(5);
+                instruction.r_target_set(5).unwrap();
                 instruction.r_base_set(6);
                 instruction.r_index_set(7);
                 let rand_value = rng.gen();
@@ -994,8 +1585,8 @@ mod tests {
                 cpu.write(6, address);
                 cpu.write(7, 0);
 
-                cpu.load_instruction(1, &instruction);
-                cpu.program_counter = 1;
+                cpu.load_instruction(0, &instruction).unwrap();
+                cpu.program_counter = 0;
                 cpu = match cpu.clock() {
                     UnknownCpu::Ok(cpu) => cpu,
                     UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
@@ -1015,16 +1606,16 @@ mod tests {
             for address in 8..31 {
                 println!("{}", address);
                 let mut instruction = Instruction::from_opcode(25);
-                instruction.r_target_set(5);
+                instruction.r_target_set(5).unwrap();
                 instruction.r_base_set(7);
-                instruction.i_offset_set(address as u32);
+                instruction.i_offset_set(address as u32).unwrap();
                 let rand_value: u16 = rng.gen();
                 cpu.write(5, (rand_value & 0xFF) as u8);
-                cpu.write(6, (rand_value >> 8) as u8 & 0xFF );
+                cpu.write(6, (rand_value >> 8) as u8);
                 cpu.write(7, 0);
 
-                cpu.load_instruction(1, &instruction);
-                cpu.program_counter = 1;
+                cpu.load_instruction(0, &instruction).unwrap();
+                cpu.program_counter = 0;
                 cpu = match cpu.clock() {
                     UnknownCpu::Ok(cpu) => cpu,
                     UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
@@ -1032,7 +1623,7 @@ mod tests {
 
                 let value = (cpu.memory.read(address).unwrap() as u16) |
                     ((cpu.memory.read(address + 1).unwrap()) as u16) << 8;
-                assert_eq!(rand_value, value as u16, "{}:{}|Inst:{}\nexp:{:X}\nval:{:X}", i, address, instruction, rand_value, value);
+                assert_eq!(rand_value, value, "{}:{}|Inst:{}\nexp:{:X}\nval:{:X}", i, address, instruction, rand_value, value);
             }
         }
     }
@@ -1045,9 +1636,9 @@ mod tests {
             for address in 10..31 {
                 println!("{}", address);
                 let mut instruction = Instruction::from_opcode(27);
-                instruction.r_target_set(5);
+                instruction.r_target_set(5).unwrap();
                 instruction.r_base_set(9);
-                instruction.i_offset_set(address as u32);
+                instruction.i_offset_set(address as u32).unwrap();
                 let rand_value: u32 = rng.gen();
                 cpu.write(5, (rand_value       & 0xFF) as u8);
                 cpu.write(6, (rand_value >>  8 & 0xFF) as u8);
@@ -1055,8 +1646,8 @@ mod tests {
                 cpu.write(8, (rand_value >> 24 & 0xFF) as u8);
                 cpu.write(9, 0);
 
-                cpu.load_instruction(1, &instruction);
-                cpu.program_counter = 1;
+                cpu.load_instruction(0, &instruction).unwrap();
+                cpu.program_counter = 0;
                 cpu = match cpu.clock() {
                     UnknownCpu::Ok(cpu) => cpu,
                     UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
@@ -1066,7 +1657,7 @@ mod tests {
                     (cpu.memory.read(address + 1).unwrap() as u32) <<  8 | 
                     (cpu.memory.read(address + 2).unwrap() as u32) << 16 |
                     (cpu.memory.read(address + 3).unwrap() as u32) << 24;
-                assert_eq!(rand_value, value as u32, "{}:{}|Inst:{}\nexp:{:X}\nval:{:X}", i, address, instruction, rand_value, value);
+                assert_eq!(rand_value, value, "{}:{}|Inst:{}\nexp:{:X}\nval:{:X}", i, address, instruction, rand_value, value);
             }
         }
     }
@@ -1079,7 +1670,7 @@ mod tests {
             for address in 8..31 {
                 println!("{}", address);
                 let mut instruction = Instruction::from_opcode(26);
-                instruction.r_target_set(5);
+                instruction.r_target_set(5).unwrap();
                 instruction.r_base_set(7);
                 instruction.r_index_set(8);
                 let rand_value: u16 = rng.gen();
@@ -1088,8 +1679,8 @@ mod tests {
                 cpu.write(7, address);
                 cpu.write(8, 0);
 
-                cpu.load_instruction(1, &instruction);
-                cpu.program_counter = 1;
+                cpu.load_instruction(0, &instruction).unwrap();
+                cpu.program_counter = 0;
                 cpu = match cpu.clock() {
                     UnknownCpu::Ok(cpu) => cpu,
                     UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
@@ -1110,7 +1701,7 @@ mod tests {
             for address in 11..31 {
                 println!("{}", address);
                 let mut instruction = Instruction::from_opcode(28);
-                instruction.r_target_set(5);
+                instruction.r_target_set(5).unwrap();
                 instruction.r_base_set(9);
                 instruction.r_index_set(10);
                 let rand_value: u32 = rng.gen();
@@ -1121,8 +1712,8 @@ mod tests {
                 cpu.write(9, address);
                 cpu.write(10, 0);
 
-                cpu.load_instruction(1, &instruction);
-                cpu.program_counter = 1;
+                cpu.load_instruction(0, &instruction).unwrap();
+                cpu.program_counter = 0;
                 cpu = match cpu.clock() {
                     UnknownCpu::Ok(cpu) => cpu,
                     UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
@@ -1137,14 +1728,132 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_ld8_incs_program_counter() {
+        let mut cpu = Cpu::new_blank();
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::Load8Bo { t: 1, base: 0, off: 40 })).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => cpu,
+            UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
+        };
+        assert_eq!(4, cpu.program_counter);
+    }
+
+    #[test]
+    fn test_ld32_out_of_memory_is_atomic() {
+        let mut cpu = Cpu::new_blank();
+        for register in 1..5 {
+            cpu.write(register, 0xAA);
+        }
+        // Last byte is past the end of memory
+        let off = (MEMORY_SIZE - 3) as u16;
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::Load32Bo { t: 1, base: 0, off })).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => panic!("Load should have faulted {}", cpu),
+            UnknownCpu::Inter(cpu) => cpu,
+        };
+        assert_eq!(Some(Fault::Unmapped(MEMORY_SIZE as u32)), cpu.fault);
+        assert_eq!(4, cpu.program_counter);
+        for register in 1..5 {
+            assert_eq!(0xAA, cpu.read(register));
+        }
+    }
+
+    #[test]
+    fn test_ld32_invalid_register_is_atomic() {
+        let mut cpu = Cpu::new_blank();
+        cpu.memory.write_u32(40, 0x1234_5678).unwrap();
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::Load32Bo { t: 28, base: 0, off: 40 })).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => panic!("Load should have faulted {}", cpu),
+            UnknownCpu::Inter(cpu) => cpu,
+        };
+        assert_eq!(Some(Fault::InvalidRegister(31)), cpu.fault);
+        assert_eq!(0, cpu.read(28));
+        assert_eq!(0, cpu.read(29));
+    }
+
+    #[test]
+    fn test_st32_out_of_memory_is_atomic() {
+        let mut cpu = Cpu::new_blank();
+        for register in 1..5 {
+            cpu.write(register, 0xAA);
+        }
+        let address = MEMORY_SIZE - 2;
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::Store32Bo { t: 1, base: 0, off: address as u16 })).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => panic!("Store should have faulted {}", cpu),
+            UnknownCpu::Inter(cpu) => cpu,
+        };
+        assert_eq!(Some(Fault::Unmapped(MEMORY_SIZE as u32)), cpu.fault);
+        assert_eq!(4, cpu.program_counter);
+        assert_eq!(Some(0), cpu.memory.read(address));
+        assert_eq!(Some(0), cpu.memory.read(address + 1));
+    }
+
+    #[test]
+    fn test_misaligned_trap() {
+        let mut cpu = Cpu::new_blank();
+        cpu.misaligned = MisalignedAccess::Trap;
+        cpu.write(1, 0xAA);
+        cpu.write(2, 0xBB);
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::Store16Bo { t: 1, base: 0, off: 41 })).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => panic!("Store should have trapped {}", cpu),
+            UnknownCpu::Inter(cpu) => cpu,
+        };
+        assert_eq!(Some(Fault::Misaligned { address: 41, width: 2 }), cpu.fault);
+        assert_eq!(Some(0), cpu.memory.read(41));
+        assert_eq!(Some(0), cpu.memory.read(42));
+
+        // Aligned accesses are unaffected
+        cpu.program_counter = 0;
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::Store16Bo { t: 1, base: 0, off: 40 })).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => cpu,
+            UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
+        };
+        assert_eq!(None, cpu.fault);
+        assert_eq!(Some(0xAA), cpu.memory.read(40));
+        assert_eq!(Some(0xBB), cpu.memory.read(41));
+    }
+
+    #[test]
+    fn test_misaligned_split_is_all_or_nothing() {
+        let mut cpu = Cpu::new_blank();
+        cpu.misaligned = MisalignedAccess::Split;
+        cpu.memory.write_u32(42, 0x4433_2211).unwrap();
+        cpu.write(28, 0xAA);
+        cpu.write(29, 0xBB);
+        // Splits into 42..44 -> r28, r29 then 44..46 -> r30, r31
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::Load32Bo { t: 28, base: 0, off: 42 })).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => panic!("Load should have faulted {}", cpu),
+            UnknownCpu::Inter(cpu) => cpu,
+        };
+        assert_eq!(Some(Fault::InvalidRegister(31)), cpu.fault);
+        assert_eq!(0xAA, cpu.read(28));
+        assert_eq!(0xBB, cpu.read(29));
+
+        // Stores r28, r29 to 42..44 then r30, r31 to 44..46
+        cpu.program_counter = 0;
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::Store32Bo { t: 28, base: 0, off: 42 })).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => panic!("Store should have faulted {}", cpu),
+            UnknownCpu::Inter(cpu) => cpu,
+        };
+        assert_eq!(Some(Fault::InvalidRegister(31)), cpu.fault);
+        assert_eq!(Some(0x4433_2211), cpu.memory.read_u32(42));
+    }
+
     #[test]
     fn test_logic_left_shift_rd() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(0);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.r_y_set(7);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0x01);
         cpu.write(7, 1);
         cpu = match cpu.clock() {
@@ -1158,14 +1867,14 @@ mod tests {
     fn test_logic_left_shift_ri() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(1);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         // Value
         instruction.r_x_set(6);
         cpu.write(6, 0x01);
         // Shift
         instruction.i_y_set(2);
         cpu.write(7, 1);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu = match cpu.clock() {
             UnknownCpu::Ok(ok) => ok,
             UnknownCpu::Inter(_) => panic!()
@@ -1178,10 +1887,10 @@ mod tests {
         // If shift value is negative then nothing should happen
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(0);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.i_y_set(0x801);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0x01);
         cpu = match cpu.clock() {
             UnknownCpu::Ok(ok) => ok,
@@ -1194,10 +1903,10 @@ mod tests {
     fn test_logic_right_shift_rd() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(2);  
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.r_y_set(7);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         println!("{instruction}");
         cpu.write(6, 0x02);
         cpu.write(7, 1);
@@ -1212,10 +1921,10 @@ mod tests {
     fn test_logic_right_shift_ri() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(3);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.i_y_set(1);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0x02);
         cpu = match cpu.clock() {
             UnknownCpu::Ok(ok) => ok,
@@ -1228,10 +1937,10 @@ mod tests {
     fn test_logic_left_shift_overflow() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(0);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.r_y_set(7);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0x80);
         cpu.write(7, 1);
         cpu = match cpu.clock() {
@@ -1241,14 +1950,36 @@ mod tests {
         assert_eq!(0x00, cpu.read(5));
     }
 
+    #[test]
+    fn test_logic_shift_out_of_range() {
+        let mut cpu = Cpu::new_blank();
+        cpu.write(6, 0xFF);
+        cpu.write(7, 8);
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::LslRd { d: 1, x: 6, y: 7, pad: 0 })).unwrap();
+        cpu.load_instruction(4, &Instruction::new(Flags::new(), Op::LsrRi { d: 2, x: 6, i: Imm12::from_value(200).unwrap() })).unwrap();
+        cpu.load_instruction(8, &Instruction::new(Flags::new(), Op::LslRi { d: 3, x: 6, i: Imm12::from_value(-1).unwrap() })).unwrap();
+        for _ in 0..3 {
+            cpu = cpu.clock().into_cpu();
+        }
+        assert_eq!((0, 0, 0xFF), (cpu.read(1), cpu.read(2), cpu.read(3)));
+    }
+
+    #[test]
+    fn test_fetch_past_the_end_faults() {
+        let mut cpu = Cpu::new_blank();
+        cpu.program_counter = u32::MAX - 1;
+        cpu = cpu.clock().into_cpu();
+        assert_eq!(Some(Fault::Unmapped(u32::MAX - 1)), cpu.fault);
+    }
+
     #[test]
     fn test_add() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(11);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.r_y_set(7);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0x0F);
         cpu.write(7, 1);
         cpu = match cpu.clock() {
@@ -1262,10 +1993,10 @@ mod tests {
     fn test_add_multiple() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(11);
-        instruction.r_dest_set(1);
+        instruction.r_dest_set(1).unwrap();
         instruction.r_x_set(2);
         instruction.r_y_set(3);
-        cpu.load_instruction(0, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         for i in 0..100 {
             cpu.program_counter = 0;
             cpu.write(2, i);
@@ -1274,7 +2005,7 @@ mod tests {
                 UnknownCpu::Ok(ok) => ok,
                 UnknownCpu::Inter(_) => panic!()
             };
-            assert_eq!((2*i+13) & 0xFF, cpu.read(1));
+            assert_eq!(2*i+13, cpu.read(1));
         }
     }
 
@@ -1282,10 +2013,10 @@ mod tests {
     fn test_rd_function_incs_program_counter() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(11);
-        instruction.r_dest_set(1);
+        instruction.r_dest_set(1).unwrap();
         instruction.r_x_set(2);
         instruction.r_y_set(3);
-        cpu.load_instruction(0, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.program_counter = 0;
         cpu = match cpu.clock() {
             UnknownCpu::Ok(ok) => ok,
@@ -1298,10 +2029,10 @@ mod tests {
     fn test_add_with_overflow() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(11);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.r_y_set(7);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0xFF);
         cpu.write(7, 2);
         //TODO ADD check for overflow flag
@@ -1316,10 +2047,10 @@ mod tests {
     fn test_add_ri() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(12);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.i_y_set(1);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0x0F);
         cpu.write(7, 1);
         cpu = match cpu.clock() {
@@ -1333,10 +2064,10 @@ mod tests {
     fn test_add_ri_negative_i() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(12);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.i_y_set(1 | (1 << 11));
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0xF1);
         cpu = match cpu.clock() {
             UnknownCpu::Ok(ok) => ok,
@@ -1351,16 +2082,16 @@ mod tests {
         let mut instruction = Instruction::from_opcode(12);
         for i in 0..100 {
             cpu.program_counter = 0;
-            instruction.r_dest_set(5);
+            instruction.r_dest_set(5).unwrap();
             instruction.r_x_set(6);
             cpu.write(6, i);
             instruction.i_y_set((i+13).into());
-            cpu.load_instruction(0, &instruction);
+            cpu.load_instruction(0, &instruction).unwrap();
             cpu = match cpu.clock() {
                 UnknownCpu::Ok(ok) => ok,
                 UnknownCpu::Inter(_) => panic!()
             };
-            assert_eq!((2*i+13) & 0xFF, cpu.read(5));
+            assert_eq!(2*i+13, cpu.read(5));
         }
     }
 
@@ -1368,10 +2099,10 @@ mod tests {
     fn test_add_with_overflow_ri() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(12);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.i_y_set(2);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0xFF);
         cpu.write(7, 2);
         //TODO ADD check for overflow flag
@@ -1386,10 +2117,10 @@ mod tests {
     fn test_sub_rd() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(13);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.r_y_set(7);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0xF4);
         cpu.write(7, 4);
         cpu = match cpu.clock() {
@@ -1404,18 +2135,18 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(13);
         for i in 0..100 {
-            instruction.r_dest_set(5);
+            instruction.r_dest_set(5).unwrap();
             instruction.r_x_set(6);
             cpu.write(6, i*2+26);
             instruction.r_y_set(7);
             cpu.write(7, i+13);
-            cpu.load_instruction(1, &instruction);
-            cpu.program_counter = 1;
+            cpu.load_instruction(0, &instruction).unwrap();
+            cpu.program_counter = 0;
             cpu = match cpu.clock() {
                 UnknownCpu::Ok(ok) => ok,
                 UnknownCpu::Inter(_) => panic!()
             };
-            assert_eq!((i+13) & 0xFF, cpu.read(5));
+            assert_eq!(i+13, cpu.read(5));
         }
     }
 
@@ -1423,12 +2154,12 @@ mod tests {
     fn test_sub_with_underflow_rd() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(13);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         cpu.write(6, 0x00);
         instruction.r_y_set(7);
         cpu.write(7, 2);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         //TODO ADD check for overflow flag
         cpu = match cpu.clock() {
             UnknownCpu::Ok(ok) => ok,
@@ -1441,11 +2172,11 @@ mod tests {
     fn test_sub_ri() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(14);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         cpu.write(6, 0xF4);
         instruction.i_y_set(4);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu = match cpu.clock() {
             UnknownCpu::Ok(ok) => ok,
             UnknownCpu::Inter(_) => panic!()
@@ -1458,17 +2189,17 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(14);
         for i in 0..100 {
-            instruction.r_dest_set(5);
+            instruction.r_dest_set(5).unwrap();
             instruction.r_x_set(6);
             cpu.write(6, i*2+26);
             instruction.i_y_set((i+13).into());
-            cpu.load_instruction(1, &instruction);
-            cpu.program_counter = 1;
+            cpu.load_instruction(0, &instruction).unwrap();
+            cpu.program_counter = 0;
             cpu = match cpu.clock() {
                 UnknownCpu::Ok(ok) => ok,
                 UnknownCpu::Inter(_) => panic!()
             };
-            assert_eq!((i+13) & 0xFF, cpu.read(5));
+            assert_eq!(i+13, cpu.read(5));
         }
     }
 
@@ -1476,11 +2207,11 @@ mod tests {
     fn test_sub_with_underflow_ri() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(14);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         cpu.write(6, 0x00);
         instruction.i_y_set(2);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         //TODO ADD check for overflow flag
         cpu = match cpu.clock() {
             UnknownCpu::Ok(ok) => ok,
@@ -1493,10 +2224,10 @@ mod tests {
     fn test_multipy() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(15);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.r_y_set(7);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 6);
         cpu.write(7, 7);
         cpu = match cpu.clock() {
@@ -1512,17 +2243,17 @@ mod tests {
         let mut instruction = Instruction::from_opcode(15);
         for i in 0..100 {
             cpu.program_counter = 0;
-            instruction.r_dest_set(5);
+            instruction.r_dest_set(5).unwrap();
             instruction.r_x_set(6);
             instruction.r_y_set(7);
-            cpu.load_instruction(0, &instruction);
+            cpu.load_instruction(0, &instruction).unwrap();
             cpu.write(6, i);
             cpu.write(7, i+13);
             cpu = match cpu.clock() {
                 UnknownCpu::Ok(ok) => ok,
                 UnknownCpu::Inter(_) => panic!()
             };
-            assert_eq!(i.wrapping_mul(i+13) & 0xFF, cpu.read(5));
+            assert_eq!(i.wrapping_mul(i+13), cpu.read(5));
         }
     }
 
@@ -1530,10 +2261,10 @@ mod tests {
     fn test_multiply_with_overflow() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(15);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.r_y_set(7);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0xF0);
         cpu.write(7, 0x0F);
         //TODO ADD check for overflow flag
@@ -1548,10 +2279,10 @@ mod tests {
     fn test_multply_ri() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(16);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.i_y_set(6);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0x7);
         cpu = match cpu.clock() {
             UnknownCpu::Ok(ok) => ok,
@@ -1560,18 +2291,25 @@ mod tests {
         assert_eq!(6*7, cpu.read(5));
     }
 
-    //TODO What happens when we multply with a negative number?
+    #[test]
+    fn test_multiply_negative_ri() {
+        let mut cpu = Cpu::new_blank();
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::MulRi { d: 5, x: 6, i: Imm12::from_value(-3).unwrap() })).unwrap();
+        cpu.write(6, 5);
+        cpu = cpu.clock().into_cpu();
+        assert_eq!(5u8.wrapping_mul(-3i8 as u8), cpu.read(5));
+    }
 
     #[test]
     fn test_mutiply_multiple_ri() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(16);
         for i in 0..100 {
-            instruction.r_dest_set(5);
+            instruction.r_dest_set(5).unwrap();
             instruction.r_x_set(6);
             cpu.write(6, i);
             instruction.i_y_set((i+13).into());
-            cpu.load_instruction(0, &instruction);
+            cpu.load_instruction(0, &instruction).unwrap();
             cpu.program_counter = 0;
 
             cpu = match cpu.clock() {
@@ -1579,7 +2317,7 @@ mod tests {
                 UnknownCpu::Inter(_) => panic!()
             };
             let y = i + 13;
-            assert_eq!(i.wrapping_mul(i+13) & 0xFF, cpu.read(5), "{i} * {y} isn't correct");
+            assert_eq!(i.wrapping_mul(i+13), cpu.read(5), "{i} * {y} isn't correct");
         }
     }
 
@@ -1587,10 +2325,10 @@ mod tests {
     fn test_multiply_with_overflow_ri() {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(16);
-        instruction.r_dest_set(5);
+        instruction.r_dest_set(5).unwrap();
         instruction.r_x_set(6);
         instruction.i_y_set(0xF0);
-        cpu.load_instruction(1, &instruction);
+        cpu.load_instruction(0, &instruction).unwrap();
         cpu.write(6, 0x0F);
         //TODO ADD check for overflow flag
         cpu = match cpu.clock() {
@@ -1605,11 +2343,11 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(12);
         
-       instruction.r_dest_set(1);
+       instruction.r_dest_set(1).unwrap();
        instruction.r_x_set(2);
        cpu.write(2, 0xFF);
        instruction.i_y_set(42);
-       cpu.load_instruction(1, &instruction);
+       cpu.load_instruction(0, &instruction).unwrap();
        cpu = match cpu.clock() {
            UnknownCpu::Ok(ok) => ok,
            UnknownCpu::Inter(_) => panic!()
@@ -1622,11 +2360,11 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(12);
         
-       instruction.r_dest_set(1);
+       instruction.r_dest_set(1).unwrap();
        instruction.r_x_set(2);
        cpu.write(2, 0x4);
        instruction.i_set(2);
-       cpu.load_instruction(1, &instruction);
+       cpu.load_instruction(0, &instruction).unwrap();
        cpu = match cpu.clock() {
            UnknownCpu::Ok(ok) => ok,
            UnknownCpu::Inter(_) => panic!()
@@ -1639,12 +2377,12 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(12);
 
-       instruction.r_dest_set(1);
+       instruction.r_dest_set(1).unwrap();
        instruction.r_x_set(2);
        cpu.write(2, 0x4);
        instruction.r_y_set(3);
        cpu.write(2, 0xFF);
-       cpu.load_instruction(1, &instruction);
+       cpu.load_instruction(0, &instruction).unwrap();
        cpu = match cpu.clock() {
            UnknownCpu::Ok(ok) => ok,
            UnknownCpu::Inter(_) => panic!()
@@ -1657,12 +2395,12 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(11);
         
-       instruction.r_dest_set(1);
+       instruction.r_dest_set(1).unwrap();
        instruction.r_x_set(2);
        cpu.write(2, 0x2);
        instruction.r_y_set(3);
        cpu.write(2, 0x32);
-       cpu.load_instruction(1, &instruction);
+       cpu.load_instruction(0, &instruction).unwrap();
        cpu = match cpu.clock() {
            UnknownCpu::Ok(ok) => ok,
            UnknownCpu::Inter(_) => panic!()
@@ -1675,12 +2413,12 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(11);
         
-       instruction.r_dest_set(1);
+       instruction.r_dest_set(1).unwrap();
        instruction.r_x_set(2);
        cpu.write(2, 0x2);
        instruction.r_y_set(3);
        cpu.write(3, 0x32);
-       cpu.load_instruction(1, &instruction);
+       cpu.load_instruction(0, &instruction).unwrap();
        cpu = match cpu.clock() {
            UnknownCpu::Ok(ok) => ok,
            UnknownCpu::Inter(_) => panic!()
@@ -1692,12 +2430,12 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(11);
         
-       instruction.r_dest_set(1);
+       instruction.r_dest_set(1).unwrap();
        instruction.r_x_set(2);
        cpu.write(2, 0x0);
        instruction.r_y_set(3);
        cpu.write(3, 0x0);
-       cpu.load_instruction(1, &instruction);
+       cpu.load_instruction(0, &instruction).unwrap();
        cpu = match cpu.clock() {
            UnknownCpu::Ok(ok) => ok,
            UnknownCpu::Inter(_) => panic!()
@@ -1710,12 +2448,12 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(13);
         
-       instruction.r_dest_set(1);
+       instruction.r_dest_set(1).unwrap();
        instruction.r_x_set(2);
        cpu.write(2, 0x2);
        instruction.r_y_set(3);
        cpu.write(3, 0x4);
-       cpu.load_instruction(1, &instruction);
+       cpu.load_instruction(0, &instruction).unwrap();
        cpu = match cpu.clock() {
            UnknownCpu::Ok(ok) => ok,
            UnknownCpu::Inter(_) => panic!()
@@ -1728,12 +2466,12 @@ mod tests {
         let mut cpu = Cpu::new_blank();
         let mut instruction = Instruction::from_opcode(11);
         
-       instruction.r_dest_set(1);
+       instruction.r_dest_set(1).unwrap();
        instruction.r_x_set(2);
        cpu.write(2, 0x3);
        instruction.r_y_set(3);
        cpu.write(3, 0x1);
-       cpu.load_instruction(1, &instruction);
+       cpu.load_instruction(0, &instruction).unwrap();
        cpu = match cpu.clock() {
            UnknownCpu::Ok(ok) => ok,
            UnknownCpu::Inter(_) => panic!()
@@ -1744,7 +2482,6 @@ mod tests {
     #[test]
     fn test_flag_skips_instuction() {
         let mut cpu = Cpu::new_blank();
-        cpu.program_counter = 0;
 
         // 10. Add 1 + 1 
         let mut i10 = Instruction::from_opcode(11);
@@ -1752,7 +2489,7 @@ mod tests {
         cpu.write(1, 1);
         i10.r_y_set(2);
         cpu.write(2, 1);
-        i10.r_dest_set(3);
+        i10.r_dest_set(3).unwrap();
         
         // 20. Jump if Zero to 5 
         let mut i20 = Instruction::from_opcode(31);
@@ -1766,13 +2503,13 @@ mod tests {
         let i50 = Instruction::from_opcode(32);
         // Should be at 30
         
-       cpu.load_instruction(0, &i10);
-       cpu.load_instruction(4, &i20);
-       cpu.load_instruction(8, &i30);
-       cpu.load_instruction(20, &i50);
+       cpu.load_instruction(0, &i10).unwrap();
+       cpu.load_instruction(4, &i20).unwrap();
+       cpu.load_instruction(8, &i30).unwrap();
+       cpu.load_instruction(20, &i50).unwrap();
        let mut count = 0;
        cpu = loop {
-           println!("PC:{}|| {}", cpu.program_counter, cpu.current_instruction());
+           println!("PC:{}|| {}", cpu.program_counter, cpu.current_instruction().unwrap());
            count += 1;
            if count > 10 {
                println!("{}", cpu);
@@ -1792,16 +2529,15 @@ mod tests {
     #[test]
     fn test_flag_zero_gets_set() {
         let mut cpu = Cpu::new_blank();
-        cpu.program_counter = 0;
 
         // 10. Add 0 + 0
         let mut i10 = Instruction::from_opcode(11);
         i10.r_x_set(1);
         i10.r_y_set(2);
-        i10.r_dest_set(3);
+        i10.r_dest_set(3).unwrap();
         cpu.write(1, 0);
         cpu.write(2, 0);
-        cpu.load_instruction(0, &i10);
+        cpu.load_instruction(0, &i10).unwrap();
         cpu = match cpu.clock() {
             UnknownCpu::Ok(cpu) => cpu,
             UnknownCpu::Inter(_) => panic!(),
@@ -1812,14 +2548,13 @@ mod tests {
     #[test]
     fn test_flag_zero_does_not_gets_set() {
         let mut cpu = Cpu::new_blank();
-        cpu.program_counter = 0;
 
         // 10. Add 1 + 1
         let mut i10 = Instruction::from_opcode(11);
         i10.r_x_set(1);
         i10.r_y_set(2);
-        i10.r_dest_set(3);
-        cpu.load_instruction(0, &i10);
+        i10.r_dest_set(3).unwrap();
+        cpu.load_instruction(0, &i10).unwrap();
         cpu.write(1, 1);
         cpu.write(2, 1);
 
@@ -1829,4 +2564,39 @@ mod tests {
         };
         assert!(!cpu.flags.zero);
     }
-}   
+
+    #[test]
+    fn test_clock_counts_cycles() {
+        let mut cpu = Cpu::new_blank();
+        cpu.write(1, 1);
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::MulRi { d: 1, x: 1, i: Imm12::from_raw(2) })).unwrap();
+        cpu.load_instruction(4, &Instruction::new(Flags::new(), Op::Load32Bo { t: 2, base: 0, off: 40 })).unwrap();
+        let mut skipped_jump = Flags::new();
+        skipped_jump.zero = true;
+        cpu.load_instruction(8, &Instruction::new(skipped_jump, Op::JumpI { target: Imm22::from_raw(0) })).unwrap();
+        cpu.load_instruction(12, &Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_raw(0) })).unwrap();
+        for _ in 0..4 {
+            cpu = cpu.clock().into_cpu();
+        }
+        let timing = CostTable::default();
+        let expected = timing.multiply + timing.load[2] + timing.branch_not_taken + timing.branch_taken;
+        assert_eq!(expected, cpu.cycles);
+    }
+
+    #[test]
+    fn test_fault_costs_cycles() {
+        let mut cpu = Cpu::new_blank();
+        cpu.timing = CostTable::uniform();
+        cpu.timing.fault = 10;
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::Load8Bo { t: 1, base: 0, off: 300 })).unwrap();
+        let cpu = cpu.clock().into_cpu();
+        assert_eq!(11, cpu.cycles);
+    }
+
+    #[test]
+    fn test_seeded_cpu_repeats() {
+        let cpu = |seed| CpuBuilder::new().seed(seed).build().unwrap().snapshot();
+        assert_eq!(cpu(5), cpu(5));
+        assert_ne!(cpu(5), cpu(6));
+    }
+}
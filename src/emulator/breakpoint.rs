@@ -0,0 +1,304 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use crate::emulator::Cpu;
+
+pub type Id = usize;
+
+/// A flag of the flag register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Carry,
+    Greater,
+    Zero,
+    Less,
+}
+
+/// When a breakpoint stops the cpu
+#[derive(Debug, Clone, Copy)]
+pub enum Condition {
+    Always,
+    /// The register holds the value
+    Register { register: u8, value: u8 },
+    /// The flag is set or clear
+    Flag { flag: Flag, set: bool },
+    Custom(fn(&Cpu) -> bool),
+}
+
+impl Condition {
+    pub fn holds(&self, cpu: &Cpu) -> bool {
+        match *self {
+            Condition::Always => true,
+            Condition::Register { register, value } => cpu.read(register) == value,
+            Condition::Flag { flag, set } => {
+                let flags = &cpu.flags;
+                set == match flag {
+                    Flag::Carry => flags.carry,
+                    Flag::Greater => flags.greater,
+                    Flag::Zero => flags.zero,
+                    Flag::Less => flags.less,
+                }
+            }
+            Condition::Custom(predicate) => predicate(cpu),
+        }
+    }
+}
+
+/// Kind of access a watchpoint fires on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Watch {
+    fn matches(self, access: Access) -> bool {
+        matches!((self, access),
+            (Watch::ReadWrite, _) | (Watch::Read, Access::Read) | (Watch::Write, Access::Write))
+    }
+}
+
+/// Something an instruction read or wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Memory(u8),
+    Register(u8),
+}
+
+#[derive(Debug, Clone)]
+pub enum Target {
+    Memory(RangeInclusive<u8>),
+    Register(u8),
+}
+
+impl Target {
+    fn contains(&self, location: Location) -> bool {
+        match (self, location) {
+            (Target::Memory(range), Location::Memory(address)) => range.contains(&address),
+            (Target::Register(register), Location::Register(other)) => *register == other,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub id: Id,
+    pub address: u32,
+    pub condition: Condition,
+}
+
+#[derive(Debug, Clone)]
+pub struct Watchpoint {
+    pub id: Id,
+    pub target: Target,
+    pub watch: Watch,
+}
+
+/// What stopped a step or run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hit {
+    /// The program counter reached a breakpoint, the instruction there
+    /// hasn't run yet
+    Breakpoint(Id),
+    /// The last instruction accessed a watched location
+    Watchpoint { id: Id, location: Location, access: Access },
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Hit::Breakpoint(id) => write!(fmt, "Breakpoint {id}"),
+            Hit::Watchpoint { id, location, access } => write!(fmt, "Watchpoint {id}: {access:?} of {location:?}"),
+        }
+    }
+}
+
+/// The breakpoints and watchpoints set on a cpu
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+    next_id: Id,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Breakpoints::default()
+    }
+
+    /// Stops when the program counter reaches `address`
+    pub fn add(&mut self, address: u32) -> Id {
+        self.add_if(address, Condition::Always)
+    }
+
+    /// Stops when the program counter reaches `address` and the condition holds
+    pub fn add_if(&mut self, address: u32, condition: Condition) -> Id {
+        let id = self.take_id();
+        self.breakpoints.push(Breakpoint { id, address, condition });
+        id
+    }
+
+    pub fn watch_memory(&mut self, range: RangeInclusive<u8>, watch: Watch) -> Id {
+        let id = self.take_id();
+        self.watchpoints.push(Watchpoint { id, target: Target::Memory(range), watch });
+        id
+    }
+
+    pub fn watch_register(&mut self, register: u8, watch: Watch) -> Id {
+        let id = self.take_id();
+        self.watchpoints.push(Watchpoint { id, target: Target::Register(register), watch });
+        id
+    }
+
+    /// Returns false if nothing has the id
+    pub fn remove(&mut self, id: Id) -> bool {
+        let before = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.watchpoints.retain(|watchpoint| watchpoint.id != id);
+        before != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    pub fn has_watchpoints(&self) -> bool {
+        !self.watchpoints.is_empty()
+    }
+
+    /// Breakpoints at the cpu's program counter whose condition holds
+    pub fn breakpoints_hit(&self, cpu: &Cpu) -> Vec<Hit> {
+        self.breakpoints.iter()
+            .filter(|breakpoint| breakpoint.address == cpu.program_counter && breakpoint.condition.holds(cpu))
+            .map(|breakpoint| Hit::Breakpoint(breakpoint.id))
+            .collect()
+    }
+
+    /// Watchpoints fired by a list of accesses
+    pub fn watchpoints_hit(&self, accesses: &[(Location, Access)]) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        for &(location, access) in accesses {
+            for watchpoint in &self.watchpoints {
+                if watchpoint.target.contains(location) && watchpoint.watch.matches(access) {
+                    hits.push(Hit::Watchpoint { id: watchpoint.id, location, access });
+                }
+            }
+        }
+        hits
+    }
+
+    fn take_id(&mut self) -> Id {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Flags, Instruction, Op, UnknownCpu};
+    use crate::emulator::op::{Imm12, Imm22};
+
+    fn add_one(d: u8) -> Instruction {
+        Instruction::new(Flags::new(), Op::AddRi { d, x: d, i: Imm12::from_value(1).unwrap() })
+    }
+
+    fn counting_cpu() -> Cpu {
+        let mut cpu = Cpu::new_blank();
+        cpu.load_instruction(0, &add_one(1)).unwrap();
+        cpu.load_instruction(4, &add_one(2)).unwrap();
+        cpu.load_instruction(8, &Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_raw(0) })).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn run_stops_at_breakpoint() {
+        let mut cpu = counting_cpu();
+        let id = cpu.breakpoints.add(8);
+        let (cpu, hits) = cpu.run(100);
+        assert_eq!(vec![Hit::Breakpoint(id)], hits);
+        let cpu = cpu.into_cpu();
+        assert_eq!(8, cpu.program_counter);
+        assert_eq!(1, cpu.read(2));
+
+        // Carries on past the breakpoint it stopped at
+        let (cpu, hits) = cpu.run(100);
+        assert_eq!(vec![Hit::Breakpoint(id)], hits);
+        assert_eq!(2, cpu.cpu().read(2));
+    }
+
+    #[test]
+    fn conditional_breakpoint() {
+        let mut cpu = counting_cpu();
+        cpu.breakpoints.add_if(0, Condition::Register { register: 1, value: 3 });
+        let (cpu, hits) = cpu.run(100);
+        assert_eq!(1, hits.len());
+        assert_eq!(3, cpu.cpu().read(1));
+        assert_eq!(0, cpu.cpu().program_counter);
+    }
+
+    #[test]
+    fn run_stops_at_limit() {
+        let mut cpu = counting_cpu();
+        cpu.breakpoints.add(40);
+        let (cpu, hits) = cpu.run(6);
+        assert!(hits.is_empty());
+        assert_eq!(2, cpu.cpu().read(1));
+        assert_eq!(0, cpu.cpu().program_counter);
+    }
+
+    #[test]
+    fn removed_breakpoint_doesnt_stop() {
+        let mut cpu = counting_cpu();
+        let id = cpu.breakpoints.add(8);
+        assert!(cpu.breakpoints.remove(id));
+        assert!(!cpu.breakpoints.remove(id));
+        let (_, hits) = cpu.run(10);
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn register_watchpoints() {
+        let mut cpu = counting_cpu();
+        let write = cpu.breakpoints.watch_register(2, Watch::Write);
+        let (cpu, hits) = cpu.step();
+        assert!(hits.is_empty());
+        let (_, hits) = cpu.into_cpu().step();
+        assert_eq!(vec![Hit::Watchpoint { id: write, location: Location::Register(2), access: Access::Write }], hits);
+
+        let mut cpu = counting_cpu();
+        let read = cpu.breakpoints.watch_register(1, Watch::Read);
+        let (_, hits) = cpu.step();
+        assert_eq!(vec![Hit::Watchpoint { id: read, location: Location::Register(1), access: Access::Read }], hits);
+    }
+
+    #[test]
+    fn memory_watchpoints() {
+        let mut cpu = Cpu::new_blank();
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::Store16Bo { t: 1, base: 0, off: 41 })).unwrap();
+        cpu.load_instruction(4, &Instruction::new(Flags::new(), Op::Load8Bo { t: 1, base: 0, off: 40 })).unwrap();
+        let id = cpu.breakpoints.watch_memory(42..=50, Watch::ReadWrite);
+
+        let (cpu, hits) = cpu.step();
+        assert_eq!(vec![Hit::Watchpoint { id, location: Location::Memory(42), access: Access::Write }], hits);
+        let (cpu, hits) = cpu.into_cpu().step();
+        assert!(hits.is_empty());
+        assert!(matches!(cpu, UnknownCpu::Ok(_)));
+        assert_eq!(8, cpu.cpu().program_counter);
+    }
+
+    #[test]
+    fn skipped_instruction_reads_nothing() {
+        let mut cpu = Cpu::new_blank();
+        let mut flags = Flags::new();
+        flags.zero = true;
+        cpu.load_instruction(0, &Instruction::new(flags, Op::Store8Bo { t: 1, base: 0, off: 40 })).unwrap();
+        cpu.breakpoints.watch_register(1, Watch::ReadWrite);
+        cpu.breakpoints.watch_memory(40..=40, Watch::ReadWrite);
+        let (_, hits) = cpu.step();
+        assert!(hits.is_empty());
+    }
+}
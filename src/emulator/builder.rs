@@ -0,0 +1,192 @@
+use std::ops::RangeInclusive;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::emulator::{Cpu, IsaProfile};
+use crate::emulator::memory::{entropy, Memory, SimpleMemory, MEMORY_SIZE};
+
+/// Read by `CpuBuilder::from_env` and the golden, no panic and conformance
+/// tests for the seed, to replay a failed run
+pub const SEED_VARIABLE: &str = "ETD_SEED";
+
+/// The seed in `ETD_SEED`, if it's set to a number
+pub fn seed_from_env() -> Option<u64> {
+    std::env::var(SEED_VARIABLE).ok().and_then(|seed| seed.trim().parse().ok())
+}
+
+/// What registers or a region of memory hold at power on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Init {
+    Zero,
+    /// Drawn from the builder's seed
+    Random,
+    /// The bytes repeated from the start of the region
+    Pattern(Vec<u8>),
+}
+
+impl Init {
+    fn value(&self, offset: usize, rng: &mut StdRng) -> u8 {
+        match self {
+            Init::Zero => 0,
+            Init::Random => rng.gen(),
+            Init::Pattern(bytes) if bytes.is_empty() => 0,
+            Init::Pattern(bytes) => bytes[offset % bytes.len()],
+        }
+    }
+}
+
+/// The seed a cpu or test was drawn from. It's printed if the thread panics
+/// while it's alive, so a failing run can be made again.
+#[derive(Debug)]
+pub struct Seed(pub u64);
+
+impl Drop for Seed {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            eprintln!("Seed {0}, replay with {SEED_VARIABLE}={0} where it's read or CpuBuilder::new().seed({0})", self.0);
+        }
+    }
+}
+
+/// Builds a cpu whose power-on registers and memory come from a seed, so
+/// the same seed gives the same cpu.
+/// ```
+/// use etd3200::emulator::builder::{CpuBuilder, Init};
+/// let cpu = CpuBuilder::new()
+///     .seed(42)
+///     .registers(Init::Zero)
+///     .region(64..=127, Init::Pattern(vec![0xDE, 0xAD]))
+///     .build()
+///     .unwrap();
+/// assert_eq!(Some(42), cpu.seed());
+/// ```
+#[derive(Debug, Clone)]
+pub struct CpuBuilder {
+    seed: u64,
+    profile: IsaProfile,
+    registers: Init,
+    memory: Init,
+    regions: Vec<(RangeInclusive<u8>, Init)>,
+}
+
+impl Default for CpuBuilder {
+    fn default() -> Self {
+        CpuBuilder::new()
+    }
+}
+
+impl CpuBuilder {
+    /// Random registers and memory, seeded from the platform
+    pub fn new() -> Self {
+        CpuBuilder {
+            seed: entropy().gen(),
+            profile: IsaProfile::v0_1_0(),
+            registers: Init::Random,
+            memory: Init::Random,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Like `new`, but seeded from `ETD_SEED` if it's set
+    pub fn from_env() -> Self {
+        let builder = CpuBuilder::new();
+        match seed_from_env() {
+            Some(seed) => builder.seed(seed),
+            None => builder,
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn profile(mut self, profile: IsaProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// The general purpose registers, register 0 is always zero
+    pub fn registers(mut self, init: Init) -> Self {
+        self.registers = init;
+        self
+    }
+
+    /// Memory outside every region
+    pub fn memory(mut self, init: Init) -> Self {
+        self.memory = init;
+        self
+    }
+
+    /// Addresses in `range`, over memory and any earlier region
+    pub fn region(mut self, range: RangeInclusive<u8>, init: Init) -> Self {
+        self.regions.push((range, init));
+        self
+    }
+
+    /// Fails if the profile can't be run
+    pub fn build(&self) -> Result<Cpu, &'static str> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut cpu = Cpu::with_profile(self.profile.clone())?;
+        for (offset, register) in cpu.general_purpose.iter_mut().enumerate() {
+            *register = self.registers.value(offset, &mut rng);
+        }
+
+        let mut memory = SimpleMemory::new_blank();
+        for address in 0..MEMORY_SIZE {
+            let (start, init) = self.regions.iter().rev()
+                .find(|(range, _)| range.contains(&address))
+                .map_or((0, &self.memory), |(range, init)| (*range.start(), init));
+            memory.write(address, init.value((address - start) as usize, &mut rng))?;
+        }
+        cpu.memory = Box::new(memory);
+        cpu.seed = Some(Seed(self.seed));
+        Ok(cpu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_cpu() {
+        let builder = CpuBuilder::new().seed(9);
+        assert_eq!(builder.build().unwrap().snapshot(), builder.build().unwrap().snapshot());
+        assert_ne!(builder.build().unwrap().snapshot(), builder.clone().seed(10).build().unwrap().snapshot());
+        assert_eq!(Some(9), builder.build().unwrap().seed());
+    }
+
+    #[test]
+    fn random_memory_varies_by_byte() {
+        let cpu = CpuBuilder::new().seed(1).build().unwrap();
+        let values: Vec<u8> = (0..MEMORY_SIZE).filter_map(|address| cpu.memory.peek(address)).collect();
+        assert!(values.iter().any(|value| *value != values[0]));
+    }
+
+    #[test]
+    fn regions_override_memory() {
+        let cpu = CpuBuilder::new()
+            .seed(2)
+            .registers(Init::Pattern(vec![7]))
+            .memory(Init::Zero)
+            .region(10..=14, Init::Pattern(vec![1, 2]))
+            .region(12..=12, Init::Pattern(vec![9]))
+            .build().unwrap();
+        assert_eq!((0, 7, 7), (cpu.read(0), cpu.read(1), cpu.read(29)));
+        let memory: Vec<u8> = (9..=15).map(|address| cpu.memory.peek(address).unwrap()).collect();
+        assert_eq!(vec![0, 1, 2, 9, 2, 1, 0], memory);
+    }
+
+    #[test]
+    fn rejects_profiles_it_cant_run() {
+        let profile = IsaProfile { general_purpose: 31, ..IsaProfile::v0_1_0() };
+        assert!(CpuBuilder::new().profile(profile).build().is_err());
+    }
+
+    #[test]
+    fn zeroed_matches_blank() {
+        let cpu = CpuBuilder::new().registers(Init::Zero).memory(Init::Zero).build().unwrap();
+        let blank = Cpu::new_blank().snapshot();
+        assert_eq!(blank, cpu.snapshot());
+    }
+}
@@ -0,0 +1,627 @@
+use std::cell::RefCell;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::emulator::memory::Memory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Writes stay in the line until it's evicted or flushed, a write
+    /// miss loads the line first
+    WriteBack,
+    /// Writes go on to memory straight away, a write miss doesn't load
+    /// the line
+    WriteThrough,
+}
+
+/// Which line of a full set is evicted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replacement {
+    LeastRecentlyUsed,
+    FirstInFirstOut,
+    Random,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Total bytes of data held
+    pub size: usize,
+    pub line_size: usize,
+    /// Lines per set
+    pub associativity: usize,
+    pub write_policy: WritePolicy,
+    pub replacement: Replacement,
+    /// Cycles added for each line loaded or written back
+    pub miss_penalty: u64,
+    /// Seeds random replacement, so a run evicts the same lines every time
+    pub seed: u64,
+}
+
+impl CacheConfig {
+    /// Write back with LRU replacement and a 10 cycle miss penalty
+    pub fn new(size: usize, line_size: usize, associativity: usize) -> Self {
+        CacheConfig {
+            size,
+            line_size,
+            associativity,
+            write_policy: WritePolicy::WriteBack,
+            replacement: Replacement::LeastRecentlyUsed,
+            miss_penalty: 10,
+            seed: 0,
+        }
+    }
+
+    pub fn sets(&self) -> usize {
+        self.size / (self.line_size * self.associativity)
+    }
+
+    fn check(&self) -> Result<(), &'static str> {
+        if self.line_size == 0 || self.associativity == 0 {
+            return Err("Line size and associativity must be at least 1");
+        }
+        if self.size == 0 || !self.size.is_multiple_of(self.line_size * self.associativity) {
+            return Err("Size must be a multiple of line size times associativity");
+        }
+        Ok(())
+    }
+}
+
+/// Counted per byte, as memory is read and written a byte at a time, so a
+/// fetch is four reads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub reads: u64,
+    pub writes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// Dirty lines written back to memory
+    pub writebacks: u64,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            accesses => self.hits as f64 / accesses as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    tag: usize,
+    data: Vec<u8>,
+    dirty: bool,
+    /// When it was last used and loaded, for replacement
+    used: u64,
+    loaded: u64,
+}
+
+/// One level of cache. It doesn't own the memory behind it, that's passed
+/// in to each access.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    config: CacheConfig,
+    sets: Vec<Vec<Line>>,
+    stats: CacheStats,
+    time: u64,
+    penalty: u64,
+    rng: StdRng,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Result<Self, &'static str> {
+        config.check()?;
+        Ok(Cache {
+            sets: vec![Vec::new(); config.sets()],
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            stats: CacheStats::default(),
+            time: 0,
+            penalty: 0,
+        })
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &CacheStats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    /// (set, tag, offset) of an address
+    fn locate(&self, address: u8) -> (usize, usize, usize) {
+        let line = address as usize / self.config.line_size;
+        let sets = self.sets.len();
+        (line % sets, line / sets, address as usize % self.config.line_size)
+    }
+
+    fn base(&self, set: usize, tag: usize) -> usize {
+        (tag * self.sets.len() + set) * self.config.line_size
+    }
+
+    fn way(&self, set: usize, tag: usize) -> Option<usize> {
+        self.sets[set].iter().position(|line| line.tag == tag)
+    }
+
+    /// The cached copy of an address, if there is one
+    pub fn peek(&self, address: u8) -> Option<u8> {
+        let (set, tag, offset) = self.locate(address);
+        self.way(set, tag).map(|way| self.sets[set][way].data[offset])
+    }
+
+    /// Finds the line, loading it on a miss, and returns its way
+    fn line(&mut self, backing: &mut dyn Memory, set: usize, tag: usize) -> usize {
+        self.time += 1;
+        if let Some(way) = self.way(set, tag) {
+            self.stats.hits += 1;
+            self.sets[set][way].used = self.time;
+            return way;
+        }
+        self.stats.misses += 1;
+        self.penalty += self.config.miss_penalty;
+
+        let way = if self.sets[set].len() < self.config.associativity {
+            self.sets[set].len()
+        } else {
+            let victim = self.victim(set);
+            self.write_back(backing, set, victim);
+            self.sets[set].remove(victim);
+            self.sets[set].len()
+        };
+        // Bytes past the end of memory are never read from the line
+        let base = self.base(set, tag);
+        let data = (base..base + self.config.line_size)
+            .map(|address| u8::try_from(address).ok()
+                .and_then(|address| backing.peek(address))
+                .unwrap_or(0))
+            .collect();
+        self.sets[set].push(Line { tag, data, dirty: false, used: self.time, loaded: self.time });
+        way
+    }
+
+    fn victim(&mut self, set: usize) -> usize {
+        let lines = &self.sets[set];
+        let oldest = |key: fn(&Line) -> u64| (0..lines.len())
+            .min_by_key(|&way| key(&lines[way]))
+            .unwrap_or(0);
+        match self.config.replacement {
+            Replacement::LeastRecentlyUsed => oldest(|line| line.used),
+            Replacement::FirstInFirstOut => oldest(|line| line.loaded),
+            Replacement::Random => self.rng.gen_range(0..lines.len()),
+        }
+    }
+
+    fn write_back(&mut self, backing: &mut dyn Memory, set: usize, way: usize) {
+        if !self.sets[set][way].dirty {
+            return;
+        }
+        let base = self.base(set, self.sets[set][way].tag);
+        for (address, value) in (base..).zip(&self.sets[set][way].data) {
+            if let Ok(address) = u8::try_from(address) {
+                if backing.contains(address) {
+                    backing.write(address, *value).ok();
+                }
+            }
+        }
+        self.sets[set][way].dirty = false;
+        self.stats.writebacks += 1;
+        self.penalty += self.config.miss_penalty;
+    }
+
+    /// `address` must be mapped in `backing`
+    pub fn read(&mut self, backing: &mut dyn Memory, address: u8) -> u8 {
+        self.stats.reads += 1;
+        let (set, tag, offset) = self.locate(address);
+        let way = self.line(backing, set, tag);
+        self.sets[set][way].data[offset]
+    }
+
+    /// `address` must be mapped in `backing`
+    pub fn write(&mut self, backing: &mut dyn Memory, address: u8, value: u8) -> Result<(), &'static str> {
+        self.stats.writes += 1;
+        let (set, tag, offset) = self.locate(address);
+        match self.config.write_policy {
+            WritePolicy::WriteBack => {
+                let way = self.line(backing, set, tag);
+                let line = &mut self.sets[set][way];
+                line.data[offset] = value;
+                line.dirty = true;
+                Ok(())
+            }
+            WritePolicy::WriteThrough => {
+                self.time += 1;
+                match self.way(set, tag) {
+                    Some(way) => {
+                        self.stats.hits += 1;
+                        let line = &mut self.sets[set][way];
+                        line.data[offset] = value;
+                        line.used = self.time;
+                    }
+                    None => self.stats.misses += 1,
+                }
+                backing.write(address, value)
+            }
+        }
+    }
+
+    /// Updates a cached copy after something else wrote to memory,
+    /// without it counting as an access
+    pub fn snoop(&mut self, address: u8, value: u8) {
+        let (set, tag, offset) = self.locate(address);
+        if let Some(way) = self.way(set, tag) {
+            self.sets[set][way].data[offset] = value;
+        }
+    }
+
+    /// Writes every dirty line back, keeping them cached
+    pub fn flush(&mut self, backing: &mut dyn Memory) {
+        for set in 0..self.sets.len() {
+            for way in 0..self.sets[set].len() {
+                self.write_back(backing, set, way);
+            }
+        }
+    }
+
+    pub fn take_penalty(&mut self) -> u64 {
+        std::mem::take(&mut self.penalty)
+    }
+}
+
+/// Lets the instruction cache load lines through the data cache, so it
+/// sees writes the data cache hasn't written back yet
+struct Through<'a> {
+    data: Option<&'a Cache>,
+    backing: &'a mut dyn Memory,
+}
+
+impl Memory for Through<'_> {
+    fn read(&self, address: u8) -> Option<u8> {
+        self.data.and_then(|data| data.peek(address))
+            .or_else(|| self.backing.peek(address))
+    }
+
+    fn write(&mut self, address: u8, value: u8) -> Result<(), &'static str> {
+        self.backing.write(address, value)
+    }
+}
+
+struct Inner {
+    backing: Box<dyn Memory>,
+    instruction: Option<Cache>,
+    data: Option<Cache>,
+}
+
+/// Instruction and data caches in front of another memory. Reads go
+/// through the data cache and fetches through the instruction cache, each
+/// can be left out. Writes update any copy in the instruction cache.
+pub struct CachedMemory {
+    // Reads update the caches
+    inner: RefCell<Inner>,
+}
+
+impl CachedMemory {
+    pub fn new(backing: Box<dyn Memory>) -> Self {
+        CachedMemory {
+            inner: RefCell::new(Inner { backing, instruction: None, data: None }),
+        }
+    }
+
+    pub fn with_instruction_cache(mut self, config: CacheConfig) -> Result<Self, &'static str> {
+        self.inner.get_mut().instruction = Some(Cache::new(config)?);
+        Ok(self)
+    }
+
+    pub fn with_data_cache(mut self, config: CacheConfig) -> Result<Self, &'static str> {
+        self.inner.get_mut().data = Some(Cache::new(config)?);
+        Ok(self)
+    }
+
+    pub fn instruction_stats(&self) -> Option<CacheStats> {
+        self.inner.borrow().instruction.as_ref().map(|cache| *cache.stats())
+    }
+
+    pub fn data_stats(&self) -> Option<CacheStats> {
+        self.inner.borrow().data.as_ref().map(|cache| *cache.stats())
+    }
+
+    /// Writes the data cache's dirty lines back to memory
+    pub fn flush(&mut self) {
+        let Inner { backing, data, .. } = self.inner.get_mut();
+        if let Some(data) = data {
+            data.flush(backing.as_mut());
+        }
+    }
+
+    /// Flushes and hands back the memory behind the caches
+    pub fn into_backing(mut self) -> Box<dyn Memory> {
+        self.flush();
+        self.inner.into_inner().backing
+    }
+}
+
+impl Memory for CachedMemory {
+    fn read(&self, address: u8) -> Option<u8> {
+        let Inner { backing, data, .. } = &mut *self.inner.borrow_mut();
+        match data {
+            Some(data) if backing.contains(address) => Some(data.read(backing.as_mut(), address)),
+            _ => backing.read(address),
+        }
+    }
+
+    fn write(&mut self, address: u8, value: u8) -> Result<(), &'static str> {
+        let Inner { backing, instruction, data } = self.inner.get_mut();
+        if !backing.contains(address) {
+            return backing.write(address, value);
+        }
+        if let Some(instruction) = instruction {
+            instruction.snoop(address, value);
+        }
+        match data {
+            Some(data) => data.write(backing.as_mut(), address, value),
+            None => backing.write(address, value),
+        }
+    }
+
+    fn contains(&self, address: u8) -> bool {
+        self.inner.borrow().backing.contains(address)
+    }
+
+    fn fetch(&self, address: u8) -> Option<u8> {
+        let Inner { backing, instruction, data } = &mut *self.inner.borrow_mut();
+        let mut through = Through { data: data.as_ref(), backing: backing.as_mut() };
+        match instruction {
+            Some(instruction) if through.contains(address) => Some(instruction.read(&mut through, address)),
+            _ => through.read(address),
+        }
+    }
+
+    fn peek(&self, address: u8) -> Option<u8> {
+        let inner = self.inner.borrow();
+        inner.data.as_ref()
+            .and_then(|data| data.peek(address))
+            .or_else(|| inner.backing.peek(address))
+    }
+
+    fn poke(&mut self, address: u8, value: u8) -> Result<(), &'static str> {
+        let Inner { backing, instruction, data } = self.inner.get_mut();
+        backing.poke(address, value)?;
+        // Any cached copy has to agree, without it counting as an access
+        for cache in [instruction, data].into_iter().flatten() {
+            cache.snoop(address, value);
+        }
+        Ok(())
+    }
+
+    fn take_penalty(&mut self) -> u64 {
+        let inner = self.inner.get_mut();
+        inner.instruction.as_mut().map_or(0, Cache::take_penalty)
+            + inner.data.as_mut().map_or(0, Cache::take_penalty)
+    }
+
+    fn cache_stats(&self) -> Vec<(&'static str, CacheStats)> {
+        let inner = self.inner.borrow();
+        [("instruction", &inner.instruction), ("data", &inner.data)].into_iter()
+            .filter_map(|(name, cache)| Some((name, *cache.as_ref()?.stats())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::memory::SimpleMemory;
+    use crate::emulator::{Cpu, Flags, Instruction, Op, Snapshot};
+    use crate::emulator::op::{Imm12, Imm22};
+
+    fn blank() -> Box<dyn Memory> {
+        Box::new(SimpleMemory::new_blank())
+    }
+
+    #[test]
+    fn rejects_bad_geometry() {
+        assert!(Cache::new(CacheConfig::new(64, 0, 1)).is_err());
+        assert!(Cache::new(CacheConfig::new(60, 8, 2)).is_err());
+        assert_eq!(4, Cache::new(CacheConfig::new(64, 8, 2)).unwrap().sets.len());
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let mut cache = Cache::new(CacheConfig::new(32, 4, 1)).unwrap();
+        let mut backing = SimpleMemory::new_blank();
+        backing.write(5, 7).unwrap();
+        for address in 0..16 {
+            cache.read(&mut backing, address);
+        }
+        assert_eq!(Some(7), cache.peek(5));
+        let stats = cache.stats();
+        assert_eq!((4, 12), (stats.misses, stats.hits));
+        assert_eq!(0.75, stats.hit_rate());
+        assert_eq!(40, cache.take_penalty());
+        assert_eq!(0, cache.take_penalty());
+    }
+
+    #[test]
+    fn write_back_waits_for_eviction() {
+        let mut cache = Cache::new(CacheConfig::new(8, 4, 1)).unwrap();
+        let mut backing = SimpleMemory::new_blank();
+        cache.write(&mut backing, 1, 9).unwrap();
+        assert_eq!(Some(0), backing.read(1));
+        // Maps to the same set
+        cache.read(&mut backing, 9);
+        assert_eq!(Some(9), backing.read(1));
+        assert_eq!(1, cache.stats().writebacks);
+    }
+
+    #[test]
+    fn write_through_doesnt_allocate() {
+        let mut config = CacheConfig::new(8, 4, 1);
+        config.write_policy = WritePolicy::WriteThrough;
+        let mut cache = Cache::new(config).unwrap();
+        let mut backing = SimpleMemory::new_blank();
+        cache.write(&mut backing, 1, 9).unwrap();
+        assert_eq!(Some(9), backing.read(1));
+        assert_eq!(None, cache.peek(1));
+        assert_eq!(9, cache.read(&mut backing, 1));
+        cache.write(&mut backing, 1, 10).unwrap();
+        assert_eq!((Some(10), Some(10)), (backing.read(1), cache.peek(1)));
+        assert_eq!(0, cache.stats().writebacks);
+    }
+
+    #[test]
+    fn replacement_policies() {
+        // One set of two lines, reads lines A, B, A then C
+        let evicted = |replacement| {
+            let mut config = CacheConfig::new(8, 4, 2);
+            config.replacement = replacement;
+            let mut cache = Cache::new(config).unwrap();
+            let mut backing = SimpleMemory::new_blank();
+            for address in [0, 4, 0, 8] {
+                cache.read(&mut backing, address);
+            }
+            [0, 4].into_iter().find(|&address| cache.peek(address).is_none())
+        };
+        assert_eq!(Some(4), evicted(Replacement::LeastRecentlyUsed));
+        assert_eq!(Some(0), evicted(Replacement::FirstInFirstOut));
+        assert!(evicted(Replacement::Random).is_some());
+        assert_eq!(evicted(Replacement::Random), evicted(Replacement::Random));
+    }
+
+    #[test]
+    fn fetches_see_unwritten_stores() {
+        let mut memory = CachedMemory::new(blank())
+            .with_instruction_cache(CacheConfig::new(16, 4, 1)).unwrap()
+            .with_data_cache(CacheConfig::new(16, 4, 1)).unwrap();
+        memory.fetch(0);
+        memory.write(0, 5).unwrap();
+        memory.write(20, 6).unwrap();
+        assert_eq!(Some(5), memory.fetch(0));
+        assert_eq!(Some(6), memory.fetch(20));
+        assert_eq!(Some(6), memory.peek(20));
+        assert_eq!(Some(0), memory.inner.borrow().backing.read(20));
+        let backing = memory.into_backing();
+        assert_eq!(Some(6), backing.read(20));
+    }
+
+    #[test]
+    fn unmapped_addresses_bypass_the_cache() {
+        let mut memory = CachedMemory::new(blank())
+            .with_data_cache(CacheConfig::new(16, 4, 1)).unwrap();
+        assert_eq!(None, memory.read(255));
+        assert!(memory.write(255, 1).is_err());
+        assert_eq!(Some(0), memory.data_stats().map(|stats| stats.reads));
+    }
+
+    #[test]
+    fn loading_costs_nothing() {
+        let cached = CachedMemory::new(blank())
+            .with_data_cache(CacheConfig::new(32, 8, 2)).unwrap();
+        let mut cpu = Cpu::new_blank();
+        cpu.trace = false;
+        cpu.memory = Box::new(cached);
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::AddRd { d: 1, x: 2, y: 3, pad: 0 })).unwrap();
+        cpu.load_bytes(100, &[1, 2, 3]).unwrap();
+        assert_eq!(0, cpu.memory.take_penalty());
+        let cpu = cpu.clock().into_cpu();
+        assert_eq!(cpu.timing.cycles(&Op::AddRd { d: 1, x: 2, y: 3, pad: 0 }, true), cpu.cycles);
+    }
+
+    /// Stores r1 at 64 then jumps back, with both caches warmed up
+    fn warm_loop() -> Cpu {
+        let cached = CachedMemory::new(blank())
+            .with_instruction_cache(CacheConfig::new(32, 8, 2)).unwrap()
+            .with_data_cache(CacheConfig::new(32, 8, 2)).unwrap();
+        let mut cpu = Cpu::new_blank();
+        cpu.trace = false;
+        cpu.memory = Box::new(cached);
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::Store8Bo { t: 1, base: 0, off: 64 })).unwrap();
+        cpu.load_instruction(4, &Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_value(0).unwrap() })).unwrap();
+        for _ in 0..4 {
+            cpu = cpu.clock().into_cpu();
+        }
+        cpu
+    }
+
+    /// Cycles the next two clocks take
+    fn loop_cycles(cpu: Cpu) -> (Cpu, u64) {
+        let start = cpu.cycles;
+        let cpu = cpu.clock().into_cpu().clock().into_cpu();
+        let cycles = cpu.cycles - start;
+        (cpu, cycles)
+    }
+
+    #[test]
+    fn restoring_costs_nothing() {
+        let cpu = warm_loop();
+        let snapshot = cpu.snapshot();
+        let (mut cpu, warm) = loop_cycles(cpu);
+        cpu.write(1, 9);
+        cpu = cpu.clock().into_cpu();
+        let stats = cpu.memory.cache_stats();
+        snapshot.restore(&mut cpu).unwrap();
+        assert_eq!(stats, cpu.memory.cache_stats());
+        assert_eq!(Some(0), cpu.memory.peek(64));
+        let (cpu, cycles) = loop_cycles(cpu);
+        assert_eq!(warm, cycles);
+        assert_eq!(snapshot.cycles + warm, cpu.cycles);
+    }
+
+    #[test]
+    fn stepping_back_costs_nothing() {
+        let mut cpu = warm_loop();
+        cpu.record_history(8);
+        cpu.write(1, 9);
+        let (cpu, warm) = loop_cycles(cpu);
+        let (mut cpu, _) = loop_cycles(cpu);
+        let stats = cpu.memory.cache_stats();
+        assert_eq!(2, cpu.step_back(2));
+        assert_eq!(stats, cpu.memory.cache_stats());
+        assert_eq!(Some(9), cpu.memory.peek(64));
+        let before = cpu.cycles;
+        let (cpu, cycles) = loop_cycles(cpu);
+        assert_eq!((warm, before + warm), (cycles, cpu.cycles));
+    }
+
+    #[test]
+    fn cpu_runs_through_caches() {
+        // Adds 1 to memory 64 over and over
+        let program = [
+            Op::Load8Bo { t: 1, base: 0, off: 64 },
+            Op::AddRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() },
+            Op::Store8Bo { t: 1, base: 0, off: 64 },
+            Op::JumpI { target: Imm22::from_value(0).unwrap() },
+        ];
+        let loaded = || {
+            let mut memory = blank();
+            for (address, op) in (0..).step_by(4).zip(program) {
+                memory.write_u32(address, Instruction::new(Flags::new(), op).encode()).unwrap();
+            }
+            memory
+        };
+        let build = |memory: Box<dyn Memory>| {
+            let mut cpu = Cpu::new_blank();
+            cpu.memory = memory;
+            cpu
+        };
+        let cached = CachedMemory::new(loaded())
+            .with_instruction_cache(CacheConfig::new(32, 8, 2)).unwrap()
+            .with_data_cache(CacheConfig::new(32, 8, 2)).unwrap();
+        let mut cpu = build(Box::new(cached));
+        let mut plain = build(loaded());
+        for _ in 0..40 {
+            cpu = cpu.clock().into_cpu();
+            plain = plain.clock().into_cpu();
+        }
+        assert_eq!(Snapshot { cycles: cpu.cycles, ..Snapshot::take(&plain) }, Snapshot::take(&cpu));
+        // Two instruction lines and one data line missed once each
+        assert_eq!(plain.cycles + 30, cpu.cycles);
+        let stats = cpu.memory.cache_stats();
+        assert_eq!(vec!["instruction", "data"], stats.iter().map(|(name, _)| *name).collect::<Vec<_>>());
+        assert_eq!((2, 158), (stats[0].1.misses, stats[0].1.hits));
+        assert_eq!((1, 19), (stats[1].1.misses, stats[1].1.hits));
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use crate::emulator::{Cpu, Flags, Instruction, UnknownCpu};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hits {
+    pub ran: u64,
+    /// Times its flags skipped it
+    pub skipped: u64,
+}
+
+impl Hits {
+    pub fn reached(&self) -> u64 {
+        self.ran + self.skipped
+    }
+}
+
+/// Where an instruction was in its source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLine {
+    pub address: u32,
+    /// Counting from 1
+    pub line: usize,
+    pub predicated: bool,
+}
+
+impl SourceLine {
+    /// Pairs each instruction, loaded a word apart from `base`, with its line
+    pub fn from_program(instructions: &[Instruction], lines: &[usize], base: u32) -> Vec<SourceLine> {
+        instructions.iter().zip(lines).zip((base..).step_by(4))
+            .map(|((instruction, &line), address)| SourceLine {
+                address,
+                line,
+                predicated: instruction.flags != Flags::new(),
+            })
+            .collect()
+    }
+}
+
+/// Which instructions a program reached, and for predicated ones whether
+/// they ran or were skipped
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    addresses: BTreeMap<u32, Hits>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage::default()
+    }
+
+    pub fn hits(&self, address: u32) -> Hits {
+        self.addresses.get(&address).copied().unwrap_or_default()
+    }
+
+    pub fn reached(&self, address: u32) -> bool {
+        self.hits(address).reached() > 0
+    }
+
+    /// Records the instruction the cpu is about to run
+    pub fn observe(&mut self, cpu: &Cpu) {
+        let Ok(instruction) = cpu.fetch(cpu.program_counter) else { return };
+        let hits = self.addresses.entry(cpu.program_counter).or_default();
+        if Flags::instruction_can_run(&cpu.flags, &instruction.flags) {
+            hits.ran += 1;
+        } else {
+            hits.skipped += 1;
+        }
+    }
+
+    /// Runs up to `limit` instructions, marking each as covered
+    pub fn run(&mut self, cpu: Cpu, limit: usize) -> UnknownCpu {
+        cpu.run_with(limit, |cpu| {
+            self.observe(&cpu);
+            cpu.clock()
+        })
+    }
+
+    /// Adds another run's coverage, such as the same program on another input
+    pub fn merge(&mut self, other: &Coverage) {
+        for (&address, hits) in &other.addresses {
+            let total = self.addresses.entry(address).or_default();
+            total.ran += hits.ran;
+            total.skipped += hits.skipped;
+        }
+    }
+
+    /// A record in lcov's tracefile format for the source file at `path`.
+    /// Every predicated instruction is a branch of two, ran and skipped.
+    pub fn lcov(&self, path: &str, source: &[SourceLine]) -> String {
+        let mut out = format!("TN:\nSF:{path}\n");
+        let mut branches = (0, 0);
+        for (block, line) in source.iter().filter(|line| line.predicated).enumerate() {
+            let hits = self.hits(line.address);
+            for (branch, taken) in [hits.ran, hits.skipped].into_iter().enumerate() {
+                if hits.reached() == 0 {
+                    writeln!(out, "BRDA:{},{block},{branch},-", line.line).unwrap();
+                } else {
+                    writeln!(out, "BRDA:{},{block},{branch},{taken}", line.line).unwrap();
+                }
+                branches.0 += 1;
+                branches.1 += (taken > 0) as usize;
+            }
+        }
+        writeln!(out, "BRF:{}\nBRH:{}", branches.0, branches.1).unwrap();
+        for line in source {
+            writeln!(out, "DA:{},{}", line.line, self.hits(line.address).reached()).unwrap();
+        }
+        let hit = source.iter().filter(|line| self.reached(line.address)).count();
+        writeln!(out, "LF:{}\nLH:{hit}\nend_of_record", source.len()).unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Op;
+    use crate::emulator::op::{Imm12, Imm22};
+    use crate::program_loader::{parse_machine_code, parse_source_lines};
+
+    /// Counts r1 down from `start`, the jump at 8 is skipped until it reaches
+    /// zero and then spins at 16
+    fn program(start: i32) -> (Cpu, Vec<SourceLine>) {
+        let mut until_zero = Flags::new();
+        until_zero.zero = true;
+        let program = [
+            Instruction::new(Flags::new(), Op::AddRi { d: 1, x: 0, i: Imm12::from_value(start).unwrap() }),
+            Instruction::new(Flags::new(), Op::SubRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() }),
+            Instruction::new(until_zero, Op::JumpI { target: Imm22::from_value(16).unwrap() }),
+            Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_value(4).unwrap() }),
+            Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_value(16).unwrap() }),
+            Instruction::new(Flags::new(), Op::Interrupt { i: Imm22::from_value(0).unwrap() }),
+        ];
+        let mut cpu = Cpu::new_blank();
+        for (address, instruction) in (0..).step_by(4).zip(&program) {
+            cpu.load_instruction(address, instruction).unwrap();
+        }
+        let lines: Vec<usize> = (10..).step_by(2).take(program.len()).collect();
+        (cpu, SourceLine::from_program(&program, &lines, 0))
+    }
+
+    #[test]
+    fn counts_ran_and_skipped() {
+        let (cpu, _) = program(3);
+        let mut coverage = Coverage::new();
+        coverage.run(cpu, 10).into_cpu();
+        assert_eq!(Hits { ran: 3, skipped: 0 }, coverage.hits(4));
+        assert_eq!(Hits { ran: 1, skipped: 2 }, coverage.hits(8));
+        assert!(coverage.reached(16));
+        assert!(!coverage.reached(20));
+    }
+
+    #[test]
+    fn lcov_report() {
+        let (cpu, source) = program(1);
+        let mut coverage = Coverage::new();
+        coverage.run(cpu, 4).into_cpu();
+        let expected = "TN:\nSF:count.mc\n\
+            BRDA:14,0,0,1\nBRDA:14,0,1,0\nBRF:2\nBRH:1\n\
+            DA:10,1\nDA:12,1\nDA:14,1\nDA:16,0\nDA:18,1\nDA:20,0\n\
+            LF:6\nLH:4\nend_of_record\n";
+        assert_eq!(expected, coverage.lcov("count.mc", &source));
+    }
+
+    #[test]
+    fn merges_runs() {
+        let mut coverage = Coverage::new();
+        for start in [1, 2] {
+            let (cpu, _) = program(start);
+            let mut run = Coverage::new();
+            run.run(cpu, 6).into_cpu();
+            coverage.merge(&run);
+        }
+        // Only the second run loops back
+        assert_eq!(Hits { ran: 2, skipped: 1 }, coverage.hits(8));
+        assert_eq!(Hits { ran: 1, skipped: 0 }, coverage.hits(12));
+    }
+
+    #[test]
+    fn maps_machine_code_lines() {
+        let source = "# Comment\n00010111110000000000000000000000\n\n0000-100000-00000 00000 00000 0000000";
+        let program = parse_machine_code(source.to_string()).unwrap();
+        let lines = SourceLine::from_program(&program, &parse_source_lines(source), 64);
+        assert_eq!(vec![
+            SourceLine { address: 64, line: 2, predicated: true },
+            SourceLine { address: 68, line: 4, predicated: false },
+        ], lines);
+    }
+}
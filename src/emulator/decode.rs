@@ -0,0 +1,157 @@
+use crate::emulator::{Instruction, Op};
+
+/// Instructions already fetched and decoded, by address. Memory addresses
+/// are a byte, so it's a table with a slot for each.
+///
+/// The cpu drops an entry when it writes to any byte of it, through a
+/// store, `load_instruction`, a snapshot or stepping back, and drops every
+/// entry when its profile changes or an extension is registered. Writes the host
+/// makes straight to `Cpu::memory` aren't seen, so after one call
+/// `Cpu::cache_decoded` again to start afresh.
+#[derive(Debug, Clone)]
+pub struct DecodeCache {
+    entries: Vec<Option<(Instruction, Op)>>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        DecodeCache {
+            entries: vec![None; u8::MAX as usize + 1],
+            hits: 0,
+            misses: 0,
+        }
+    }
+}
+
+impl DecodeCache {
+    pub fn new() -> Self {
+        DecodeCache::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Doesn't count as a hit or miss
+    pub fn peek(&self, address: u32) -> Option<(Instruction, Op)> {
+        *self.entries.get(address as usize)?
+    }
+
+    pub(crate) fn get(&mut self, address: u32) -> Option<(Instruction, Op)> {
+        let entry = self.peek(address);
+        match entry {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        entry
+    }
+
+    pub(crate) fn insert(&mut self, address: u32, instruction: Instruction, op: Op) {
+        if let Some(entry) = self.entries.get_mut(address as usize) {
+            *entry = Some((instruction, op));
+        }
+    }
+
+    /// Drops every instruction that includes the byte at `address`
+    pub fn invalidate(&mut self, address: u32) {
+        for start in address.saturating_sub(3)..=address {
+            if let Some(entry) = self.entries.get_mut(start as usize) {
+                *entry = None;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Cpu, Fault, Flags, IsaProfile, UnknownCpu};
+    use crate::emulator::op::{Imm12, Imm22};
+
+    fn add_one() -> Instruction {
+        Instruction::new(Flags::new(), Op::AddRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() })
+    }
+
+    #[test]
+    fn invalidates_overlapping_entries() {
+        let mut cache = DecodeCache::new();
+        for address in [0, 4, 8] {
+            cache.insert(address, add_one(), add_one().op());
+        }
+        cache.invalidate(7);
+        assert_eq!(None, cache.peek(4));
+        assert!(cache.peek(0).is_some() && cache.peek(8).is_some());
+    }
+
+    #[test]
+    fn loops_hit_the_cache() {
+        let mut cpu = Cpu::new_blank();
+        cpu.load_instruction(0, &add_one()).unwrap();
+        cpu.load_instruction(4, &Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_value(0).unwrap() })).unwrap();
+        cpu.cache_decoded(true);
+        for _ in 0..10 {
+            cpu = cpu.clock().into_cpu();
+        }
+        assert_eq!(5, cpu.read(1));
+        let cache = cpu.decode_cache().unwrap();
+        assert_eq!((2, 8), (cache.misses, cache.hits));
+    }
+
+    #[test]
+    fn stores_to_code_invalidate() {
+        // Overwrites the add at 8 with the word in r2..r5 then runs it
+        let mut cpu = Cpu::new_blank();
+        let sub = Instruction::new(Flags::new(), Op::SubRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() });
+        for (register, byte) in (2..).zip(sub.encode().to_le_bytes()) {
+            cpu.write(register, byte);
+        }
+        cpu.load_instruction(0, &add_one()).unwrap();
+        cpu.load_instruction(4, &Instruction::new(Flags::new(), Op::Store32Bo { t: 2, base: 0, off: 0 })).unwrap();
+        cpu.load_instruction(8, &Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_value(0).unwrap() })).unwrap();
+        cpu.cache_decoded(true);
+        for _ in 0..4 {
+            cpu = match cpu.clock() {
+                UnknownCpu::Ok(cpu) => cpu,
+                UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
+            };
+        }
+        // add, store, jump then the new sub
+        assert_eq!(0, cpu.read(1));
+    }
+
+    #[test]
+    fn profile_and_extensions_clear() {
+        let mut cpu = Cpu::new_blank();
+        cpu.trace = false;
+        cpu.load_instruction(0, &add_one()).unwrap();
+        cpu.cache_decoded(true);
+        cpu = cpu.clock().into_cpu();
+        assert_eq!(1, cpu.decode_cache().unwrap().len());
+        cpu.register_extension(40, "same", |cpu, _| UnknownCpu::Ok(cpu)).unwrap();
+        assert!(cpu.decode_cache().unwrap().is_empty());
+
+        // A revision without add RI runs the same word as a reserved opcode
+        cpu.program_counter = 0;
+        cpu = cpu.clock().into_cpu();
+        let opcode = add_one().opcode;
+        let mut profile = IsaProfile { version: "test", ..IsaProfile::v0_1_0() };
+        profile.opcodes[opcode as usize] = None;
+        cpu.set_profile(profile).unwrap();
+        cpu.program_counter = 0;
+        let cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => panic!("Opcode should have faulted {}", cpu),
+            UnknownCpu::Inter(cpu) => cpu,
+        };
+        assert_eq!(Some(Fault::UnknownOpcode(opcode)), cpu.fault);
+    }
+}
@@ -0,0 +1,396 @@
+use std::fmt;
+use crate::emulator::{Cpu, Pipeline, Snapshot, UnknownCpu};
+
+/// A way of running instructions that should give the same results as
+/// clocking
+#[derive(Clone, Copy)]
+pub enum Engine {
+    Interpreter,
+    DecodeCache,
+    Translated,
+    Pipeline,
+    /// Any other, such as one being written, given a name to report it by
+    Custom(&'static str, fn(Cpu) -> UnknownCpu),
+}
+
+impl Engine {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Engine::Interpreter => "interpreter",
+            Engine::DecodeCache => "decode cache",
+            Engine::Translated => "translated",
+            Engine::Pipeline => "pipeline",
+            Engine::Custom(name, _) => name,
+        }
+    }
+
+    pub fn all() -> [Engine; 4] {
+        [Engine::Interpreter, Engine::DecodeCache, Engine::Translated, Engine::Pipeline]
+    }
+}
+
+impl fmt::Debug for Engine {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.name())
+    }
+}
+
+/// Instructions either side of the diverging one shown in a report
+const CONTEXT: u32 = 3;
+
+/// One engine and the cpu it's running
+struct Lane {
+    engine: Engine,
+    cpu: Option<Cpu>,
+    pipeline: Pipeline,
+    interrupted: bool,
+}
+
+impl Lane {
+    /// Runs up to `limit` instructions, stopping if interrupted. A
+    /// translated lane runs them as blocks.
+    fn step(&mut self, limit: usize) {
+        let mut cpu = self.cpu.take().expect("Lane lost its cpu");
+        for _ in 0..limit {
+            let result = match self.engine {
+                Engine::Interpreter | Engine::DecodeCache => cpu.clock(),
+                Engine::Translated => return self.finish(cpu.run_translated(limit)),
+                Engine::Pipeline => self.pipeline.clock(cpu),
+                Engine::Custom(_, clock) => clock(cpu),
+            };
+            match result {
+                UnknownCpu::Ok(next) => cpu = next,
+                interrupted => return self.finish(interrupted),
+            }
+        }
+        self.finish(UnknownCpu::Ok(cpu));
+    }
+
+    /// Runs the whole block at the program counter, up to `limit`
+    /// instructions, giving the number run
+    fn step_block(&mut self, limit: usize) -> usize {
+        let (result, count) = self.cpu.take().expect("Lane lost its cpu").run_block(limit);
+        self.finish(result);
+        count
+    }
+
+    fn finish(&mut self, result: UnknownCpu) {
+        self.interrupted = matches!(result, UnknownCpu::Inter(_));
+        self.cpu = Some(result.into_cpu());
+    }
+
+    fn cpu(&self) -> &Cpu {
+        self.cpu.as_ref().expect("Lane lost its cpu")
+    }
+
+    fn translated(&self) -> bool {
+        matches!(self.engine, Engine::Translated)
+    }
+}
+
+/// The first step where an engine disagreed with the first engine
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// Counting from 0. When a translated engine diverged, the step its
+    /// block started at.
+    pub step: usize,
+    /// Program counter before the step
+    pub address: u32,
+    pub expected: &'static str,
+    pub actual: &'static str,
+    /// Each thing that differed, as text
+    pub differences: Vec<String>,
+    /// The instructions around `address`, disassembled
+    pub context: String,
+    /// The first engine's power-on seed, if it was built from one
+    pub seed: Option<u64>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(fmt, "{} diverged from {} at step {}, address {}", self.actual, self.expected, self.step, self.address)?;
+        for difference in &self.differences {
+            writeln!(fmt, "  {difference}")?;
+        }
+        if let Some(seed) = self.seed {
+            writeln!(fmt, "  power-on seed {seed}")?;
+        }
+        write!(fmt, "{}", self.context)
+    }
+}
+
+/// Runs the same program on several engines in lockstep, comparing them
+/// after every instruction.
+///
+/// Translated engines run a whole block at a time, so the others are
+/// compared with each other after every instruction of the block and
+/// with the translated ones at its end.
+pub struct Differential {
+    lanes: Vec<Lane>,
+    steps: usize,
+}
+
+impl Differential {
+    /// Gives each engine its own cpu from `setup`, which must make the same
+    /// cpu every time. The first engine is the one the others are checked
+    /// against. Translated engines don't trace or keep history, as doing
+    /// either slows blocks down.
+    pub fn new(engines: &[Engine], setup: impl Fn() -> Cpu) -> Result<Self, &'static str> {
+        if engines.len() < 2 {
+            return Err("Need at least two engines to compare");
+        }
+        let lanes = engines.iter()
+            .map(|&engine| {
+                let mut cpu = setup();
+                match engine {
+                    Engine::DecodeCache => cpu.cache_decoded(true),
+                    Engine::Translated => {
+                        cpu.translate_blocks(true);
+                        cpu.trace = false;
+                        cpu.history = None;
+                    }
+                    _ => (),
+                }
+                Lane { engine, cpu: Some(cpu), pipeline: Pipeline::new(true), interrupted: false }
+            })
+            .collect();
+        Ok(Differential { lanes, steps: 0 })
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// The cpu an engine is running
+    pub fn cpu(&self, engine: usize) -> Option<&Cpu> {
+        self.lanes.get(engine).map(Lane::cpu)
+    }
+
+    /// Steps every engine once, or through a block if any of them
+    /// translate, returning whether they were interrupted
+    pub fn step(&mut self) -> Result<bool, Box<Divergence>> {
+        self.advance(usize::MAX)
+    }
+
+    /// Steps until the engines are interrupted, `limit` steps have been
+    /// taken or they diverge, returning the steps taken
+    pub fn run(&mut self, limit: usize) -> Result<usize, Box<Divergence>> {
+        let start = self.steps;
+        while self.steps - start < limit {
+            if self.advance(limit - (self.steps - start))? {
+                break;
+            }
+        }
+        Ok(self.steps - start)
+    }
+
+    /// Runs a block, of at most `limit` instructions, on the first
+    /// translated engine, then as many instructions on every other engine
+    fn advance(&mut self, limit: usize) -> Result<bool, Box<Divergence>> {
+        let start = (self.steps, self.lanes[0].cpu().program_counter, context(self.lanes[0].cpu(), self.lanes[0].cpu().program_counter));
+        let translated = self.lanes.iter().position(Lane::translated);
+        let count = match translated {
+            Some(lane) => self.lanes[lane].step_block(limit),
+            None => 1,
+        };
+
+        // Engines that don't translate go an instruction at a time
+        let stepped: Vec<usize> = (0..self.lanes.len()).filter(|&lane| !self.lanes[lane].translated()).collect();
+        for _ in 0..count {
+            let Some(&reference) = stepped.first() else { break };
+            let address = self.lanes[reference].cpu().program_counter;
+            let context = context(self.lanes[reference].cpu(), address);
+            for &lane in &stepped {
+                self.lanes[lane].step(1);
+            }
+            let step = self.steps;
+            self.steps += 1;
+            for &lane in &stepped[1..] {
+                self.compare(reference, lane, step, address, &context)?;
+            }
+            if self.lanes[reference].interrupted {
+                break;
+            }
+        }
+        if stepped.is_empty() {
+            self.steps += count;
+        }
+
+        for lane in 0..self.lanes.len() {
+            if self.lanes[lane].translated() && Some(lane) != translated {
+                self.lanes[lane].step(count);
+            }
+        }
+        let (step, address, context) = start;
+        for lane in 1..self.lanes.len() {
+            self.compare(0, lane, step, address, &context)?;
+        }
+        Ok(self.lanes[0].interrupted)
+    }
+
+    fn compare(&self, expected: usize, actual: usize, step: usize, address: u32, context: &str) -> Result<(), Box<Divergence>> {
+        let (expected, actual) = (&self.lanes[expected], &self.lanes[actual]);
+        let differences = differences(expected, actual);
+        if differences.is_empty() {
+            return Ok(());
+        }
+        Err(Box::new(Divergence {
+            step,
+            address,
+            expected: expected.engine.name(),
+            actual: actual.engine.name(),
+            differences,
+            context: context.to_string(),
+            seed: expected.cpu().seed(),
+        }))
+    }
+}
+
+fn differences(expected: &Lane, actual: &Lane) -> Vec<String> {
+    let mut differences = Vec::new();
+    let mut differ = |what: &str, expected: String, actual: String| {
+        if expected != actual {
+            differences.push(format!("{what}: expected {expected}, got {actual}"));
+        }
+    };
+    let (cpu, other) = (expected.cpu(), actual.cpu());
+    let (snapshot, other_snapshot) = (Snapshot::take(cpu), Snapshot::take(other));
+    differ("pc", snapshot.program_counter.to_string(), other_snapshot.program_counter.to_string());
+    differ("flags", snapshot.flags.to_string(), other_snapshot.flags.to_string());
+    differ("sp", snapshot.stack_pointer.to_string(), other_snapshot.stack_pointer.to_string());
+    for (register, (a, b)) in (1..).zip(snapshot.registers.iter().zip(&other_snapshot.registers)) {
+        differ(&format!("r{register}"), a.to_string(), b.to_string());
+    }
+    for ((address, a), (_, b)) in snapshot.memory.iter().zip(&other_snapshot.memory) {
+        differ(&format!("memory {address}"), a.to_string(), b.to_string());
+    }
+    if snapshot.memory.len() != other_snapshot.memory.len() {
+        differ("mapped bytes", snapshot.memory.len().to_string(), other_snapshot.memory.len().to_string());
+    }
+    differ("fault", format!("{:?}", cpu.fault), format!("{:?}", other.fault));
+    differ("interrupted", expected.interrupted.to_string(), actual.interrupted.to_string());
+    differ("cycles", cpu.cycles.to_string(), other.cycles.to_string());
+    differences
+}
+
+/// Disassembles the instructions around `address`, marking it
+fn context(cpu: &Cpu, address: u32) -> String {
+    let first = address.saturating_sub(4 * CONTEXT);
+    let mut out = String::new();
+    for pc in (first..=address.saturating_add(4 * CONTEXT)).step_by(4) {
+        let marker = if pc == address { "-->" } else { "   " };
+        let line = match cpu.fetch(pc) {
+            Ok(instruction) => cpu.profile.disassemble(&instruction, &cpu.extensions).to_string(),
+            Err(fault) => fault.to_string(),
+        };
+        out.push_str(&format!("{pc:>5} {marker} {line}\n"));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Flags, Instruction, Op};
+    use crate::emulator::op::{Imm12, Imm22};
+
+    /// Counts r1 down from 4, storing each value, then interrupts
+    fn countdown() -> Cpu {
+        let mut cpu = Cpu::new_blank();
+        cpu.trace = false;
+        let mut greater = Flags::new();
+        greater.greater = true;
+        let program = [
+            Instruction::new(Flags::new(), Op::AddRi { d: 1, x: 0, i: Imm12::from_value(4).unwrap() }),
+            Instruction::new(Flags::new(), Op::SubRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() }),
+            Instruction::new(Flags::new(), Op::Store8Bi { t: 1, base: 0, index: 1, pad: 0 }),
+            Instruction::new(greater, Op::JumpI { target: Imm22::from_value(4).unwrap() }),
+            Instruction::new(Flags::new(), Op::Interrupt { i: Imm22::from_value(0).unwrap() }),
+        ];
+        for (address, instruction) in (0..).step_by(4).zip(&program) {
+            cpu.load_instruction(address, instruction).unwrap();
+        }
+        cpu
+    }
+
+    /// Clocks, but leaves r2 wrong after the instruction at 8
+    fn broken(cpu: Cpu) -> UnknownCpu {
+        let address = cpu.program_counter;
+        let mut result = cpu.clock();
+        if address == 8 {
+            result.cpu_mut().write(2, 1);
+        }
+        result
+    }
+
+    #[test]
+    fn engines_agree() {
+        let mut differential = Differential::new(&Engine::all(), countdown).unwrap();
+        assert_eq!(Ok(14), differential.run(100).map_err(|divergence| divergence.to_string()));
+        assert_eq!(0, differential.cpu(2).unwrap().read(1));
+    }
+
+    #[test]
+    fn reports_the_first_divergence() {
+        let engines = [Engine::Interpreter, Engine::Translated, Engine::Custom("broken", broken)];
+        let mut differential = Differential::new(&engines, countdown).unwrap();
+        let divergence = differential.run(100).unwrap_err();
+        assert_eq!((2, 8, "broken"), (divergence.step, divergence.address, divergence.actual));
+        assert_eq!(vec!["r2: expected 0, got 1".to_string()], divergence.differences);
+        let marked: Vec<&str> = divergence.context.lines().filter(|line| line.contains("-->")).collect();
+        assert_eq!(1, marked.len());
+        assert!(marked[0].starts_with("    8"), "{}", divergence.context);
+        // Clipped at address 0
+        assert_eq!(6, divergence.context.lines().count());
+    }
+
+    #[test]
+    fn needs_two_engines() {
+        assert!(Differential::new(&[Engine::Interpreter], countdown).is_err());
+    }
+
+    #[test]
+    fn translated_engines_run_whole_blocks() {
+        let setup = || {
+            let mut cpu = countdown();
+            cpu.trace = true;
+            cpu.record_history(4);
+            cpu
+        };
+        let mut differential = Differential::new(&[Engine::Translated, Engine::Interpreter], setup).unwrap();
+        let translated = differential.cpu(0).unwrap();
+        assert!(!translated.trace && translated.history().is_none());
+        assert!(differential.cpu(1).unwrap().history().is_some());
+        // The first block stops after the store at 8, which writes into it
+        assert_eq!(Ok(false), differential.step().map_err(|divergence| divergence.to_string()));
+        assert_eq!(3, differential.steps());
+        assert_eq!(12, differential.cpu(0).unwrap().program_counter);
+        assert_eq!(Ok(11), differential.run(100).map_err(|divergence| divergence.to_string()));
+    }
+
+    #[test]
+    fn translated_engines_see_code_change() {
+        // The store at 4 overwrites the add at 8, later in the same block
+        let setup = || {
+            let mut cpu = Cpu::new_blank();
+            cpu.trace = false;
+            let sub = Instruction::new(Flags::new(), Op::SubRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() });
+            for (register, byte) in (2..).zip(sub.encode().to_le_bytes()) {
+                cpu.write(register, byte);
+            }
+            let program = [
+                Instruction::new(Flags::new(), Op::AddRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() }),
+                Instruction::new(Flags::new(), Op::Store32Bo { t: 2, base: 0, off: 8 }),
+                Instruction::new(Flags::new(), Op::AddRi { d: 1, x: 1, i: Imm12::from_value(10).unwrap() }),
+                Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_value(0).unwrap() }),
+            ];
+            for (address, instruction) in (0..).step_by(4).zip(&program) {
+                cpu.load_instruction(address, instruction).unwrap();
+            }
+            cpu
+        };
+        let mut differential = Differential::new(&[Engine::Interpreter, Engine::Translated], setup).unwrap();
+        assert_eq!(Ok(40), differential.run(40).map_err(|divergence| divergence.to_string()));
+        assert!(differential.cpu(1).unwrap().translation().unwrap().translated > 1);
+    }
+}
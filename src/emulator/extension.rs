@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::emulator::{Cpu, Instruction, IsaProfile, UnknownCpu};
+use crate::emulator::op::Op;
+
+/// Runs an extension instruction. Like the built in instructions the
+/// handler is responsible for moving the program counter on.
+pub type Handler = fn(Cpu, &Instruction) -> UnknownCpu;
+
+#[derive(Clone, Copy)]
+pub struct Extension {
+    pub mnemonic: &'static str,
+    pub handler: Handler,
+}
+
+/// Custom instructions installed into the opcodes a profile leaves free.
+///
+/// The cpu runs them from here and the disassembler names them from here,
+/// an assembler should look mnemonics up with [`Extensions::opcode`].
+#[derive(Clone, Default)]
+pub struct Extensions {
+    by_opcode: HashMap<u8, Extension>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Extensions::default()
+    }
+
+    /// Installs an extension at an opcode `profile` leaves free, see
+    /// [`Cpu::register_extension`] for the cpu's own profile
+    pub fn register(&mut self, profile: &IsaProfile, opcode: u8, mnemonic: &'static str, handler: Handler) -> Result<(), &'static str> {
+        if !profile.is_free(opcode) {
+            return Err("Opcode isn't free for extensions in the profile");
+        }
+        if self.by_opcode.contains_key(&opcode) {
+            return Err("Opcode already has an extension");
+        }
+        if self.opcode(mnemonic).is_some() {
+            return Err("Mnemonic already has an extension");
+        }
+        self.by_opcode.insert(opcode, Extension { mnemonic, handler });
+        Ok(())
+    }
+
+    pub fn get(&self, opcode: u8) -> Option<&Extension> {
+        self.by_opcode.get(&opcode)
+    }
+
+    pub fn mnemonic(&self, opcode: u8) -> Option<&'static str> {
+        self.get(opcode).map(|extension| extension.mnemonic)
+    }
+
+    pub fn opcode(&self, mnemonic: &str) -> Option<u8> {
+        self.by_opcode.iter()
+            .find(|(_, extension)| extension.mnemonic == mnemonic)
+            .map(|(opcode, _)| *opcode)
+    }
+}
+
+/// An instruction shown with the names of any extensions, see
+/// [`Instruction::disassemble`]
+pub struct Disassembly<'a> {
+    pub(crate) instruction: &'a Instruction,
+    /// The instruction's operation, decoded for the cpu's ISA profile
+    pub(crate) op: Op,
+    pub(crate) extensions: &'a Extensions,
+}
+
+impl fmt::Display for Disassembly<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "|{}", self.instruction.flags_str())?;
+        match self.op {
+            Op::Reserved { opcode, operands } => match self.extensions.mnemonic(opcode) {
+                Some(mnemonic) => write!(fmt, "|{:20}|{:19X}|", mnemonic, operands),
+                None => write!(fmt, "|{}", self.op),
+            },
+            op => write!(fmt, "|{}", op),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Fault;
+    use crate::emulator::flags::Flags;
+
+    /// Puts 42 in the destination register
+    fn answer(mut cpu: Cpu, instruction: &Instruction) -> UnknownCpu {
+        cpu.write(instruction.r_dest(), 42);
+        cpu.program_counter += 4;
+        UnknownCpu::Ok(cpu)
+    }
+
+    #[test]
+    fn only_free_opcodes() {
+        let profile = IsaProfile::v0_1_0();
+        let mut extensions = Extensions::new();
+        assert!(extensions.register(&profile, 12, "answer", answer).is_err());
+        assert!(extensions.register(&profile, 64, "answer", answer).is_err());
+        assert!(extensions.register(&profile, 33, "answer", answer).is_ok());
+        assert!(extensions.register(&profile, 33, "other", answer).is_err());
+        assert!(extensions.register(&profile, 34, "answer", answer).is_err());
+    }
+
+    #[test]
+    fn follows_the_profile() {
+        // Frees 12 and maps 50 to it
+        let mut profile = IsaProfile::v0_1_0();
+        profile.opcodes[12] = None;
+        profile.opcodes[50] = Some(12);
+        let mut extensions = Extensions::new();
+        assert!(extensions.register(&profile, 50, "answer", answer).is_err());
+        assert!(extensions.register(&profile, 12, "answer", answer).is_ok());
+    }
+
+    #[test]
+    fn looks_up_both_ways() {
+        let mut extensions = Extensions::new();
+        extensions.register(&IsaProfile::v0_1_0(), 40, "answer", answer).unwrap();
+        assert_eq!(Some(40), extensions.opcode("answer"));
+        assert_eq!(Some("answer"), extensions.mnemonic(40));
+        assert_eq!(None, extensions.opcode("question"));
+        assert_eq!(None, extensions.mnemonic(41));
+    }
+
+    #[test]
+    fn cpu_runs_extension() {
+        let mut cpu = Cpu::new_blank();
+        cpu.register_extension(40, "answer", answer).unwrap();
+        let mut instruction = Instruction::from_opcode(40);
+        instruction.r_dest_set(3).unwrap();
+        cpu.load_instruction(0, &instruction).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => cpu,
+            UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
+        };
+        assert_eq!(42, cpu.read(3));
+        assert_eq!(4, cpu.program_counter);
+    }
+
+    #[test]
+    fn extension_obeys_flags() {
+        let mut cpu = Cpu::new_blank();
+        cpu.register_extension(40, "answer", answer).unwrap();
+        let mut flags = Flags::new();
+        flags.zero = true;
+        let mut instruction = Instruction::new(flags, Op::Reserved { opcode: 40, operands: 0 });
+        instruction.r_dest_set(3).unwrap();
+        cpu.load_instruction(0, &instruction).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => cpu,
+            UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
+        };
+        assert_eq!(0, cpu.read(3));
+    }
+
+    #[test]
+    fn unknown_opcode_faults() {
+        let mut cpu = Cpu::new_blank();
+        cpu.load_instruction(0, &Instruction::from_opcode(40)).unwrap();
+        cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => panic!("Opcode should have faulted {}", cpu),
+            UnknownCpu::Inter(cpu) => cpu,
+        };
+        assert_eq!(Some(Fault::UnknownOpcode(40)), cpu.fault);
+    }
+
+    #[test]
+    fn disassembles_mnemonic() {
+        let mut extensions = Extensions::new();
+        extensions.register(&IsaProfile::v0_1_0(), 40, "answer", answer).unwrap();
+        let instruction = Instruction::from_opcode(40);
+        assert!(instruction.disassemble(&extensions).to_string().contains("answer"));
+        assert!(!instruction.disassemble(&Extensions::new()).to_string().contains("answer"));
+    }
+}
@@ -0,0 +1,28 @@
+use std::fmt;
+
+/// Why an instruction couldn't complete. Set on the cpu when clock
+/// returns [`UnknownCpu::Inter`](super::UnknownCpu) because of an error
+/// rather than a software interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The register isn't part of the register file
+    InvalidRegister(u8),
+    /// No memory is mapped at the address
+    Unmapped(u32),
+    /// A multi-byte access that isn't aligned to its width
+    Misaligned { address: u32, width: u8 },
+    /// A reserved opcode with no extension installed
+    UnknownOpcode(u8),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::InvalidRegister(register) => write!(fmt, "Invalid register {register}"),
+            Fault::Unmapped(address) => write!(fmt, "No memory at address {address}"),
+            Fault::Misaligned { address, width } =>
+                write!(fmt, "Address {address} isn't aligned to {width} bytes"),
+            Fault::UnknownOpcode(opcode) => write!(fmt, "Instruction {opcode} not implemented"),
+        }
+    }
+}
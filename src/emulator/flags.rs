@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq, Clone, Copy)]
 pub struct Flags {
     pub carry: bool,
     pub greater: bool,
@@ -8,6 +8,12 @@ pub struct Flags {
     pub less: bool,
 }
 
+impl Default for Flags {
+    fn default() -> Self {
+        Flags::new()
+    }
+}
+
 impl Flags {
     pub fn new() -> Flags {
         Flags {
@@ -18,6 +24,21 @@ impl Flags {
         }
     }
 
+    /// Packed as carry 8, greater 4, zero 2, less 1
+    pub fn nibble(&self) -> u8 {
+        (self.carry as u8) << 3 | (self.greater as u8) << 2 | (self.zero as u8) << 1 | self.less as u8
+    }
+
+    /// Unpacks `nibble`, ignoring the bits above it
+    pub fn from_nibble(nibble: u8) -> Flags {
+        Flags {
+            carry: nibble & 8 != 0,
+            greater: nibble & 4 != 0,
+            zero: nibble & 2 != 0,
+            less: nibble & 1 != 0,
+        }
+    }
+
     pub fn set_all_flags(&mut self, state:bool) {
             self.carry = state;
             self.less = state;
@@ -63,7 +84,6 @@ impl fmt::Debug for Flags {
 
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
 
@@ -106,4 +126,14 @@ mod tests {
         assert!(Flags::instruction_can_run(&cpu, &inst));
     }
 
+    #[test]
+    fn nibble_round_trips() {
+        let flags = Flags { carry: true, greater: false, zero: true, less: false };
+        assert_eq!(0b1010, flags.nibble());
+        for nibble in 0..16 {
+            assert_eq!(nibble, Flags::from_nibble(nibble).nibble());
+        }
+        assert_eq!(Flags::from_nibble(0xF), Flags::from_nibble(0xFF));
+    }
+
 }
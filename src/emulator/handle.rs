@@ -0,0 +1,98 @@
+use std::panic::{self, AssertUnwindSafe};
+use crate::emulator::{Cpu, UnknownCpu};
+
+/// How a step or run through a `Handle` ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stepped {
+    /// The instruction ran, or a run's budget was spent
+    Ran,
+    Interrupted,
+    /// The emulator panicked and the cpu went with it
+    Lost,
+}
+
+/// A cpu held in place for the C and JavaScript APIs, which can't take it
+/// by value to clock it. Tracing is off.
+pub struct Handle {
+    /// Only missing while an instruction runs, or for good once one
+    /// panicked
+    cpu: Option<Cpu>,
+}
+
+impl Handle {
+    pub fn new(mut cpu: Cpu) -> Handle {
+        cpu.trace = false;
+        Handle { cpu: Some(cpu) }
+    }
+
+    /// None once the cpu is lost
+    pub fn cpu(&self) -> Option<&Cpu> {
+        self.cpu.as_ref()
+    }
+
+    pub fn cpu_mut(&mut self) -> Option<&mut Cpu> {
+        self.cpu.as_mut()
+    }
+
+    /// Runs one instruction, catching a panic rather than unwinding into
+    /// the caller
+    pub fn step(&mut self) -> Stepped {
+        let Some(cpu) = self.cpu.take() else { return Stepped::Lost };
+        let (cpu, stepped) = match panic::catch_unwind(AssertUnwindSafe(|| cpu.clock())) {
+            Ok(UnknownCpu::Ok(cpu)) => (cpu, Stepped::Ran),
+            Ok(UnknownCpu::Inter(cpu)) => (cpu, Stepped::Interrupted),
+            Err(_) => return Stepped::Lost,
+        };
+        self.cpu = Some(cpu);
+        stepped
+    }
+
+    /// Steps until interrupted or `budget` cycles have been spent, with the
+    /// number of instructions run
+    pub fn run(&mut self, budget: u64) -> (Stepped, u64) {
+        let Some(cpu) = self.cpu() else { return (Stepped::Lost, 0) };
+        let end = cpu.cycles.saturating_add(budget);
+        let mut steps = 0;
+        while self.cpu().is_some_and(|cpu| cpu.cycles < end) {
+            steps += 1;
+            let stepped = self.step();
+            if stepped != Stepped::Ran {
+                return (stepped, steps);
+            }
+        }
+        (Stepped::Ran, steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Flags, Instruction, Op};
+    use crate::emulator::op::{Imm12, Imm22};
+
+    #[test]
+    fn runs_to_a_budget() {
+        let mut cpu = Cpu::new_blank();
+        // Adds 1 to r1 then jumps back to the start
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::AddRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() })).unwrap();
+        cpu.load_instruction(4, &Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_value(0).unwrap() })).unwrap();
+        let mut handle = Handle::new(cpu);
+        assert_eq!(Stepped::Ran, handle.step());
+        let (stepped, steps) = handle.run(10);
+        assert_eq!(Stepped::Ran, stepped);
+        assert!(handle.cpu().unwrap().cycles >= 10);
+        // The run starts at the jump, so every second step is an add
+        assert_eq!(1 + steps as u8 / 2, handle.cpu().unwrap().read(1));
+    }
+
+    #[test]
+    fn loses_the_cpu_to_a_panic() {
+        let mut cpu = Cpu::new_blank();
+        cpu.register_extension(40, "panics", |_, _| panic!("Extension panicked")).unwrap();
+        cpu.load_bytes(0, &(40u32 << 22).to_le_bytes()).unwrap();
+        let mut handle = Handle::new(cpu);
+        assert_eq!(Stepped::Lost, handle.step());
+        assert!(handle.cpu().is_none());
+        assert_eq!((Stepped::Lost, 0), handle.run(10));
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::VecDeque;
+use crate::emulator::flags::Flags;
+
+/// What one clock changed, holding the values from before it ran
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub program_counter: u32,
+    pub flags: Flags,
+    /// (register, old value) in the order they were written
+    pub registers: Vec<(u8, u8)>,
+    /// (address, old value) in the order they were written
+    pub memory: Vec<(u8, u8)>,
+    pub cycles: u64,
+}
+
+impl Step {
+    pub fn wrote(&self, address: u8) -> bool {
+        self.memory.iter().any(|(written, _)| *written == address)
+    }
+}
+
+/// Undo log of the most recent clocks, oldest first.
+///
+/// Only writes made while an instruction runs are logged, so registers
+/// and memory changed by the host between clocks aren't undone.
+#[derive(Debug, Clone)]
+pub struct History {
+    steps: VecDeque<Step>,
+    limit: usize,
+    recording: bool,
+}
+
+impl History {
+    /// Keeps at most `limit` steps, dropping the oldest
+    pub fn new(limit: usize) -> Self {
+        History {
+            steps: VecDeque::new(),
+            limit,
+            recording: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn steps(&self) -> impl DoubleEndedIterator<Item = &Step> {
+        self.steps.iter()
+    }
+
+    /// Forgets every step, so nothing before now can be undone
+    pub fn clear(&mut self) {
+        self.steps.clear();
+        self.recording = false;
+    }
+
+    pub(crate) fn begin(&mut self, program_counter: u32, flags: Flags, cycles: u64) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.steps.len() == self.limit {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(Step {
+            program_counter,
+            flags,
+            registers: Vec::new(),
+            memory: Vec::new(),
+            cycles,
+        });
+        self.recording = true;
+    }
+
+    pub(crate) fn end(&mut self) {
+        self.recording = false;
+    }
+
+    pub(crate) fn register(&mut self, register: u8, old: u8) {
+        if let Some(step) = self.recording_step() {
+            step.registers.push((register, old));
+        }
+    }
+
+    pub(crate) fn memory(&mut self, address: u8, old: u8) {
+        if let Some(step) = self.recording_step() {
+            step.memory.push((address, old));
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Step> {
+        self.steps.pop_back()
+    }
+
+    fn recording_step(&mut self) -> Option<&mut Step> {
+        if self.recording {
+            self.steps.back_mut()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::{Cpu, Instruction, Op, Snapshot, UnknownCpu};
+    use crate::emulator::flags::Flags;
+    use crate::emulator::op::{Imm12, Imm22};
+
+    /// Stores 0, 1, 2... to memory 64, 65, 66... forever
+    fn counting_cpu() -> Cpu {
+        let mut cpu = Cpu::new_blank();
+        let one = Imm12::from_value(1).unwrap();
+        let program = [
+            Op::AddRi { d: 1, x: 0, i: Imm12::from_value(64).unwrap() },
+            Op::Store8Bo { t: 2, base: 1, off: 0 },
+            Op::AddRi { d: 2, x: 2, i: one },
+            Op::AddRi { d: 1, x: 1, i: one },
+            Op::JumpI { target: Imm22::from_value(4).unwrap() },
+        ];
+        for (address, op) in (0..).step_by(4).zip(program) {
+            cpu.load_instruction(address, &Instruction::new(Flags::new(), op)).unwrap();
+        }
+        cpu
+    }
+
+    fn clock(cpu: Cpu) -> Cpu {
+        match cpu.clock() {
+            UnknownCpu::Ok(cpu) => cpu,
+            UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
+        }
+    }
+
+    #[test]
+    fn steps_back_to_earlier_states() {
+        let mut cpu = counting_cpu();
+        cpu.record_history(100);
+        let mut snapshots = vec![Snapshot::take(&cpu)];
+        for _ in 0..30 {
+            cpu = clock(cpu);
+            snapshots.push(Snapshot::take(&cpu));
+        }
+        for n in [1, 4, 10, 15] {
+            snapshots.truncate(snapshots.len() - n);
+            assert_eq!(n, cpu.step_back(n));
+            assert_eq!(snapshots.last(), Some(&Snapshot::take(&cpu)));
+        }
+        // Can run forwards again after going back
+        cpu = clock(cpu);
+        assert_eq!(1, cpu.step_back(1));
+        assert_eq!(snapshots.last(), Some(&Snapshot::take(&cpu)));
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut cpu = counting_cpu();
+        cpu.record_history(3);
+        for _ in 0..5 {
+            cpu = clock(cpu);
+        }
+        assert_eq!(3, cpu.history().unwrap().len());
+        assert_eq!(3, cpu.step_back(10));
+        // Only back to after the second clock
+        assert_eq!(8, cpu.program_counter);
+        // An add then a store
+        assert_eq!(cpu.timing.alu + cpu.timing.store[0], cpu.cycles);
+    }
+
+    #[test]
+    fn steps_back_to_last_write() {
+        let mut cpu = counting_cpu();
+        cpu.record_history(100);
+        for _ in 0..20 {
+            cpu = clock(cpu);
+        }
+        assert_eq!(Some(2), cpu.memory.read(66));
+        assert!(cpu.step_back_to_write(66).is_some());
+        // Back on the store that wrote 66, before it ran
+        assert_eq!(4, cpu.program_counter);
+        assert_eq!(66, cpu.read(1));
+        assert_eq!(Some(0), cpu.memory.read(66));
+        assert_eq!(None, cpu.step_back_to_write(100));
+    }
+
+    #[test]
+    fn host_writes_are_not_logged() {
+        let mut cpu = counting_cpu();
+        cpu.record_history(100);
+        cpu = clock(cpu);
+        cpu.write(5, 99);
+        assert_eq!(1, cpu.step_back(1));
+        assert_eq!(99, cpu.read(5));
+    }
+}
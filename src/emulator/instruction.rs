@@ -1,9 +1,11 @@
 #[allow(unused_imports)]
 use rand::Rng;
 use crate::emulator::flags::Flags;
+use crate::emulator::op::Op;
+use crate::emulator::extension::{Disassembly, Extensions};
 
 pub const NEGITIVE_BIT: u32 = 1 << 21;
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Instruction {
     pub flags: Flags,
     pub opcode: u8,
@@ -13,46 +15,56 @@ pub struct Instruction {
 impl std::fmt::Display for Instruction {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         // Flags
-        write!(fmt, "|{}", if self.flags.carry {"C"} else {"-"}).ok();
-        write!(fmt, "{}", if self.flags.less {"N"} else {"-"}).ok();
-        write!(fmt, "{}", if self.flags.zero {"Z"} else {"-"}).ok();
-        write!(fmt, "{}", if self.flags.greater {"P"} else {"-"}).ok();
-
-        // Opcode
-        let opcode: String = format!("{}", self.opcode);
-        write!(fmt, "|{:20}|", match self.opcode {
-            11 => "Addition RD",
-            12 => "Addition RI",
-            17 => "Load from Mem BO",
-            23 => "Store 8bits",
-            29 => "Jump offset",
-            30 => "Jump to Rd",
-            31 => "Jump to I",
-            32 => "Interupt",
-            _ => opcode.as_str()
-        }).ok();
-
-        // Operands
-        match (self.opcode, self.opcode % 2 == 0) {
-            // Logic Rd
-            (0..=16, false)             => write!(fmt, "{:4}:{:4}:{:4}:    |", self.r_dest(), self.r_x(), self.r_y()).ok(),
-            (0..=16, true)              => write!(fmt, "{:4}:{:4}:{:9}|", self.r_dest(), self.r_x(), self.i_y()).ok(),
-            (17..=28, false)            => write!(fmt, "{:4}:{:4}:{:9}|", self.r_target(), self.r_base(), self.i_offset()).ok(),
-            (17..=28, true)             => write!(fmt, "Memory             |").ok(),
-            (29, _) | (31, _) | (32, _) => write!(fmt, "{:19}|", self.i()).ok(),
-            (30, _)                     => write!(fmt, "{:19}|", self.r_dest()).ok(),
-            (33..=u8::MAX, _)           => write!(fmt, "{:19}|", self.i()).ok(),
-        };
-
+        write!(fmt, "|{}", self.flags_str()).ok();
 
-  //      write!(fmt, "{:9}|", self.operands).ok();
+        // Opcode and operands
+        write!(fmt, "|{}", self.op()).ok();
 
         Ok(())
     }
 
 }
 
+impl From<u32> for Instruction {
+    fn from(value: u32) -> Self {
+        Instruction::decode(value)
+    }
+}
+
+impl From<&Instruction> for u32 {
+    fn from(instruction: &Instruction) -> Self {
+        instruction.encode()
+    }
+}
+
 impl Instruction {
+    pub fn new(flags: Flags, op: Op) -> Self {
+        let bits = u32::from(op);
+        Instruction {
+            flags,
+            opcode: (bits >> 22) as u8,
+            operands: bits & 0x3FFFFF,
+        }
+    }
+
+    /// Decodes the opcode and operands
+    pub fn op(&self) -> Op {
+        Op::from((self.opcode as u32 & 0x3F) << 22 | (self.operands & 0x3FFFFF))
+    }
+
+    /// Displays the instruction, naming any extension opcodes
+    pub fn disassemble<'a>(&'a self, extensions: &'a Extensions) -> Disassembly<'a> {
+        Disassembly { instruction: self, op: self.op(), extensions }
+    }
+
+    pub(crate) fn flags_str(&self) -> String {
+        format!("{}{}{}{}",
+            if self.flags.carry {"C"} else {"-"},
+            if self.flags.less {"N"} else {"-"},
+            if self.flags.zero {"Z"} else {"-"},
+            if self.flags.greater {"P"} else {"-"})
+    }
+
     pub fn from_opcode(opcode: u8) -> Self {
        Instruction {
            flags: Flags::new(),
@@ -62,21 +74,33 @@ impl Instruction {
     }
 
 
-    #[allow(arithmetic_overflow)]
+    /// Panics if the opcode doesn't fit in its 6 bits, see try_encode
     pub fn encode(&self) -> u32 {
-        // TODO move this earlier
+        match self.try_encode() {
+            Ok(word) => word,
+            Err(msg) => panic!("{msg}"),
+        }
+    }
+
+    #[allow(arithmetic_overflow)]
+    pub fn try_encode(&self) -> Result<u32, &'static str> {
         if self.opcode > 0x3F {
-            panic!("opcode too large");
+            return Err("opcode too large");
         };
         if self.operands > 0x3FFFFF {
-            panic!("operands too large {:X}", self.operands);
+            return Err("operands too large");
         };
-        (if self.flags.carry             {(1_u32) << 31} else{ 0 }) |
+        Ok((if self.flags.carry             {(1_u32) << 31} else{ 0 }) |
         (if self.flags.less    {1<< 30} else{ 0 }) as u32 |
         (if self.flags.zero     {1<< 29} else{ 0 }) as u32 |
         (if self.flags.greater {1<< 28} else{ 0 }) as u32 |
         (self.opcode as u32)            << 22 |
-        self.operands
+        self.operands)
+    }
+
+    /// The 22 operand bits, undecoded
+    pub fn operands(&self) -> u32 {
+        self.operands & 0x3FFFFF
     }
 
     pub fn decode(value: u32) -> Self {
@@ -114,27 +138,29 @@ impl Instruction {
     }
 
 
-    // Should restructer this...... 
-    // Yuck
+    // Raw field accessors, these don't check the opcode's format.
+    // Use op() to get the operands decoded for the opcode
 
     pub fn r_dest(&self) -> u8 {
         self.first()
     }
 
-    pub fn r_dest_set(&mut self, value:u8) {
+    /// Fails if the register doesn't fit in 5 bits
+    pub fn r_dest_set(&mut self, value:u8) -> Result<(), &'static str> {
         // TODO add tests
         if value > 0x1F {
-            panic!("Value too large")
+            return Err("Value too large");
         }
         self.operands &= !(0x1F << 16);
         self.operands |= (value as u32) << 17;
+        Ok(())
     }
 
     pub fn r_target(&self) -> u8 {
         self.r_dest()
     }
 
-    pub fn r_target_set(&mut self, value: u8) {
+    pub fn r_target_set(&mut self, value: u8) -> Result<(), &'static str> {
         self.r_dest_set(value)
     }
 
@@ -191,13 +217,15 @@ impl Instruction {
         self.operands & 0xFFF
     }
 
-    pub fn i_offset_set(&mut self, value: u32) {
+    /// Fails if the offset doesn't fit in 12 bits
+    pub fn i_offset_set(&mut self, value: u32) -> Result<(), &'static str> {
         if value > 0xFFF{
-            panic!("value is too large")
+            return Err("value is too large");
         }
         //TODO add tests
         self.operands &= !0xFFF;
         self.operands |= 0xFFF & value;
+        Ok(())
     }
 
     pub fn i(&self) -> i32 {
@@ -209,14 +237,12 @@ impl Instruction {
         }
     }
 
-    pub fn i_set(&mut self, mut value: i32) {
-        self.operands = 0;
-        if value > 0 {
-            self.operands |= NEGITIVE_BIT;
-        } 
+    pub fn i_set(&mut self, value: i32) {
         // maybe throw error here if value is too large?
-        value &= 0x1FFFFF;
-        self.operands = value.abs() as u32;
+        self.operands = value.unsigned_abs() & 0x1FFFFF;
+        if value < 0 {
+            self.operands |= NEGITIVE_BIT;
+        }
     }
 
 }
@@ -329,13 +355,53 @@ mod tests {
     fn i_offset_set() {
         let mut rng = rand::thread_rng();
         let mut instruction = Instruction::from_opcode(0);
-        instruction.i_offset_set(0);
+        instruction.i_offset_set(0).unwrap();
         assert_eq!(0, instruction.i_offset(), "Failed on value {}", 0);
         for i in 0..100 {
             let value = rng.gen::<u32>() & 0xFFF;
-            instruction.i_offset_set(value);
+            instruction.i_offset_set(value).unwrap();
             assert_eq!(value, instruction.i_offset(), "Failed on value {}, on the {} test", value, i);
         }
+        assert!(instruction.i_offset_set(0x1000).is_err());
+        assert!(instruction.r_dest_set(32).is_err());
     }
-}
 
+    #[test]
+    fn round_trips_words() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            let word = rng.gen::<u32>();
+            let instruction = Instruction::from(word);
+            assert_eq!(word, u32::from(&instruction));
+            assert_eq!(instruction, Instruction::new(instruction.flags, instruction.op()));
+        }
+    }
+
+    #[test]
+    fn try_encode_rejects_large_opcodes() {
+        let instruction = Instruction::from_opcode(0x40);
+        assert_eq!(Err("opcode too large"), instruction.try_encode());
+        assert_eq!(Ok(0x0400_0000), Instruction::from_opcode(0x10).try_encode());
+    }
+
+    #[test]
+    fn new_from_op() {
+        let mut flags = Flags::new();
+        flags.zero = true;
+        let instruction = Instruction::new(flags, Op::AddRd { d: 1, x: 2, y: 3, pad: 0 });
+        assert_eq!(11, instruction.opcode);
+        assert_eq!(1, instruction.r_dest());
+        assert_eq!(2, instruction.r_x());
+        assert_eq!(3, instruction.r_y());
+        assert!(instruction.flags.zero);
+    }
+
+    #[test]
+    fn i_set_negative() {
+        let mut instruction = Instruction::from_opcode(29);
+        for value in [-1, -12, -0x1FFFFF, 0, 12, 0x1FFFFF] {
+            instruction.i_set(value);
+            assert_eq!(value, instruction.i(), "Failed on value {}", value);
+        }
+    }
+}
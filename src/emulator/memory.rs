@@ -1,5 +1,7 @@
 use std::fmt;
-use rand::Rng;
+use rand::{Rng, RngCore, SeedableRng};
+use rand::rngs::StdRng;
+use crate::emulator::cache::CacheStats;
 #[allow(unused_imports)]
 use rand::Fill;
 
@@ -11,10 +13,67 @@ pub struct SimpleMemory {
     data: [u8; MEMORY_SIZE as usize]
 }
 
+/// What a load or store wider than a byte does when its address isn't a
+/// multiple of its width
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MisalignedAccess {
+    /// Carry out the access as if it were aligned
+    #[default]
+    Allow,
+    /// Fault without touching the registers or memory
+    Trap,
+    /// Break the access at the alignment boundary into two accesses. Both
+    /// are checked before either is committed, so a fault in the second
+    /// leaves the registers and memory untouched
+    Split,
+}
+
 pub trait Memory {
     fn read(&self, address: u8) -> Option<u8>;
     fn write(&mut self, address:u8, value: u8) -> Result<(), &'static str>;
 
+    /// Returns true if reads and writes to the address will succeed
+    fn contains(&self, address: u8) -> bool {
+        self.peek(address).is_some()
+    }
+
+    /// Reads a byte of an instruction being fetched
+    fn fetch(&self, address: u8) -> Option<u8> {
+        self.read(address)
+    }
+
+    /// Reads without side effects, for looking at memory rather than
+    /// running a program. Backends where reading has side effects, or is
+    /// counted, should override this.
+    fn peek(&self, address: u8) -> Option<u8> {
+        self.read(address)
+    }
+
+    /// Writes without side effects, for the host putting memory into a
+    /// state rather than a program storing to it. Backends where writing
+    /// is counted or costs cycles should override this.
+    fn poke(&mut self, address: u8, value: u8) -> Result<(), &'static str> {
+        self.write(address, value)
+    }
+
+    /// Every mapped address and its value, in address order
+    fn contents(&self) -> Vec<(u8, u8)> {
+        (0..=u8::MAX)
+            .filter_map(|address| Some((address, self.peek(address)?)))
+            .collect()
+    }
+
+    /// Extra cycles accesses have taken since the last call, such as cache
+    /// misses. The cpu adds them to its cycle count after each clock.
+    fn take_penalty(&mut self) -> u64 {
+        0
+    }
+
+    /// Statistics of any caches in front of the memory, by name
+    fn cache_stats(&self) -> Vec<(&'static str, CacheStats)> {
+        Vec::new()
+    }
+
     fn read_u32(&self, address: u8) -> Option<u32> {
         Some(
              self.read(address    )? as u32        |
@@ -32,12 +91,33 @@ pub trait Memory {
     }
 }
 
+impl Default for SimpleMemory {
+    fn default() -> Self {
+        SimpleMemory::new()
+    }
+}
+
+/// A generator seeded from the platform, for power-on state nobody asked
+/// to repeat. It's the only source of randomness that isn't passed in.
+pub fn entropy() -> StdRng {
+    #[cfg(not(target_arch = "wasm32"))]
+    let seed = rand::thread_rng().next_u64();
+    // wasm32-unknown-unknown has no OS random source, JavaScript does
+    #[cfg(target_arch = "wasm32")]
+    let seed = (js_sys::Math::random() * u64::MAX as f64) as u64;
+    StdRng::seed_from_u64(seed)
+}
+
 impl SimpleMemory {
     pub fn new() -> Self {
-        let mut rng = rand::thread_rng();
-        SimpleMemory {
-            data: [rng.gen(); MEMORY_SIZE as usize]
-        }
+        SimpleMemory::with_rng(&mut entropy())
+    }
+
+    /// Filled from `rng`, so a seeded generator gives the same memory
+    pub fn with_rng(rng: &mut impl RngCore) -> Self {
+        let mut data = [0; MEMORY_SIZE as usize];
+        rng.fill(&mut data[..]);
+        SimpleMemory { data }
     }
 
     pub fn new_blank() -> Self {
@@ -76,7 +156,7 @@ impl fmt::Display for dyn Memory {
             if i % 8 == 0 {
                 write!(fmt, "\n|{:3}|", i).ok();
             }
-            match self.read(i) {
+            match self.peek(i) {
                 Some(v) => write!(fmt, "{:2X}|", v),
                 None =>  write!(fmt, "XX|"),
             }.ok();
@@ -118,4 +198,9 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_seeded_memory_repeats() {
+        let memory = |seed| SimpleMemory::with_rng(&mut StdRng::seed_from_u64(seed)).contents();
+        assert_eq!(memory(3), memory(3));
+    }
 }
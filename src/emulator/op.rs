@@ -0,0 +1,414 @@
+use std::fmt;
+
+/// A sign-magnitude immediate `BITS` wide, the top bit being the sign.
+/// The raw bits are kept so that "negative zero" survives a round trip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Imm<const BITS: u32>(u32);
+
+/// The 12 bit immediate of the RI formats
+pub type Imm12 = Imm<12>;
+/// The 22 bit immediate of the jump and interrupt formats
+pub type Imm22 = Imm<22>;
+
+impl<const BITS: u32> Imm<BITS> {
+    const SIGN: u32 = 1 << (BITS - 1);
+    const MAGNITUDE: u32 = Self::SIGN - 1;
+
+    /// Keeps the lowest `BITS` bits of `raw`
+    pub const fn from_raw(raw: u32) -> Self {
+        Imm(raw & (Self::SIGN | Self::MAGNITUDE))
+    }
+
+    /// Returns None if `value` can't be held in `BITS - 1` bits plus a sign
+    pub fn from_value(value: i32) -> Option<Self> {
+        let magnitude = value.unsigned_abs();
+        if magnitude > Self::MAGNITUDE {
+            return None;
+        }
+        Some(Imm(if value < 0 { Self::SIGN | magnitude } else { magnitude }))
+    }
+
+    pub const fn raw(self) -> u32 {
+        self.0
+    }
+
+    pub const fn value(self) -> i32 {
+        let magnitude = (self.0 & Self::MAGNITUDE) as i32;
+        if self.0 & Self::SIGN == 0 {
+            magnitude
+        } else {
+            -magnitude
+        }
+    }
+}
+
+impl<const BITS: u32> fmt::Display for Imm<BITS> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.value())
+    }
+}
+
+/// A decoded opcode together with its operands.
+///
+/// Covers bits 27-0 of an instruction word, the condition flags in bits
+/// 31-28 live on [`Instruction`](super::Instruction). Every value of those
+/// 28 bits decodes to exactly one `Op` and encodes back to the same bits,
+/// which is why the formats with unused low bits keep them in `pad`.
+///
+/// Register formats
+/// RD: d 21-17 | x 16-12 | y 11-7 | pad 6-0
+/// RI: d 21-17 | x 16-12 | i 11-0
+/// BO: t 21-17 | base 16-12 | off 11-0
+/// BI: t 21-17 | base 16-12 | index 11-7 | pad 6-0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    // Logic
+    LslRd { d: u8, x: u8, y: u8, pad: u8 },
+    LslRi { d: u8, x: u8, i: Imm12 },
+    LsrRd { d: u8, x: u8, y: u8, pad: u8 },
+    LsrRi { d: u8, x: u8, i: Imm12 },
+    AndRd { d: u8, x: u8, y: u8, pad: u8 },
+    AndRi { d: u8, x: u8, i: Imm12 },
+    OrRd { d: u8, x: u8, y: u8, pad: u8 },
+    OrRi { d: u8, x: u8, i: Imm12 },
+    XorRd { d: u8, x: u8, y: u8, pad: u8 },
+    XorRi { d: u8, x: u8, i: Imm12 },
+    NotRd { d: u8, x: u8, y: u8, pad: u8 },
+    // Arithmetic
+    AddRd { d: u8, x: u8, y: u8, pad: u8 },
+    AddRi { d: u8, x: u8, i: Imm12 },
+    SubRd { d: u8, x: u8, y: u8, pad: u8 },
+    SubRi { d: u8, x: u8, i: Imm12 },
+    MulRd { d: u8, x: u8, y: u8, pad: u8 },
+    MulRi { d: u8, x: u8, i: Imm12 },
+    // Memory
+    Load8Bo { t: u8, base: u8, off: u16 },
+    Load8Bi { t: u8, base: u8, index: u8, pad: u8 },
+    Load16Bo { t: u8, base: u8, off: u16 },
+    Load16Bi { t: u8, base: u8, index: u8, pad: u8 },
+    Load32Bo { t: u8, base: u8, off: u16 },
+    Load32Bi { t: u8, base: u8, index: u8, pad: u8 },
+    Store8Bo { t: u8, base: u8, off: u16 },
+    Store8Bi { t: u8, base: u8, index: u8, pad: u8 },
+    Store16Bo { t: u8, base: u8, off: u16 },
+    Store16Bi { t: u8, base: u8, index: u8, pad: u8 },
+    Store32Bo { t: u8, base: u8, off: u16 },
+    Store32Bi { t: u8, base: u8, index: u8, pad: u8 },
+    // Flow control
+    JumpOffset { offset: Imm22 },
+    /// Bits 16-0 are unused
+    JumpRd { d: u8, pad: u32 },
+    JumpI { target: Imm22 },
+    Interrupt { i: Imm22 },
+    /// Opcodes 33-63, which the ISA leaves unassigned
+    Reserved { opcode: u8, operands: u32 },
+}
+
+// Field extraction, see the format table on Op
+const fn field(bits: u32, shift: u32, width: u32) -> u32 {
+    (bits >> shift) & ((1 << width) - 1)
+}
+
+const fn first(bits: u32) -> u8 {
+    field(bits, 17, 5) as u8
+}
+
+const fn second(bits: u32) -> u8 {
+    field(bits, 12, 5) as u8
+}
+
+const fn third(bits: u32) -> u8 {
+    field(bits, 7, 5) as u8
+}
+
+const fn fourth(bits: u32) -> u8 {
+    field(bits, 0, 7) as u8
+}
+
+const fn low12(bits: u32) -> u32 {
+    field(bits, 0, 12)
+}
+
+const fn rd(a: u8, b: u8, c: u8, pad: u8) -> u32 {
+    (a as u32 & 0x1F) << 17 | (b as u32 & 0x1F) << 12 | (c as u32 & 0x1F) << 7 | (pad as u32 & 0x7F)
+}
+
+const fn ri(a: u8, b: u8, i: u32) -> u32 {
+    (a as u32 & 0x1F) << 17 | (b as u32 & 0x1F) << 12 | (i & 0xFFF)
+}
+
+impl Op {
+    pub fn opcode(&self) -> u8 {
+        (u32::from(*self) >> 22) as u8
+    }
+
+    /// Human readable name, as shown by the disassembler
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::LslRd { .. } => "Left shift RD",
+            Op::LslRi { .. } => "Left shift RI",
+            Op::LsrRd { .. } => "Right shift RD",
+            Op::LsrRi { .. } => "Right shift RI",
+            Op::AndRd { .. } => "And RD",
+            Op::AndRi { .. } => "And RI",
+            Op::OrRd { .. } => "Or RD",
+            Op::OrRi { .. } => "Or RI",
+            Op::XorRd { .. } => "Xor RD",
+            Op::XorRi { .. } => "Xor RI",
+            Op::NotRd { .. } => "Not RD",
+            Op::AddRd { .. } => "Addition RD",
+            Op::AddRi { .. } => "Addition RI",
+            Op::SubRd { .. } => "Subtract RD",
+            Op::SubRi { .. } => "Subtract RI",
+            Op::MulRd { .. } => "Multiply RD",
+            Op::MulRi { .. } => "Multiply RI",
+            Op::Load8Bo { .. } => "Load 8bits BO",
+            Op::Load8Bi { .. } => "Load 8bits BI",
+            Op::Load16Bo { .. } => "Load 16bits BO",
+            Op::Load16Bi { .. } => "Load 16bits BI",
+            Op::Load32Bo { .. } => "Load 32bits BO",
+            Op::Load32Bi { .. } => "Load 32bits BI",
+            Op::Store8Bo { .. } => "Store 8bits BO",
+            Op::Store8Bi { .. } => "Store 8bits BI",
+            Op::Store16Bo { .. } => "Store 16bits BO",
+            Op::Store16Bi { .. } => "Store 16bits BI",
+            Op::Store32Bo { .. } => "Store 32bits BO",
+            Op::Store32Bi { .. } => "Store 32bits BI",
+            Op::JumpOffset { .. } => "Jump offset",
+            Op::JumpRd { .. } => "Jump to Rd",
+            Op::JumpI { .. } => "Jump to I",
+            Op::Interrupt { .. } => "Interupt",
+            Op::Reserved { .. } => "Reserved",
+        }
+    }
+
+    /// Registers the operation reads, the extension opcodes are unknown so
+    /// they read none
+    pub fn sources(&self) -> Vec<u8> {
+        match *self {
+            Op::NotRd { x, .. } => vec![x],
+            Op::LslRd { x, y, .. } | Op::LsrRd { x, y, .. } |
+            Op::AndRd { x, y, .. } | Op::OrRd { x, y, .. } |
+            Op::XorRd { x, y, .. } | Op::AddRd { x, y, .. } |
+            Op::SubRd { x, y, .. } | Op::MulRd { x, y, .. } => vec![x, y],
+            Op::LslRi { x, .. } | Op::LsrRi { x, .. } |
+            Op::AndRi { x, .. } | Op::OrRi { x, .. } |
+            Op::XorRi { x, .. } | Op::AddRi { x, .. } |
+            Op::SubRi { x, .. } | Op::MulRi { x, .. } => vec![x],
+            Op::Load8Bo { base, .. } | Op::Load16Bo { base, .. } |
+            Op::Load32Bo { base, .. } => vec![base],
+            Op::Load8Bi { base, index, .. } | Op::Load16Bi { base, index, .. } |
+            Op::Load32Bi { base, index, .. } => vec![base, index],
+            Op::Store8Bo { t, base, .. } => vec![base, t],
+            Op::Store16Bo { t, base, .. } => vec![base, t, t + 1],
+            Op::Store32Bo { t, base, .. } => vec![base, t, t + 1, t + 2, t + 3],
+            Op::Store8Bi { t, base, index, .. } => vec![base, index, t],
+            Op::Store16Bi { t, base, index, .. } => vec![base, index, t, t + 1],
+            Op::Store32Bi { t, base, index, .. } => vec![base, index, t, t + 1, t + 2, t + 3],
+            Op::JumpRd { d, .. } => vec![d],
+            Op::JumpOffset { .. } | Op::JumpI { .. } |
+            Op::Interrupt { .. } | Op::Reserved { .. } => vec![],
+        }
+    }
+
+    /// Registers the operation writes, as with sources extensions write none
+    pub fn destinations(&self) -> Vec<u8> {
+        match *self {
+            Op::LslRd { d, .. } | Op::LslRi { d, .. } | Op::LsrRd { d, .. } |
+            Op::LsrRi { d, .. } | Op::AndRd { d, .. } | Op::AndRi { d, .. } |
+            Op::OrRd { d, .. } | Op::OrRi { d, .. } | Op::XorRd { d, .. } |
+            Op::XorRi { d, .. } | Op::NotRd { d, .. } | Op::AddRd { d, .. } |
+            Op::AddRi { d, .. } | Op::SubRd { d, .. } | Op::SubRi { d, .. } |
+            Op::MulRd { d, .. } | Op::MulRi { d, .. } => vec![d],
+            Op::Load8Bo { t, .. } | Op::Load8Bi { t, .. } => vec![t],
+            Op::Load16Bo { t, .. } | Op::Load16Bi { t, .. } => vec![t, t + 1],
+            Op::Load32Bo { t, .. } | Op::Load32Bi { t, .. } => vec![t, t + 1, t + 2, t + 3],
+            _ => vec![],
+        }
+    }
+}
+
+impl From<u32> for Op {
+    /// Decodes bits 27-0, the condition flags in 31-28 are ignored
+    fn from(value: u32) -> Self {
+        let opcode = field(value, 22, 6) as u8;
+        let bits = field(value, 0, 22);
+        let (a, b, c, pad) = (first(bits), second(bits), third(bits), fourth(bits));
+        let imm12 = Imm12::from_raw(bits);
+        let off = low12(bits) as u16;
+        let imm22 = Imm22::from_raw(bits);
+        match opcode {
+            0 => Op::LslRd { d: a, x: b, y: c, pad },
+            1 => Op::LslRi { d: a, x: b, i: imm12 },
+            2 => Op::LsrRd { d: a, x: b, y: c, pad },
+            3 => Op::LsrRi { d: a, x: b, i: imm12 },
+            4 => Op::AndRd { d: a, x: b, y: c, pad },
+            5 => Op::AndRi { d: a, x: b, i: imm12 },
+            6 => Op::OrRd { d: a, x: b, y: c, pad },
+            7 => Op::OrRi { d: a, x: b, i: imm12 },
+            8 => Op::XorRd { d: a, x: b, y: c, pad },
+            9 => Op::XorRi { d: a, x: b, i: imm12 },
+            10 => Op::NotRd { d: a, x: b, y: c, pad },
+            11 => Op::AddRd { d: a, x: b, y: c, pad },
+            12 => Op::AddRi { d: a, x: b, i: imm12 },
+            13 => Op::SubRd { d: a, x: b, y: c, pad },
+            14 => Op::SubRi { d: a, x: b, i: imm12 },
+            15 => Op::MulRd { d: a, x: b, y: c, pad },
+            16 => Op::MulRi { d: a, x: b, i: imm12 },
+            17 => Op::Load8Bo { t: a, base: b, off },
+            18 => Op::Load8Bi { t: a, base: b, index: c, pad },
+            19 => Op::Load16Bo { t: a, base: b, off },
+            20 => Op::Load16Bi { t: a, base: b, index: c, pad },
+            21 => Op::Load32Bo { t: a, base: b, off },
+            22 => Op::Load32Bi { t: a, base: b, index: c, pad },
+            23 => Op::Store8Bo { t: a, base: b, off },
+            24 => Op::Store8Bi { t: a, base: b, index: c, pad },
+            25 => Op::Store16Bo { t: a, base: b, off },
+            26 => Op::Store16Bi { t: a, base: b, index: c, pad },
+            27 => Op::Store32Bo { t: a, base: b, off },
+            28 => Op::Store32Bi { t: a, base: b, index: c, pad },
+            29 => Op::JumpOffset { offset: imm22 },
+            30 => Op::JumpRd { d: a, pad: field(bits, 0, 17) },
+            31 => Op::JumpI { target: imm22 },
+            32 => Op::Interrupt { i: imm22 },
+            opcode => Op::Reserved { opcode, operands: bits },
+        }
+    }
+}
+
+impl From<Op> for u32 {
+    /// Encodes into bits 27-0, the condition flags are left clear
+    fn from(op: Op) -> Self {
+        let (opcode, operands) = match op {
+            Op::LslRd { d, x, y, pad } => (0, rd(d, x, y, pad)),
+            Op::LslRi { d, x, i } => (1, ri(d, x, i.raw())),
+            Op::LsrRd { d, x, y, pad } => (2, rd(d, x, y, pad)),
+            Op::LsrRi { d, x, i } => (3, ri(d, x, i.raw())),
+            Op::AndRd { d, x, y, pad } => (4, rd(d, x, y, pad)),
+            Op::AndRi { d, x, i } => (5, ri(d, x, i.raw())),
+            Op::OrRd { d, x, y, pad } => (6, rd(d, x, y, pad)),
+            Op::OrRi { d, x, i } => (7, ri(d, x, i.raw())),
+            Op::XorRd { d, x, y, pad } => (8, rd(d, x, y, pad)),
+            Op::XorRi { d, x, i } => (9, ri(d, x, i.raw())),
+            Op::NotRd { d, x, y, pad } => (10, rd(d, x, y, pad)),
+            Op::AddRd { d, x, y, pad } => (11, rd(d, x, y, pad)),
+            Op::AddRi { d, x, i } => (12, ri(d, x, i.raw())),
+            Op::SubRd { d, x, y, pad } => (13, rd(d, x, y, pad)),
+            Op::SubRi { d, x, i } => (14, ri(d, x, i.raw())),
+            Op::MulRd { d, x, y, pad } => (15, rd(d, x, y, pad)),
+            Op::MulRi { d, x, i } => (16, ri(d, x, i.raw())),
+            Op::Load8Bo { t, base, off } => (17, ri(t, base, off as u32)),
+            Op::Load8Bi { t, base, index, pad } => (18, rd(t, base, index, pad)),
+            Op::Load16Bo { t, base, off } => (19, ri(t, base, off as u32)),
+            Op::Load16Bi { t, base, index, pad } => (20, rd(t, base, index, pad)),
+            Op::Load32Bo { t, base, off } => (21, ri(t, base, off as u32)),
+            Op::Load32Bi { t, base, index, pad } => (22, rd(t, base, index, pad)),
+            Op::Store8Bo { t, base, off } => (23, ri(t, base, off as u32)),
+            Op::Store8Bi { t, base, index, pad } => (24, rd(t, base, index, pad)),
+            Op::Store16Bo { t, base, off } => (25, ri(t, base, off as u32)),
+            Op::Store16Bi { t, base, index, pad } => (26, rd(t, base, index, pad)),
+            Op::Store32Bo { t, base, off } => (27, ri(t, base, off as u32)),
+            Op::Store32Bi { t, base, index, pad } => (28, rd(t, base, index, pad)),
+            Op::JumpOffset { offset } => (29, offset.raw()),
+            Op::JumpRd { d, pad } => (30, (d as u32 & 0x1F) << 17 | (pad & 0x1FFFF)),
+            Op::JumpI { target } => (31, target.raw()),
+            Op::Interrupt { i } => (32, i.raw()),
+            Op::Reserved { opcode, operands } => (opcode as u32 & 0x3F, operands & 0x3FFFFF),
+        };
+        opcode << 22 | operands
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{:20}|", self.name())?;
+        match *self {
+            Op::LslRd { d, x, y, .. } | Op::LsrRd { d, x, y, .. } |
+            Op::AndRd { d, x, y, .. } | Op::OrRd { d, x, y, .. } |
+            Op::XorRd { d, x, y, .. } | Op::NotRd { d, x, y, .. } |
+            Op::AddRd { d, x, y, .. } | Op::SubRd { d, x, y, .. } |
+            Op::MulRd { d, x, y, .. }
+                => write!(fmt, "{:4}:{:4}:{:4}:    |", d, x, y),
+            Op::LslRi { d, x, i } | Op::LsrRi { d, x, i } |
+            Op::AndRi { d, x, i } | Op::OrRi { d, x, i } |
+            Op::XorRi { d, x, i } | Op::AddRi { d, x, i } |
+            Op::SubRi { d, x, i } | Op::MulRi { d, x, i }
+                => write!(fmt, "{:4}:{:4}:{:9}|", d, x, i),
+            Op::Load8Bo { t, base, off } | Op::Load16Bo { t, base, off } |
+            Op::Load32Bo { t, base, off } | Op::Store8Bo { t, base, off } |
+            Op::Store16Bo { t, base, off } | Op::Store32Bo { t, base, off }
+                => write!(fmt, "{:4}:{:4}:{:9}|", t, base, off),
+            Op::Load8Bi { t, base, index, .. } | Op::Load16Bi { t, base, index, .. } |
+            Op::Load32Bi { t, base, index, .. } | Op::Store8Bi { t, base, index, .. } |
+            Op::Store16Bi { t, base, index, .. } | Op::Store32Bi { t, base, index, .. }
+                => write!(fmt, "{:4}:{:4}:{:4}:    |", t, base, index),
+            Op::JumpOffset { offset: i } | Op::JumpI { target: i } | Op::Interrupt { i }
+                => write!(fmt, "{:19}|", i),
+            Op::JumpRd { d, .. } => write!(fmt, "{:19}|", d),
+            Op::Reserved { opcode, operands } => write!(fmt, "{:2}:{:16X}|", opcode, operands),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn round_trips_every_opcode() {
+        let mut rng = rand::thread_rng();
+        for opcode in 0..64_u32 {
+            for _ in 0..1000 {
+                let bits = opcode << 22 | (rng.gen::<u32>() & 0x3FFFFF);
+                let op = Op::from(bits);
+                assert_eq!(opcode as u8, op.opcode(), "{op:?}");
+                assert_eq!(bits, u32::from(op), "{op:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn ignores_condition_flags() {
+        assert_eq!(Op::from(0xF800_0ABC), Op::from(0x0800_0ABC));
+        assert_eq!(0x0800_0ABC, u32::from(Op::from(0xF800_0ABC)));
+    }
+
+    #[test]
+    fn decodes_add_rd() {
+        let bits = 11 << 22 | 0b00001_00010_00011_0000000;
+        assert_eq!(Op::AddRd { d: 1, x: 2, y: 3, pad: 0 }, Op::from(bits));
+    }
+
+    #[test]
+    fn decodes_load_8_bo() {
+        let bits = 17 << 22 | 0b00100_00101_111111111111;
+        assert_eq!(Op::Load8Bo { t: 4, base: 5, off: 0xFFF }, Op::from(bits));
+    }
+
+    #[test]
+    fn decodes_negative_jump() {
+        let bits = 31 << 22 | 0x200000 | 12;
+        assert_eq!(Op::from(bits), Op::JumpI { target: Imm22::from_value(-12).unwrap() });
+        match Op::from(bits) {
+            Op::JumpI { target } => assert_eq!(-12, target.value()),
+            op => panic!("Decoded as {op:?}"),
+        }
+    }
+
+    #[test]
+    fn imm_keeps_negative_zero() {
+        let zero = Imm12::from_raw(0x800);
+        assert_eq!(0, zero.value());
+        assert_ne!(Imm12::from_value(0).unwrap(), zero);
+        assert_eq!(0x800, zero.raw());
+    }
+
+    #[test]
+    fn imm_range() {
+        assert_eq!(Some(2047), Imm12::from_value(2047).map(Imm12::value));
+        assert_eq!(Some(-2047), Imm12::from_value(-2047).map(Imm12::value));
+        assert_eq!(None, Imm12::from_value(2048));
+        assert_eq!(None, Imm22::from_value(-0x200000));
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use crate::emulator::{Cpu, Flags, UnknownCpu};
+use crate::emulator::timing::Class;
+
+pub const STAGES: [&str; 5] = ["IF", "ID", "EX", "MEM", "WB"];
+
+/// Something an instruction reads that an earlier one may still be producing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resource {
+    Register(u8),
+    /// Read by any predicated instruction
+    Flags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hazard {
+    /// Read a resource before the instruction at `producer` wrote it back
+    Data { resource: Resource, producer: u32, stalls: u64, forwarded: bool },
+    /// A taken jump, resolved in EX, flushing the instructions fetched behind it
+    Control { target: u32, flushed: usize },
+}
+
+/// One instruction's trip through the pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub address: u32,
+    pub text: String,
+    /// Cycle it entered each stage it reached, flushed rows stop early
+    pub stages: Vec<u64>,
+    pub flushed: bool,
+    pub hazards: Vec<Hazard>,
+}
+
+impl Row {
+    /// Stage it was in during `cycle`, and whether it was stalled there
+    fn stage_at(&self, cycle: u64) -> Option<(usize, bool)> {
+        let stage = self.stages.iter().rposition(|&entered| entered <= cycle)?;
+        let leaves = match self.stages.get(stage + 1) {
+            Some(&next) => next,
+            None => self.stages[stage] + 1,
+        };
+        (cycle < leaves).then_some((stage, cycle != self.stages[stage]))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Producer {
+    address: u32,
+    /// First cycle a consumer can be in EX with forwarding
+    forwarded: u64,
+    /// Cycle it's in WB
+    written_back: u64,
+}
+
+/// Classic 5 stage pipeline model for teaching.
+///
+/// Instructions run through `Cpu::clock` so the architectural results are
+/// the same as without the model; the pipeline only works out when each
+/// one would have been in each stage. Jumps are predicted not taken and
+/// resolved in EX. With forwarding results go from EX, or MEM for loads,
+/// straight to the next EX, otherwise the register file is written in the
+/// first half of WB and read in the second half of ID.
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub forwarding: bool,
+    rows: Vec<Row>,
+    producers: HashMap<Resource, Producer>,
+    /// IF, ID and EX cycles of the last instruction that wasn't flushed
+    last: Option<[u64; 3]>,
+    fetch_at: u64,
+}
+
+impl Pipeline {
+    pub fn new(forwarding: bool) -> Self {
+        Pipeline {
+            forwarding,
+            rows: Vec::new(),
+            producers: HashMap::new(),
+            last: None,
+            fetch_at: 0,
+        }
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    /// Cycles until the last instruction left WB
+    pub fn cycles(&self) -> u64 {
+        self.rows.iter()
+            .filter_map(|row| row.stages.last().map(|&cycle| cycle + 1))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn stalls(&self) -> u64 {
+        self.rows.iter()
+            .flat_map(|row| &row.hazards)
+            .map(|hazard| match hazard {
+                Hazard::Data { stalls, .. } => *stalls,
+                Hazard::Control { .. } => 0,
+            })
+            .sum()
+    }
+
+    /// Clocks the cpu once, adding the instruction to the diagram
+    pub fn clock(&mut self, cpu: Cpu) -> UnknownCpu {
+        let address = cpu.program_counter;
+        let instruction = match cpu.fetch(address) {
+            Ok(instruction) => instruction,
+            // Faults in IF without getting any further
+            Err(_) => return cpu.clock(),
+        };
+        let op = cpu.profile.op(&instruction);
+        let ran = Flags::instruction_can_run(&cpu.flags, &instruction.flags);
+        let text = cpu.profile.disassemble(&instruction, &cpu.extensions).to_string();
+
+        let mut sources: Vec<Resource> = if ran {
+            op.sources().into_iter().filter(|&r| r != 0).map(Resource::Register).collect()
+        } else {
+            Vec::new()
+        };
+        if instruction.flags != Flags::new() {
+            sources.push(Resource::Flags);
+        }
+
+        let (fetch, decode, execute_earliest) = match self.last {
+            Some([_, id, ex]) => {
+                let fetch = id.max(self.fetch_at);
+                let decode = (fetch + 1).max(ex);
+                (fetch, decode, (decode + 1).max(ex + 1))
+            }
+            None => (self.fetch_at, self.fetch_at + 1, self.fetch_at + 2),
+        };
+        let mut execute = execute_earliest;
+        let mut hazards = Vec::new();
+        for resource in sources {
+            let Some(producer) = self.producers.get(&resource) else { continue };
+            // Already in the register file when read
+            if producer.written_back <= decode {
+                continue;
+            }
+            let ready = if self.forwarding { producer.forwarded } else { producer.written_back + 1 };
+            let stalls = ready.saturating_sub(execute_earliest);
+            execute = execute.max(ready);
+            hazards.push(Hazard::Data {
+                resource,
+                producer: producer.address,
+                stalls,
+                forwarded: self.forwarding,
+            });
+        }
+        let stages = vec![fetch, decode, execute, execute + 1, execute + 2];
+
+        let result = cpu.clock();
+        let cpu = result.cpu();
+
+        if ran && cpu.fault.is_none() {
+            let class = Class::of(&op);
+            let forwarded = match class {
+                Class::Load(_) => execute + 2,
+                _ => execute + 1,
+            };
+            let producer = Producer { address, forwarded, written_back: execute + 2 };
+            for register in op.destinations() {
+                self.producers.insert(Resource::Register(register), producer);
+            }
+            if matches!(class, Class::Alu | Class::Multiply) {
+                self.producers.insert(Resource::Flags, producer);
+            }
+        }
+        self.last = Some([fetch, decode, execute]);
+
+        let taken = ran && cpu.fault.is_none() && matches!(Class::of(&op), Class::Branch);
+        let mut flushed = Vec::new();
+        if taken {
+            // Fetched one a cycle behind the jump until it left EX
+            for (n, fetched) in (decode..=execute).enumerate() {
+                let address = address.wrapping_add(4 * (n as u32 + 1));
+                let text = match cpu.fetch(address) {
+                    Ok(instruction) => cpu.profile.disassemble(&instruction, &cpu.extensions).to_string(),
+                    Err(_) => String::from("?"),
+                };
+                let stages = if fetched < execute { vec![fetched, fetched + 1] } else { vec![fetched] };
+                flushed.push(Row { address, text, stages, flushed: true, hazards: Vec::new() });
+            }
+            hazards.push(Hazard::Control { target: cpu.program_counter, flushed: flushed.len() });
+            self.fetch_at = execute + 1;
+        }
+
+        self.rows.push(Row { address, text, stages, flushed: false, hazards });
+        self.rows.append(&mut flushed);
+        result
+    }
+
+    /// Runs up to `limit` instructions, adding a row for each
+    pub fn run(&mut self, cpu: Cpu, limit: usize) -> UnknownCpu {
+        cpu.run_with(limit, |cpu| self.clock(cpu))
+    }
+
+    /// Stage names for each cycle of each row, "--" for a stall
+    fn grid(&self) -> Vec<Vec<&'static str>> {
+        let cycles = self.cycles();
+        self.rows.iter()
+            .map(|row| (0..cycles)
+                .map(|cycle| match row.stage_at(cycle) {
+                    Some((_, true)) => "--",
+                    Some((stage, false)) => STAGES[stage],
+                    None => "",
+                })
+                .collect())
+            .collect()
+    }
+
+    fn label(row: &Row) -> String {
+        if row.flushed {
+            format!("{} (flushed)", row.text)
+        } else {
+            row.text.clone()
+        }
+    }
+
+    /// A row per instruction and a column per cycle, counting from 1
+    pub fn diagram(&self) -> String {
+        let labels: Vec<String> = self.rows.iter().map(Pipeline::label).collect();
+        let width = labels.iter().map(String::len).max().unwrap_or(0);
+        let mut out = format!("{:>8} {:width$}", "address", "");
+        for cycle in 1..=self.cycles() {
+            write!(out, " {cycle:<3}").unwrap();
+        }
+        out.push('\n');
+        for ((row, label), cells) in self.rows.iter().zip(&labels).zip(self.grid()) {
+            write!(out, "{:>8} {label:width$}", row.address).unwrap();
+            for cell in cells {
+                write!(out, " {cell:<3}").unwrap();
+            }
+            out.truncate(out.trim_end().len());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Same layout as the diagram
+    pub fn csv(&self) -> String {
+        let mut out = String::from("address,instruction");
+        for cycle in 1..=self.cycles() {
+            write!(out, ",{cycle}").unwrap();
+        }
+        out.push('\n');
+        for (row, cells) in self.rows.iter().zip(self.grid()) {
+            write!(out, "{},\"{}\"", row.address, Pipeline::label(row).replace('"', "\"\"")).unwrap();
+            for cell in cells {
+                write!(out, ",{cell}").unwrap();
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Instruction, Op, Snapshot};
+    use crate::emulator::op::{Imm12, Imm22};
+
+    fn cpu_with(program: &[Op]) -> Cpu {
+        let mut cpu = Cpu::new_blank();
+        for (address, op) in (0..).step_by(4).zip(program) {
+            cpu.load_instruction(address, &Instruction::new(Flags::new(), *op)).unwrap();
+        }
+        cpu
+    }
+
+    fn add(d: u8, x: u8, i: i32) -> Op {
+        Op::AddRi { d, x, i: Imm12::from_value(i).unwrap() }
+    }
+
+    fn data_stalls(row: &Row) -> u64 {
+        row.hazards.iter()
+            .map(|hazard| match hazard {
+                Hazard::Data { stalls, .. } => *stalls,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn independent_instructions_dont_stall() {
+        let mut pipeline = Pipeline::new(true);
+        pipeline.run(cpu_with(&[add(1, 0, 1), add(2, 0, 2), add(3, 0, 3)]), 3).into_cpu();
+        let rows = pipeline.rows();
+        assert_eq!(vec![0, 1, 2, 3, 4], rows[0].stages);
+        assert_eq!(vec![2, 3, 4, 5, 6], rows[2].stages);
+        assert_eq!(7, pipeline.cycles());
+        assert_eq!(0, pipeline.stalls());
+    }
+
+    #[test]
+    fn forwarding_removes_alu_stalls() {
+        let program = [add(1, 0, 1), add(2, 1, 1)];
+        let mut pipeline = Pipeline::new(true);
+        pipeline.run(cpu_with(&program), 2).into_cpu();
+        let row = &pipeline.rows()[1];
+        assert_eq!(vec![Hazard::Data { resource: Resource::Register(1), producer: 0, stalls: 0, forwarded: true }], row.hazards);
+        assert_eq!(vec![1, 2, 3, 4, 5], row.stages);
+
+        let mut pipeline = Pipeline::new(false);
+        pipeline.run(cpu_with(&program), 2).into_cpu();
+        let row = &pipeline.rows()[1];
+        // Reads r1 in ID during the add's WB
+        assert_eq!(2, data_stalls(row));
+        assert_eq!(vec![1, 2, 5, 6, 7], row.stages);
+    }
+
+    #[test]
+    fn load_use_stalls_with_forwarding() {
+        let mut pipeline = Pipeline::new(true);
+        pipeline.run(cpu_with(&[Op::Load8Bo { t: 1, base: 0, off: 40 }, add(2, 1, 1), add(3, 0, 1)]), 3).into_cpu();
+        let rows = pipeline.rows();
+        assert_eq!(1, data_stalls(&rows[1]));
+        assert_eq!(vec![1, 2, 4, 5, 6], rows[1].stages);
+        // Held in IF behind the stall
+        assert_eq!(vec![2, 4, 5, 6, 7], rows[2].stages);
+    }
+
+    #[test]
+    fn taken_jump_flushes() {
+        let program = [Op::JumpI { target: Imm22::from_value(12).unwrap() }, add(1, 0, 1), add(2, 0, 1), add(3, 0, 1)];
+        let mut pipeline = Pipeline::new(true);
+        let cpu = pipeline.run(cpu_with(&program), 2).into_cpu();
+        assert_eq!(0, cpu.read(1));
+        assert_eq!(1, cpu.read(3));
+
+        let rows = pipeline.rows();
+        assert_eq!(vec![Hazard::Control { target: 12, flushed: 2 }], rows[0].hazards);
+        assert!(rows[1].flushed && rows[2].flushed);
+        assert_eq!(vec![1, 2], rows[1].stages);
+        assert_eq!(vec![2], rows[2].stages);
+        assert_eq!(12, rows[3].address);
+        assert_eq!(vec![3, 4, 5, 6, 7], rows[3].stages);
+    }
+
+    #[test]
+    fn same_results_as_clock() {
+        let program = [
+            add(1, 0, 5),
+            Op::Store8Bo { t: 1, base: 0, off: 60 },
+            Op::Load8Bo { t: 2, base: 0, off: 60 },
+            Op::AddRd { d: 3, x: 3, y: 2, pad: 0 },
+            Op::SubRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() },
+            Op::JumpI { target: Imm22::from_value(24).unwrap() },
+            Op::JumpI { target: Imm22::from_value(12).unwrap() },
+        ];
+        let mut cpu = cpu_with(&program);
+        // Loops back to the sub until r1 is zero
+        let mut until_zero = Flags::new();
+        until_zero.zero = true;
+        cpu.load_instruction(20, &Instruction::new(until_zero, Op::JumpI { target: Imm22::from_value(28).unwrap() })).unwrap();
+        cpu.load_instruction(28, &Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_value(28).unwrap() })).unwrap();
+
+        let mut plain = cpu_with(&[]);
+        plain.restore(&Snapshot::take(&cpu)).unwrap();
+        let mut pipeline = Pipeline::new(true);
+        let piped = pipeline.run(cpu, 30).into_cpu();
+        for _ in 0..30 {
+            plain = plain.clock().into_cpu();
+        }
+        assert_eq!(Snapshot::take(&plain), Snapshot::take(&piped));
+        assert!(pipeline.cycles() > 30);
+    }
+
+    #[test]
+    fn diagram_and_csv() {
+        let mut pipeline = Pipeline::new(true);
+        pipeline.run(cpu_with(&[Op::Load8Bo { t: 1, base: 0, off: 40 }, add(2, 1, 1)]), 2).into_cpu();
+        let diagram = pipeline.diagram();
+        let lines: Vec<&str> = diagram.lines().collect();
+        assert_eq!(3, lines.len());
+        assert!(lines[0].trim_start().starts_with("address"));
+        assert!(lines[2].ends_with("IF  ID  --  EX  MEM WB"), "{}", lines[2]);
+
+        let csv = pipeline.csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!("address,instruction,1,2,3,4,5,6,7", lines[0]);
+        assert!(lines[2].ends_with(",,IF,ID,--,EX,MEM,WB"), "{}", lines[2]);
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::emulator::{Cpu, Flags, UnknownCpu};
+use crate::emulator::timing::Class;
+
+/// Guesses whether a predicated instruction or jump will run before its
+/// flags are known
+pub trait Predictor {
+    fn name(&self) -> &'static str;
+    fn predict(&self, address: u32) -> bool;
+    /// Told what really happened after each prediction
+    fn update(&mut self, address: u32, ran: bool);
+}
+
+/// Always guesses the same way
+#[derive(Debug, Clone)]
+pub struct Static {
+    pub ran: bool,
+}
+
+impl Predictor for Static {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    fn predict(&self, _address: u32) -> bool {
+        self.ran
+    }
+
+    fn update(&mut self, _address: u32, _ran: bool) {}
+}
+
+/// The most index bits a table can have, at 64k entries
+pub const MAX_BITS: u32 = 16;
+
+fn table<T: Clone>(bits: u32, entry: T) -> Result<Vec<T>, &'static str> {
+    if bits > MAX_BITS {
+        return Err("Predictor tables are at most 16 bits");
+    }
+    Ok(vec![entry; 1 << bits])
+}
+
+/// Instructions are a word apart, so the bottom two address bits are dropped
+fn index(address: u32, bits: u32) -> usize {
+    ((address >> 2) & ((1 << bits) - 1)) as usize
+}
+
+/// Guesses whatever happened last time, per table entry
+#[derive(Debug, Clone)]
+pub struct OneBit {
+    bits: u32,
+    table: Vec<bool>,
+}
+
+impl OneBit {
+    /// A table of 2^bits entries indexed by address
+    pub fn new(bits: u32) -> Result<Self, &'static str> {
+        Ok(OneBit { bits, table: table(bits, true)? })
+    }
+}
+
+impl Predictor for OneBit {
+    fn name(&self) -> &'static str {
+        "1-bit"
+    }
+
+    fn predict(&self, address: u32) -> bool {
+        self.table[index(address, self.bits)]
+    }
+
+    fn update(&mut self, address: u32, ran: bool) {
+        self.table[index(address, self.bits)] = ran;
+    }
+}
+
+/// 0 and 1 predict skipped, 2 and 3 predict ran
+fn counter_predicts(counter: u8) -> bool {
+    counter >= 2
+}
+
+fn count(counter: &mut u8, ran: bool) {
+    *counter = if ran { (*counter + 1).min(3) } else { counter.saturating_sub(1) };
+}
+
+/// Saturating two bit counters, so one surprise doesn't flip the guess
+#[derive(Debug, Clone)]
+pub struct TwoBit {
+    bits: u32,
+    table: Vec<u8>,
+}
+
+impl TwoBit {
+    /// A table of 2^bits counters indexed by address, starting weakly ran
+    pub fn new(bits: u32) -> Result<Self, &'static str> {
+        Ok(TwoBit { bits, table: table(bits, 2)? })
+    }
+}
+
+impl Predictor for TwoBit {
+    fn name(&self) -> &'static str {
+        "2-bit"
+    }
+
+    fn predict(&self, address: u32) -> bool {
+        counter_predicts(self.table[index(address, self.bits)])
+    }
+
+    fn update(&mut self, address: u32, ran: bool) {
+        count(&mut self.table[index(address, self.bits)], ran);
+    }
+}
+
+/// Two bit counters indexed by the address xored with whether each of the
+/// last `bits` branches ran
+#[derive(Debug, Clone)]
+pub struct Gshare {
+    bits: u32,
+    history: u32,
+    table: Vec<u8>,
+}
+
+impl Gshare {
+    pub fn new(bits: u32) -> Result<Self, &'static str> {
+        Ok(Gshare { bits, history: 0, table: table(bits, 2)? })
+    }
+
+    fn slot(&self, address: u32) -> usize {
+        index(address, self.bits) ^ index(self.history << 2, self.bits)
+    }
+}
+
+impl Predictor for Gshare {
+    fn name(&self) -> &'static str {
+        "gshare"
+    }
+
+    fn predict(&self, address: u32) -> bool {
+        counter_predicts(self.table[self.slot(address)])
+    }
+
+    fn update(&mut self, address: u32, ran: bool) {
+        let slot = self.slot(address);
+        count(&mut self.table[slot], ran);
+        self.history = (self.history << 1) | ran as u32;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Branch {
+    pub address: u32,
+    pub ran: bool,
+}
+
+/// The predicated instructions and jumps a program ran into, in order, so
+/// every predictor can be tried on the same run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub branches: Vec<Branch>,
+}
+
+impl Trace {
+    /// Runs up to `limit` instructions, keeping the branches
+    pub fn record(cpu: Cpu, limit: usize) -> (UnknownCpu, Trace) {
+        let mut trace = Trace::default();
+        let cpu = cpu.run_with(limit, |cpu| {
+            trace.observe(&cpu);
+            cpu.clock()
+        });
+        (cpu, trace)
+    }
+
+    /// Records the instruction the cpu is about to run, if it's a branch
+    pub fn observe(&mut self, cpu: &Cpu) {
+        let Ok(instruction) = cpu.fetch(cpu.program_counter) else { return };
+        let jump = matches!(Class::of(&cpu.profile.op(&instruction)), Class::Branch);
+        if jump || instruction.flags != Flags::new() {
+            self.branches.push(Branch {
+                address: cpu.program_counter,
+                ran: Flags::instruction_can_run(&cpu.flags, &instruction.flags),
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Accuracy {
+    pub predictions: u64,
+    pub correct: u64,
+}
+
+impl Accuracy {
+    pub fn rate(&self) -> f64 {
+        match self.predictions {
+            0 => 0.0,
+            predictions => self.correct as f64 / predictions as f64,
+        }
+    }
+
+    fn add(&mut self, correct: bool) {
+        self.predictions += 1;
+        self.correct += correct as u64;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub predictor: &'static str,
+    pub total: Accuracy,
+    pub addresses: BTreeMap<u32, Accuracy>,
+}
+
+/// Runs a predictor over a trace
+pub fn simulate(predictor: &mut dyn Predictor, trace: &Trace) -> Report {
+    let mut report = Report {
+        predictor: predictor.name(),
+        total: Accuracy::default(),
+        addresses: BTreeMap::new(),
+    };
+    for branch in &trace.branches {
+        let correct = predictor.predict(branch.address) == branch.ran;
+        predictor.update(branch.address, branch.ran);
+        report.total.add(correct);
+        report.addresses.entry(branch.address).or_default().add(correct);
+    }
+    report
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(fmt, "{}: {}/{} correct ({:.1}%)", self.predictor,
+            self.total.correct, self.total.predictions, self.total.rate() * 100.0)?;
+        for (address, accuracy) in &self.addresses {
+            writeln!(fmt, "{:>8}: {}/{} ({:.1}%)", address,
+                accuracy.correct, accuracy.predictions, accuracy.rate() * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Instruction, Op};
+    use crate::emulator::op::{Imm12, Imm22};
+
+    /// `iterations` loops of a branch that runs `length` times then is skipped
+    fn loops(length: usize, iterations: usize) -> Trace {
+        let pattern = (0..length).map(|_| true).chain([false]);
+        let branches = pattern.cycle().take((length + 1) * iterations)
+            .map(|ran| Branch { address: 12, ran })
+            .collect();
+        Trace { branches }
+    }
+
+    #[test]
+    fn two_bit_misses_once_per_loop() {
+        let trace = loops(9, 10);
+        assert_eq!(10, simulate(&mut Static { ran: false }, &trace).total.correct);
+        let one_bit = simulate(&mut OneBit::new(4).unwrap(), &trace);
+        let two_bit = simulate(&mut TwoBit::new(4).unwrap(), &trace);
+        // The 1-bit misses the exit and the first of the next loop
+        assert_eq!(100 - 19, one_bit.total.correct);
+        assert_eq!(100 - 10, two_bit.total.correct);
+    }
+
+    #[test]
+    fn gshare_learns_alternation() {
+        let branches = (0..200).map(|n| Branch { address: 8, ran: n % 2 == 0 }).collect();
+        let trace = Trace { branches };
+        let one_bit = simulate(&mut OneBit::new(4).unwrap(), &trace);
+        let gshare = simulate(&mut Gshare::new(4).unwrap(), &trace);
+        assert_eq!(1, one_bit.total.correct);
+        assert!(gshare.total.rate() > 0.95, "{}", gshare);
+    }
+
+    #[test]
+    fn rejects_huge_tables() {
+        assert!(OneBit::new(MAX_BITS).is_ok());
+        assert!(OneBit::new(MAX_BITS + 1).is_err());
+        assert!(TwoBit::new(32).is_err());
+        assert!(Gshare::new(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn reports_per_address() {
+        let trace = Trace { branches: vec![
+            Branch { address: 0, ran: true },
+            Branch { address: 4, ran: false },
+            Branch { address: 0, ran: true },
+        ] };
+        let report = simulate(&mut Static { ran: true }, &trace);
+        assert_eq!(Accuracy { predictions: 2, correct: 2 }, report.addresses[&0]);
+        assert_eq!(Accuracy { predictions: 1, correct: 0 }, report.addresses[&4]);
+        assert_eq!("static: 2/3 correct (66.7%)", report.to_string().lines().next().unwrap());
+    }
+
+    #[test]
+    fn records_a_program() {
+        // Counts r1 down from 3, jumping back while it isn't zero
+        let mut cpu = Cpu::new_blank();
+        cpu.write(1, 3);
+        let mut not_zero = Flags::new();
+        not_zero.greater = true;
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::SubRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() })).unwrap();
+        cpu.load_instruction(4, &Instruction::new(not_zero, Op::JumpI { target: Imm22::from_value(0).unwrap() })).unwrap();
+        cpu.load_instruction(8, &Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_value(8).unwrap() })).unwrap();
+
+        let (_, trace) = Trace::record(cpu, 7);
+        let ran: Vec<(u32, bool)> = trace.branches.iter().map(|branch| (branch.address, branch.ran)).collect();
+        assert_eq!(vec![(4, true), (4, true), (4, false), (8, true)], ran);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::emulator::flags::Flags;
+use crate::emulator::instruction::Instruction;
+use crate::emulator::op::Op;
+use crate::emulator::extension::{Disassembly, Extensions};
+
+/// How the ALU sets the greater and less flags from its result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagSemantics {
+    /// Results are unsigned, so greater is set for any non zero result and
+    /// less is never set
+    Unsigned,
+    /// Results are two's complement, greater and less follow the sign bit
+    Signed,
+}
+
+impl FlagSemantics {
+    pub fn update(self, flags: &mut Flags, result: u8, carry: bool) {
+        flags.carry = carry;
+        flags.zero = result == 0;
+        match self {
+            FlagSemantics::Unsigned => {
+                flags.greater = result > 0;
+            }
+            FlagSemantics::Signed => {
+                flags.greater = (result as i8) > 0;
+                flags.less = (result as i8) < 0;
+            }
+        }
+    }
+}
+
+/// The parts of the ISA that change between revisions of the spec,
+/// picked when a cpu is created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IsaProfile {
+    /// Spec version the profile implements
+    pub version: &'static str,
+    /// Maps each opcode of this revision to the v0.1.0 opcode of the same
+    /// instruction, which is the numbering [`Op`] uses. None leaves the
+    /// opcode free for extensions.
+    pub opcodes: [Option<u8>; 64],
+    pub flags: FlagSemantics,
+    /// Number of general purpose registers, numbered from 1. Register 0 is
+    /// always zero. At most 30 as registers are addressed with 5 bits and
+    /// the one above the general purpose registers is reserved.
+    pub general_purpose: u8,
+}
+
+impl Default for IsaProfile {
+    fn default() -> Self {
+        IsaProfile::v0_1_0()
+    }
+}
+
+impl IsaProfile {
+    /// The current draft, the profile this emulator was written against
+    pub fn v0_1_0() -> Self {
+        let mut opcodes = [None; 64];
+        for (opcode, canonical) in opcodes.iter_mut().zip(0..=32) {
+            *opcode = Some(canonical);
+        }
+        IsaProfile {
+            version: "0.1.0",
+            opcodes,
+            flags: FlagSemantics::Unsigned,
+            general_purpose: 29,
+        }
+    }
+
+    /// Fails if a cpu can't run the profile, as when it has more general
+    /// purpose registers than can be addressed
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.general_purpose > 30 {
+            return Err("Too many general purpose registers");
+        }
+        Ok(())
+    }
+
+    /// Whether the revision leaves `opcode` free for extensions
+    pub fn is_free(&self, opcode: u8) -> bool {
+        self.opcodes.get(opcode as usize).is_some_and(Option::is_none)
+    }
+
+    /// Decodes the operation of an instruction under this revision
+    pub fn op(&self, instruction: &Instruction) -> Op {
+        let operands = instruction.operands();
+        match self.opcodes[instruction.opcode as usize & 0x3F] {
+            Some(canonical) => Op::from((canonical as u32) << 22 | operands),
+            None => Op::Reserved { opcode: instruction.opcode & 0x3F, operands },
+        }
+    }
+
+    /// Displays the instruction as decoded by this revision
+    pub fn disassemble<'a>(&self, instruction: &'a Instruction, extensions: &'a Extensions) -> Disassembly<'a> {
+        Disassembly { instruction, op: self.op(instruction), extensions }
+    }
+
+    /// Encodes an operation under this revision, None if the revision
+    /// doesn't have the instruction
+    pub fn instruction(&self, flags: Flags, op: Op) -> Option<Instruction> {
+        let mut instruction = Instruction::new(flags, op);
+        if let Op::Reserved { .. } = op {
+            return self.opcodes[instruction.opcode as usize].is_none().then_some(instruction);
+        }
+        let opcode = self.opcodes.iter().position(|canonical| *canonical == Some(instruction.opcode))?;
+        instruction.opcode = opcode as u8;
+        Some(instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Cpu, UnknownCpu};
+
+    /// A made up revision with add RD moved to opcode 40 and signed flags
+    fn moved_add() -> IsaProfile {
+        let mut profile = IsaProfile { version: "test", flags: FlagSemantics::Signed, ..IsaProfile::v0_1_0() };
+        profile.opcodes[11] = None;
+        profile.opcodes[40] = Some(11);
+        profile
+    }
+
+    #[test]
+    fn v0_1_0_uses_op_numbering() {
+        let profile = IsaProfile::v0_1_0();
+        for opcode in 0..64 {
+            let instruction = Instruction::from_opcode(opcode);
+            assert_eq!(instruction.op(), profile.op(&instruction));
+        }
+    }
+
+    #[test]
+    fn remaps_opcodes() {
+        let profile = moved_add();
+        let add = Op::AddRd { d: 1, x: 2, y: 3, pad: 0 };
+        let instruction = profile.instruction(Flags::new(), add).unwrap();
+        assert_eq!(40, instruction.opcode);
+        assert_eq!(add, profile.op(&instruction));
+        assert_eq!(Op::Reserved { opcode: 11, operands: 0 }, profile.op(&Instruction::from_opcode(11)));
+        assert!(profile.instruction(Flags::new(), Op::Reserved { opcode: 40, operands: 0 }).is_none());
+    }
+
+    #[test]
+    fn signed_flags() {
+        let mut flags = Flags::new();
+        FlagSemantics::Signed.update(&mut flags, 0xFE, false);
+        assert!(flags.less);
+        assert!(!flags.greater);
+        FlagSemantics::Unsigned.update(&mut flags, 0xFE, false);
+        assert!(flags.greater);
+    }
+
+    #[test]
+    fn runs_profiles_side_by_side() {
+        let add = Op::AddRd { d: 1, x: 2, y: 3, pad: 0 };
+        for profile in [IsaProfile::v0_1_0(), moved_add()] {
+            let mut cpu = Cpu::with_profile(profile.clone()).unwrap();
+            cpu.write(2, 0x80);
+            cpu.write(3, 0x01);
+            cpu.load_instruction(0, &profile.instruction(Flags::new(), add).unwrap()).unwrap();
+            cpu = match cpu.clock() {
+                UnknownCpu::Ok(cpu) => cpu,
+                UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
+            };
+            assert_eq!(0x81, cpu.read(1), "Profile {}", profile.version);
+        }
+    }
+
+    #[test]
+    fn register_count() {
+        let profile = IsaProfile { general_purpose: 8, ..IsaProfile::v0_1_0() };
+        let mut cpu = Cpu::with_profile(profile).unwrap();
+        cpu.write(8, 1);
+        cpu.write(9, 1);
+        assert_eq!(1, cpu.read(8));
+        assert_eq!(0, cpu.read(9));
+        let profile = IsaProfile { general_purpose: 31, ..IsaProfile::v0_1_0() };
+        assert!(Cpu::with_profile(profile).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::AddAssign;
+use crate::emulator::{Cpu, Flags, UnknownCpu};
+use crate::emulator::timing::Class;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub executed: u64,
+    /// Times its flags skipped it
+    pub skipped: u64,
+    pub cycles: u64,
+}
+
+impl AddAssign for Counts {
+    fn add_assign(&mut self, other: Counts) {
+        self.executed += other.executed;
+        self.skipped += other.skipped;
+        self.cycles += other.cycles;
+    }
+}
+
+/// Counts what ran where while clocking a cpu.
+///
+/// Blocks are found as the program runs: one starts wherever the program
+/// counter lands after a jump and ends at the next jump, so a block
+/// entered part way through is counted separately. The ISA has no call or
+/// return yet, so there's no call graph and the collapsed stacks are just
+/// label then address.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    addresses: BTreeMap<u32, Counts>,
+    /// (first, last) address of each block and how often it ran
+    blocks: BTreeMap<(u32, u32), u64>,
+    labels: BTreeMap<u32, String>,
+    block_start: Option<u32>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// Names addresses, each counting towards the closest label at or
+    /// before it
+    pub fn with_labels(labels: impl IntoIterator<Item = (String, u32)>) -> Self {
+        Profiler {
+            labels: labels.into_iter().map(|(label, address)| (address, label)).collect(),
+            ..Profiler::default()
+        }
+    }
+
+    pub fn counts(&self, address: u32) -> Option<&Counts> {
+        self.addresses.get(&address)
+    }
+
+    pub fn label(&self, address: u32) -> Option<&str> {
+        self.labels.range(..=address).next_back().map(|(_, label)| label.as_str())
+    }
+
+    /// Clocks the cpu once, counting the instruction it runs
+    pub fn clock(&mut self, cpu: Cpu) -> UnknownCpu {
+        let address = cpu.program_counter;
+        let Ok(instruction) = cpu.fetch(address) else { return cpu.clock() };
+        let ran = Flags::instruction_can_run(&cpu.flags, &instruction.flags);
+        let jump = matches!(Class::of(&cpu.profile.op(&instruction)), Class::Branch);
+        let cycles = cpu.cycles;
+
+        let result = cpu.clock();
+        let cpu = result.cpu();
+        let counts = self.addresses.entry(address).or_default();
+        if ran {
+            counts.executed += 1;
+        } else {
+            counts.skipped += 1;
+        }
+        counts.cycles += cpu.cycles - cycles;
+
+        let start = *self.block_start.get_or_insert(address);
+        if jump || cpu.program_counter != address.wrapping_add(4) {
+            *self.blocks.entry((start, address)).or_default() += 1;
+            self.block_start = None;
+        }
+        result
+    }
+
+    /// Runs up to `limit` instructions, counting each
+    pub fn run(&mut self, cpu: Cpu, limit: usize) -> UnknownCpu {
+        cpu.run_with(limit, |cpu| self.clock(cpu))
+    }
+
+    /// Counts summed over each label, addresses before the first label
+    /// are left out
+    pub fn by_label(&self) -> BTreeMap<&str, Counts> {
+        let mut labels = BTreeMap::new();
+        for (&address, &counts) in &self.addresses {
+            if let Some(label) = self.label(address) {
+                *labels.entry(label).or_default() += counts;
+            }
+        }
+        labels
+    }
+
+    /// The `n` addresses that took the most cycles, most first
+    pub fn hotspots(&self, n: usize) -> Vec<(u32, Counts)> {
+        let mut hotspots: Vec<(u32, Counts)> = self.addresses.iter()
+            .map(|(&address, &counts)| (address, counts))
+            .collect();
+        hotspots.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        hotspots.truncate(n);
+        hotspots
+    }
+
+    /// (first, last) address of each block and how often it ran, most
+    /// often first
+    pub fn blocks(&self) -> Vec<((u32, u32), u64)> {
+        let mut blocks: Vec<((u32, u32), u64)> = self.blocks.iter()
+            .map(|(&block, &count)| (block, count))
+            .collect();
+        blocks.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        blocks
+    }
+
+    /// Hotspots, labels and blocks as text
+    pub fn report(&self, top: usize) -> String {
+        let mut out = String::new();
+        let total: u64 = self.addresses.values().map(|counts| counts.cycles).sum();
+        writeln!(out, "{total} cycles").unwrap();
+        writeln!(out, "\nHotspots\n{:>8} {:>8} {:>8} {:>8} {:>6}  label", "address", "cycles", "executed", "skipped", "%").unwrap();
+        for (address, counts) in self.hotspots(top) {
+            writeln!(out, "{:>8} {:>8} {:>8} {:>8} {:>6.1}  {}", address, counts.cycles, counts.executed,
+                counts.skipped, percent(counts.cycles, total), self.label(address).unwrap_or("")).unwrap();
+        }
+        if !self.labels.is_empty() {
+            writeln!(out, "\nLabels\n{:>8} {:>8} {:>8}  label", "cycles", "executed", "skipped").unwrap();
+            for (label, counts) in self.by_label() {
+                writeln!(out, "{:>8} {:>8} {:>8}  {}", counts.cycles, counts.executed, counts.skipped, label).unwrap();
+            }
+        }
+        writeln!(out, "\nBlocks\n{:>8} {:>8} {:>8}", "first", "last", "count").unwrap();
+        for ((first, last), count) in self.blocks().into_iter().take(top) {
+            writeln!(out, "{first:>8} {last:>8} {count:>8}").unwrap();
+        }
+        out
+    }
+
+    /// A `frames cycles` line per address, as read by flamegraph tools
+    pub fn collapsed(&self) -> String {
+        let mut out = String::new();
+        for (&address, counts) in &self.addresses {
+            if counts.cycles == 0 {
+                continue;
+            }
+            match self.label(address) {
+                Some(label) => writeln!(out, "{label};{address} {}", counts.cycles),
+                None => writeln!(out, "{address} {}", counts.cycles),
+            }.unwrap();
+        }
+        out
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        total => part as f64 * 100.0 / total as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{CostTable, Instruction, Op};
+    use crate::emulator::op::{Imm12, Imm22};
+
+    /// Counts r1 down from 3 then spins at 12
+    fn countdown() -> Cpu {
+        let mut cpu = Cpu::new_blank();
+        cpu.timing = CostTable::uniform();
+        let mut not_zero = Flags::new();
+        not_zero.greater = true;
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::AddRi { d: 1, x: 0, i: Imm12::from_value(3).unwrap() })).unwrap();
+        cpu.load_instruction(4, &Instruction::new(Flags::new(), Op::SubRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() })).unwrap();
+        cpu.load_instruction(8, &Instruction::new(not_zero, Op::JumpI { target: Imm22::from_value(4).unwrap() })).unwrap();
+        cpu.load_instruction(12, &Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_value(12).unwrap() })).unwrap();
+        cpu
+    }
+
+    fn profiled() -> Profiler {
+        let mut profiler = Profiler::with_labels([(String::from("start"), 0), (String::from("loop"), 4), (String::from("end"), 12)]);
+        profiler.run(countdown(), 9).into_cpu();
+        profiler
+    }
+
+    #[test]
+    fn counts_each_address() {
+        let profiler = profiled();
+        assert_eq!(Some(&Counts { executed: 3, skipped: 0, cycles: 3 }), profiler.counts(4));
+        assert_eq!(Some(&Counts { executed: 2, skipped: 1, cycles: 3 }), profiler.counts(8));
+        assert_eq!(Some(&Counts { executed: 2, skipped: 0, cycles: 2 }), profiler.counts(12));
+        assert_eq!(None, profiler.counts(16));
+        assert_eq!(vec![4, 8], profiler.hotspots(2).iter().map(|(address, _)| *address).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn counts_each_label() {
+        let profiler = profiled();
+        assert_eq!(Some("loop"), profiler.label(8));
+        let labels = profiler.by_label();
+        assert_eq!(6, labels["loop"].cycles);
+        assert_eq!(1, labels["loop"].skipped);
+        assert_eq!(1, labels["start"].executed);
+    }
+
+    #[test]
+    fn finds_blocks() {
+        let profiler = profiled();
+        assert_eq!(vec![((4, 8), 2), ((12, 12), 2), ((0, 8), 1)], profiler.blocks());
+    }
+
+    #[test]
+    fn collapsed_stacks() {
+        let profiler = profiled();
+        assert_eq!("start;0 1\nloop;4 3\nloop;8 3\nend;12 2\n", profiler.collapsed());
+        assert!(profiler.report(3).starts_with("9 cycles\n"));
+    }
+}
@@ -0,0 +1,296 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::emulator::Cpu;
+use crate::emulator::flags::Flags;
+
+/// Format version written at the top of every snapshot
+pub const SNAPSHOT_VERSION: u32 = 1;
+const HEADER: &str = "ETD32 SNAPSHOT";
+const BYTES_PER_LINE: usize = 16;
+
+/// The architectural state of a cpu, frozen so it can be saved to a file
+/// and restored later.
+///
+/// Configuration that isn't machine state, the misaligned access policy and
+/// installed extensions, isn't included.
+///
+/// The file is plain text:
+/// ```text
+/// ETD32 SNAPSHOT 1
+/// isa 0.1.0
+/// cycles 12
+/// pc 0000000C
+/// sp 00
+/// flags -G--
+/// registers 00 0A 0B ...
+/// memory 00000040 00 01 02 ...
+/// ```
+/// with a memory line for each run of up to 16 contiguous bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Version of the ISA profile the cpu was running
+    pub isa: String,
+    /// Cycles the cpu had spent
+    pub cycles: u64,
+    pub program_counter: u32,
+    pub stack_pointer: u8,
+    pub flags: Flags,
+    /// General purpose registers, starting at register 1
+    pub registers: Vec<u8>,
+    /// Every mapped byte of memory as (address, value)
+    pub memory: Vec<(u8, u8)>,
+}
+
+impl Snapshot {
+    pub fn take(cpu: &Cpu) -> Snapshot {
+        Snapshot {
+            isa: cpu.profile.version.to_string(),
+            cycles: cpu.cycles,
+            program_counter: cpu.program_counter,
+            stack_pointer: cpu.stack_pointer,
+            flags: cpu.flags,
+            registers: cpu.general_purpose.clone(),
+            memory: cpu.memory.contents(),
+        }
+    }
+
+    /// Puts the cpu back into the saved state. The cpu must be running the
+    /// same ISA version and be able to hold every saved byte of memory.
+    /// Its history is cleared as the steps before can't be undone from here.
+    pub fn restore(&self, cpu: &mut Cpu) -> Result<(), &'static str> {
+        if self.isa != cpu.profile.version {
+            return Err("Snapshot is for a different ISA version");
+        }
+        if self.registers.len() != cpu.general_purpose.len() {
+            return Err("Snapshot has a different number of registers");
+        }
+        if self.memory.iter().any(|(address, _)| !cpu.memory.contains(*address)) {
+            return Err("Snapshot has memory the cpu doesn't");
+        }
+        for (address, value) in &self.memory {
+            cpu.poke(*address, *value)?;
+        }
+        cpu.forget_decoded();
+        if let Some(history) = &mut cpu.history {
+            history.clear();
+        }
+        cpu.cycles = self.cycles;
+        cpu.program_counter = self.program_counter;
+        cpu.stack_pointer = self.stack_pointer;
+        cpu.flags = self.flags;
+        cpu.general_purpose.copy_from_slice(&self.registers);
+        cpu.fault = None;
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Snapshot> {
+        Snapshot::parse(&fs::read_to_string(path)?)
+            .map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))
+    }
+
+    pub fn parse(text: &str) -> Result<Snapshot, &'static str> {
+        let mut lines = text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty());
+        let version = lines.next()
+            .and_then(|line| line.strip_prefix(HEADER))
+            .ok_or("Not a snapshot")?;
+        if parse_number(version, 10)? != SNAPSHOT_VERSION {
+            return Err("Unsupported snapshot version");
+        }
+
+        let mut snapshot = Snapshot {
+            isa: String::new(),
+            cycles: 0,
+            program_counter: 0,
+            stack_pointer: 0,
+            flags: Flags::new(),
+            registers: Vec::new(),
+            memory: Vec::new(),
+        };
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "isa" => snapshot.isa = value.trim().to_string(),
+                "cycles" => snapshot.cycles = value.trim().parse().map_err(|_| "Invalid number")?,
+                "pc" => snapshot.program_counter = parse_number(value, 16)?,
+                "sp" => snapshot.stack_pointer = parse_byte(value)?,
+                "flags" => snapshot.flags = parse_flags(value.trim())?,
+                "registers" => snapshot.registers = parse_bytes(value)?,
+                "memory" => {
+                    let (start, bytes) = value.trim().split_once(' ').ok_or("Memory line without bytes")?;
+                    let start = parse_number(start, 16)?;
+                    for (address, value) in (start..).zip(parse_bytes(bytes)?) {
+                        let address = u8::try_from(address).map_err(|_| "Memory address too large")?;
+                        snapshot.memory.push((address, value));
+                    }
+                }
+                _ => return Err("Unknown snapshot line"),
+            }
+        }
+        if snapshot.isa.is_empty() {
+            return Err("Snapshot is missing its ISA version");
+        }
+        Ok(snapshot)
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(fmt, "{HEADER} {SNAPSHOT_VERSION}")?;
+        writeln!(fmt, "isa {}", self.isa)?;
+        writeln!(fmt, "cycles {}", self.cycles)?;
+        writeln!(fmt, "pc {:08X}", self.program_counter)?;
+        writeln!(fmt, "sp {:02X}", self.stack_pointer)?;
+        writeln!(fmt, "flags {}", self.flags)?;
+        write!(fmt, "registers")?;
+        for value in &self.registers {
+            write!(fmt, " {value:02X}")?;
+        }
+        writeln!(fmt)?;
+
+        // Group memory into runs of contiguous addresses
+        let mut run: Vec<(u8, u8)> = Vec::new();
+        for &(address, value) in &self.memory {
+            let contiguous = run.last().is_some_and(|(last, _)| *last as u32 + 1 == address as u32);
+            if !contiguous || run.len() == BYTES_PER_LINE {
+                write_run(fmt, &run)?;
+                run.clear();
+            }
+            run.push((address, value));
+        }
+        write_run(fmt, &run)
+    }
+}
+
+fn write_run(fmt: &mut fmt::Formatter, run: &[(u8, u8)]) -> fmt::Result {
+    let Some((start, _)) = run.first() else {
+        return Ok(());
+    };
+    write!(fmt, "memory {:08X}", start)?;
+    for (_, value) in run {
+        write!(fmt, " {value:02X}")?;
+    }
+    writeln!(fmt)
+}
+
+fn parse_number(text: &str, radix: u32) -> Result<u32, &'static str> {
+    u32::from_str_radix(text.trim(), radix).map_err(|_| "Invalid number")
+}
+
+fn parse_byte(text: &str) -> Result<u8, &'static str> {
+    u8::from_str_radix(text.trim(), 16).map_err(|_| "Invalid byte")
+}
+
+fn parse_bytes(text: &str) -> Result<Vec<u8>, &'static str> {
+    text.split_whitespace().map(parse_byte).collect()
+}
+
+/// Reads flags as written by their Display, "CGZL" with - for clear
+fn parse_flags(text: &str) -> Result<Flags, &'static str> {
+    let bits: Vec<bool> = text.chars()
+        .zip("CGZL".chars())
+        .map(|(c, name)| match c {
+            '-' => Ok(false),
+            c if c == name => Ok(true),
+            _ => Err("Invalid flags"),
+        })
+        .collect::<Result<_, _>>()?;
+    match bits[..] {
+        [carry, greater, zero, less] if text.len() == 4 => Ok(Flags { carry, greater, zero, less }),
+        _ => Err("Invalid flags"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Instruction, Op, UnknownCpu};
+    use crate::emulator::memory::MEMORY_SIZE;
+
+    fn running_cpu() -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::AddRd { d: 1, x: 2, y: 3, pad: 0 })).unwrap();
+        match cpu.clock() {
+            UnknownCpu::Ok(cpu) => cpu,
+            UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
+        }
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let snapshot = Snapshot::take(&running_cpu());
+        assert_eq!(MEMORY_SIZE as usize, snapshot.memory.len());
+        let text = snapshot.to_string();
+        assert_eq!(Ok(snapshot), Snapshot::parse(&text), "{text}");
+    }
+
+    #[test]
+    fn restores_exactly() {
+        let cpu = running_cpu();
+        let snapshot = Snapshot::take(&cpu);
+        let mut restored = Cpu::new_blank();
+        Snapshot::parse(&snapshot.to_string()).unwrap()
+            .restore(&mut restored).unwrap();
+        assert_eq!(cpu.cycles, restored.cycles);
+        assert_eq!(cpu.program_counter, restored.program_counter);
+        assert_eq!(cpu.flags, restored.flags);
+        assert_eq!(cpu.general_purpose, restored.general_purpose);
+        assert_eq!(cpu.memory.contents(), restored.memory.contents());
+    }
+
+    #[test]
+    fn saves_to_file() {
+        let snapshot = Snapshot::take(&running_cpu());
+        let path = std::env::temp_dir().join(format!("etd32-snapshot-{}.txt", std::process::id()));
+        snapshot.save(&path).unwrap();
+        let loaded = Snapshot::load(&path);
+        fs::remove_file(&path).ok();
+        assert_eq!(snapshot, loaded.unwrap());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let text = Snapshot::take(&Cpu::new_blank()).to_string()
+            .replace("SNAPSHOT 1", "SNAPSHOT 2");
+        assert_eq!(Err("Unsupported snapshot version"), Snapshot::parse(&text));
+        assert_eq!(Err("Not a snapshot"), Snapshot::parse("isa 0.1.0"));
+    }
+
+    #[test]
+    fn restore_clears_history() {
+        let mut cpu = Cpu::new_blank();
+        cpu.record_history(8);
+        let snapshot = cpu.snapshot();
+        cpu.load_instruction(0, &Instruction::new(Flags::new(), Op::AddRd { d: 1, x: 2, y: 3, pad: 0 })).unwrap();
+        let mut cpu = match cpu.clock() {
+            UnknownCpu::Ok(cpu) => cpu,
+            UnknownCpu::Inter(cpu) => panic!("Unexpected intrrupt {}", cpu),
+        };
+        assert_eq!(1, cpu.history().unwrap().len());
+        snapshot.restore(&mut cpu).unwrap();
+        assert!(cpu.history().unwrap().is_empty());
+        assert_eq!(0, cpu.cycles);
+    }
+
+    #[test]
+    fn rejects_other_isa() {
+        let mut snapshot = Snapshot::take(&Cpu::new_blank());
+        snapshot.isa = "9.9.9".to_string();
+        assert!(snapshot.restore(&mut Cpu::new_blank()).is_err());
+    }
+
+    #[test]
+    fn parses_flags() {
+        let flags = parse_flags("C-Z-").unwrap();
+        assert!(flags.carry && flags.zero && !flags.greater && !flags.less);
+        assert!(parse_flags("Z---").is_err());
+        assert!(parse_flags("C-Z").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use crate::emulator::Op;
+
+/// Groups of instructions that cost the same
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// Shifts, logic, add and sub
+    Alu,
+    Multiply,
+    /// Width in bytes
+    Load(u8),
+    /// Width in bytes
+    Store(u8),
+    Branch,
+    Interrupt,
+    Reserved,
+}
+
+impl Class {
+    pub fn of(op: &Op) -> Class {
+        match op {
+            Op::MulRd { .. } | Op::MulRi { .. } => Class::Multiply,
+            Op::Load8Bo { .. } | Op::Load8Bi { .. } => Class::Load(1),
+            Op::Load16Bo { .. } | Op::Load16Bi { .. } => Class::Load(2),
+            Op::Load32Bo { .. } | Op::Load32Bi { .. } => Class::Load(4),
+            Op::Store8Bo { .. } | Op::Store8Bi { .. } => Class::Store(1),
+            Op::Store16Bo { .. } | Op::Store16Bi { .. } => Class::Store(2),
+            Op::Store32Bo { .. } | Op::Store32Bi { .. } => Class::Store(4),
+            Op::JumpOffset { .. } | Op::JumpRd { .. } | Op::JumpI { .. } => Class::Branch,
+            Op::Interrupt { .. } => Class::Interrupt,
+            Op::Reserved { .. } => Class::Reserved,
+            _ => Class::Alu,
+        }
+    }
+}
+
+/// Cycles each kind of instruction takes.
+///
+/// Every jump is predicated, so a branch is taken when its flags let it
+/// run and not taken when they skip it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CostTable {
+    pub alu: u64,
+    pub multiply: u64,
+    /// Indexed by width, 8, 16 then 32 bit
+    pub load: [u64; 3],
+    pub store: [u64; 3],
+    pub branch_taken: u64,
+    pub branch_not_taken: u64,
+    /// Any other instruction its flags skip
+    pub skipped: u64,
+    pub interrupt: u64,
+    /// Instructions run by an extension handler
+    pub extension: u64,
+    /// Added on top when the instruction faults
+    pub fault: u64,
+}
+
+impl CostTable {
+    /// Every instruction takes one cycle, whatever it does
+    pub fn uniform() -> Self {
+        CostTable {
+            alu: 1,
+            multiply: 1,
+            load: [1; 3],
+            store: [1; 3],
+            branch_taken: 1,
+            branch_not_taken: 1,
+            skipped: 1,
+            interrupt: 1,
+            extension: 1,
+            fault: 0,
+        }
+    }
+
+    /// Cycles for `op`, where `ran` is false if its flags skipped it
+    pub fn cycles(&self, op: &Op, ran: bool) -> u64 {
+        self.class_cycles(Class::of(op), ran)
+    }
+
+    pub fn class_cycles(&self, class: Class, ran: bool) -> u64 {
+        if !ran {
+            return match class {
+                Class::Branch => self.branch_not_taken,
+                _ => self.skipped,
+            };
+        }
+        match class {
+            Class::Alu => self.alu,
+            Class::Multiply => self.multiply,
+            Class::Load(width) => self.load[width_index(width)],
+            Class::Store(width) => self.store[width_index(width)],
+            Class::Branch => self.branch_taken,
+            Class::Interrupt => self.interrupt,
+            Class::Reserved => self.extension,
+        }
+    }
+}
+
+impl Default for CostTable {
+    /// A single issue core with one memory port, wider accesses take a
+    /// cycle per extra byte
+    fn default() -> Self {
+        CostTable {
+            alu: 1,
+            multiply: 3,
+            load: [2, 3, 5],
+            store: [2, 3, 5],
+            branch_taken: 3,
+            branch_not_taken: 1,
+            skipped: 1,
+            interrupt: 4,
+            extension: 1,
+            fault: 4,
+        }
+    }
+}
+
+fn width_index(width: u8) -> usize {
+    match width {
+        1 => 0,
+        2 => 1,
+        _ => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::op::{Imm12, Imm22};
+
+    #[test]
+    fn classifies_ops() {
+        assert_eq!(Class::Alu, Class::of(&Op::AddRi { d: 1, x: 1, i: Imm12::from_raw(1) }));
+        assert_eq!(Class::Multiply, Class::of(&Op::MulRd { d: 1, x: 1, y: 2, pad: 0 }));
+        assert_eq!(Class::Load(2), Class::of(&Op::Load16Bo { t: 1, base: 0, off: 4 }));
+        assert_eq!(Class::Store(4), Class::of(&Op::Store32Bi { t: 1, base: 0, index: 2, pad: 0 }));
+        assert_eq!(Class::Branch, Class::of(&Op::JumpI { target: Imm22::from_raw(0) }));
+        assert_eq!(Class::Reserved, Class::of(&Op::Reserved { opcode: 40, operands: 0 }));
+    }
+
+    #[test]
+    fn branch_cost_depends_on_taken() {
+        let table = CostTable::default();
+        let jump = Op::JumpOffset { offset: Imm22::from_raw(8) };
+        assert_eq!(table.branch_taken, table.cycles(&jump, true));
+        assert_eq!(table.branch_not_taken, table.cycles(&jump, false));
+        let add = Op::AddRi { d: 1, x: 1, i: Imm12::from_raw(1) };
+        assert_eq!(table.skipped, table.cycles(&add, false));
+    }
+
+    #[test]
+    fn load_cost_depends_on_width() {
+        let table = CostTable::default();
+        assert_eq!(2, table.cycles(&Op::Load8Bo { t: 1, base: 0, off: 0 }, true));
+        assert_eq!(3, table.cycles(&Op::Load16Bo { t: 1, base: 0, off: 0 }, true));
+        assert_eq!(5, table.cycles(&Op::Load32Bo { t: 1, base: 0, off: 0 }, true));
+    }
+}
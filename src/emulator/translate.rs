@@ -0,0 +1,342 @@
+use std::fmt;
+use std::rc::Rc;
+use crate::emulator::{Cpu, Fault, InstSet, Instruction, Op, UnknownCpu};
+use crate::emulator::timing::Class;
+
+/// Most instructions put in one block
+pub const MAX_BLOCK: usize = 64;
+
+/// An instruction lowered to a closure with its operands bound, so running
+/// it neither decodes nor matches on the operation again
+pub struct MicroOp {
+    /// For the flags that predicate it and for tracing
+    pub instruction: Instruction,
+    pub class: Class,
+    pub(crate) run: Box<dyn Fn(Cpu) -> UnknownCpu>,
+}
+
+impl fmt::Debug for MicroOp {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("MicroOp")
+            .field("instruction", &self.instruction)
+            .field("class", &self.class)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MicroOp {
+    /// Binds the operands of `op` as `Cpu::dispatch` would use them, and
+    /// the handler of an extension as it's registered now
+    fn lower(cpu: &Cpu, instruction: Instruction, op: Op) -> MicroOp {
+        let run: Box<dyn Fn(Cpu) -> UnknownCpu> = match op {
+            Op::LslRd { d, x, y, .. } => Box::new(move |cpu| InstSet::logical_left_shift_rd(cpu, d, x, y)),
+            Op::LslRi { d, x, i } => Box::new(move |cpu| InstSet::logical_left_shift_ri(cpu, d, x, i)),
+            Op::LsrRd { d, x, y, .. } => Box::new(move |cpu| InstSet::logical_right_shift_rd(cpu, d, x, y)),
+            Op::LsrRi { d, x, i } => Box::new(move |cpu| InstSet::logical_right_shift_ri(cpu, d, x, i)),
+            Op::AndRd { d, x, y, .. } => Box::new(move |cpu| InstSet::logical_and_rd(cpu, d, x, y)),
+            Op::AndRi { d, x, i } => Box::new(move |cpu| InstSet::logical_and_ri(cpu, d, x, i)),
+            Op::OrRd { d, x, y, .. } => Box::new(move |cpu| InstSet::logical_or_rd(cpu, d, x, y)),
+            Op::OrRi { d, x, i } => Box::new(move |cpu| InstSet::logical_or_ri(cpu, d, x, i)),
+            Op::XorRd { d, x, y, .. } => Box::new(move |cpu| InstSet::logical_xor_rd(cpu, d, x, y)),
+            Op::XorRi { d, x, i } => Box::new(move |cpu| InstSet::logical_xor_ri(cpu, d, x, i)),
+            Op::NotRd { d, x, y, .. } => Box::new(move |cpu| InstSet::logical_not_rd(cpu, d, x, y)),
+            Op::AddRd { d, x, y, .. } => Box::new(move |cpu| InstSet::logical_add_rd(cpu, d, x, y)),
+            Op::AddRi { d, x, i } => Box::new(move |cpu| InstSet::logical_add_ri(cpu, d, x, i)),
+            Op::SubRd { d, x, y, .. } => Box::new(move |cpu| InstSet::sub_rd(cpu, d, x, y)),
+            Op::SubRi { d, x, i } => Box::new(move |cpu| InstSet::sub_ri(cpu, d, x, i)),
+            Op::MulRd { d, x, y, .. } => Box::new(move |cpu| InstSet::multiply_rd(cpu, d, x, y)),
+            Op::MulRi { d, x, i } => Box::new(move |cpu| InstSet::multiply_ri(cpu, d, x, i)),
+            Op::Load8Bo { t, base, off } => Box::new(move |cpu| InstSet::load_8_bo(cpu, t, base, off)),
+            Op::Load8Bi { t, base, index, .. } => Box::new(move |cpu| InstSet::load_8_bi(cpu, t, base, index)),
+            Op::Load16Bo { t, base, off } => Box::new(move |cpu| InstSet::load_16_bo(cpu, t, base, off)),
+            Op::Load16Bi { t, base, index, .. } => Box::new(move |cpu| InstSet::load_16_bi(cpu, t, base, index)),
+            Op::Load32Bo { t, base, off } => Box::new(move |cpu| InstSet::load_32_bo(cpu, t, base, off)),
+            Op::Load32Bi { t, base, index, .. } => Box::new(move |cpu| InstSet::load_32_bi(cpu, t, base, index)),
+            Op::Store8Bo { t, base, off } => Box::new(move |cpu| InstSet::store_8_bo(cpu, t, base, off)),
+            Op::Store8Bi { t, base, index, .. } => Box::new(move |cpu| InstSet::store_8_bi(cpu, t, base, index)),
+            Op::Store16Bo { t, base, off } => Box::new(move |cpu| InstSet::store_16_bo(cpu, t, base, off)),
+            Op::Store16Bi { t, base, index, .. } => Box::new(move |cpu| InstSet::store_16_bi(cpu, t, base, index)),
+            Op::Store32Bo { t, base, off } => Box::new(move |cpu| InstSet::store_32_bo(cpu, t, base, off)),
+            Op::Store32Bi { t, base, index, .. } => Box::new(move |cpu| InstSet::store_32_bi(cpu, t, base, index)),
+            Op::JumpOffset { offset } => Box::new(move |cpu| InstSet::jump_offset(cpu, offset)),
+            Op::JumpRd { d, .. } => Box::new(move |cpu| InstSet::jump_to_rd(cpu, d)),
+            Op::JumpI { target } => Box::new(move |cpu| InstSet::jump_to_i(cpu, target)),
+            Op::Interrupt { .. } => Box::new(InstSet::trigger_interupt),
+            Op::Reserved { opcode, .. } => match cpu.extensions.get(opcode) {
+                Some(extension) => {
+                    let handler = extension.handler;
+                    Box::new(move |cpu| handler(cpu, &instruction))
+                }
+                None => Box::new(move |cpu| InstSet::fault(cpu, Fault::UnknownOpcode(opcode))),
+            },
+        };
+        MicroOp { instruction, class: Class::of(&op), run }
+    }
+}
+
+/// A run of straight-line instructions, decoded and lowered once. It ends
+/// after the first jump, interrupt or reserved opcode, as any of them can
+/// move the program counter somewhere else.
+#[derive(Debug)]
+pub struct Block {
+    pub start: u32,
+    pub ops: Vec<MicroOp>,
+}
+
+impl Block {
+    /// Decodes from `start` until the block has to end or the next
+    /// instruction can't be fetched. None if not even the first can be.
+    pub(crate) fn translate(cpu: &Cpu, start: u32) -> Option<Block> {
+        let mut ops = Vec::new();
+        let mut address = start;
+        while ops.len() < MAX_BLOCK {
+            let Ok(instruction) = cpu.fetch(address) else { break };
+            let op = cpu.profile.op(&instruction);
+            ops.push(MicroOp::lower(cpu, instruction, op));
+            if matches!(Class::of(&op), Class::Branch | Class::Interrupt | Class::Reserved) {
+                break;
+            }
+            address += 4;
+        }
+        (!ops.is_empty()).then_some(Block { start, ops })
+    }
+
+    /// One past the last byte of the block
+    pub fn end(&self) -> u32 {
+        self.start + 4 * self.ops.len() as u32
+    }
+}
+
+/// Blocks already translated, by start address.
+///
+/// Like the decode cache, the cpu drops every block holding a byte it
+/// writes, and every block when its profile changes or an extension is
+/// registered. Writes the host makes straight to `Cpu::memory` aren't
+/// seen, so after one call `Cpu::translate_blocks` again.
+#[derive(Debug, Clone)]
+pub struct Translation {
+    blocks: Vec<Option<Rc<Block>>>,
+    /// Bytes inside at least one block, so most writes skip the search
+    code: Vec<bool>,
+    /// Set when a block was dropped, so a running block stops after the
+    /// instruction that wrote to it
+    pub(crate) stale: bool,
+    pub translated: u64,
+    /// Times a block was run from the table
+    pub entered: u64,
+}
+
+impl Default for Translation {
+    fn default() -> Self {
+        Translation {
+            blocks: vec![None; u8::MAX as usize + 1],
+            code: vec![false; u8::MAX as usize + 1],
+            stale: false,
+            translated: 0,
+            entered: 0,
+        }
+    }
+}
+
+impl Translation {
+    pub fn new() -> Self {
+        Translation::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn block(&self, start: u32) -> Option<&Block> {
+        self.blocks.get(start as usize)?.as_deref()
+    }
+
+    pub(crate) fn get(&mut self, start: u32) -> Option<Rc<Block>> {
+        let block = self.blocks.get(start as usize)?.clone()?;
+        self.entered += 1;
+        Some(block)
+    }
+
+    pub(crate) fn insert(&mut self, block: Block) -> Rc<Block> {
+        for address in block.start..block.end() {
+            if let Some(code) = self.code.get_mut(address as usize) {
+                *code = true;
+            }
+        }
+        let block = Rc::new(block);
+        if let Some(slot) = self.blocks.get_mut(block.start as usize) {
+            *slot = Some(block.clone());
+        }
+        self.translated += 1;
+        block
+    }
+
+    /// Drops every block that includes the byte at `address`
+    pub fn invalidate(&mut self, address: u32) {
+        if !self.code.get(address as usize).copied().unwrap_or(false) {
+            return;
+        }
+        for slot in &mut self.blocks {
+            if slot.as_ref().is_some_and(|block| (block.start..block.end()).contains(&address)) {
+                *slot = None;
+            }
+        }
+        self.code[address as usize] = false;
+        for block in self.blocks.iter().flatten() {
+            if (block.start..block.end()).contains(&address) {
+                self.code[address as usize] = true;
+            }
+        }
+        self.stale = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.fill(None);
+        self.code.fill(false);
+        self.stale = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Flags, Fault, UnknownCpu};
+    use crate::emulator::cache::{CacheConfig, CachedMemory};
+    use crate::emulator::memory::SimpleMemory;
+    use crate::emulator::op::{Imm12, Imm22};
+
+    fn add(d: u8, i: i32) -> Instruction {
+        Instruction::new(Flags::new(), Op::AddRi { d, x: d, i: Imm12::from_value(i).unwrap() })
+    }
+
+    fn jump(target: i32) -> Instruction {
+        Instruction::new(Flags::new(), Op::JumpI { target: Imm22::from_value(target).unwrap() })
+    }
+
+    fn load(cpu: &mut Cpu, program: &[Instruction]) {
+        cpu.trace = false;
+        for (address, instruction) in (0..).step_by(4).zip(program) {
+            cpu.load_instruction(address, instruction).unwrap();
+        }
+    }
+
+    /// Runs the same cpu interpreted and translated, checking they end up
+    /// in the same state
+    fn matches_interpreter(program: &[Instruction], setup: impl Fn(&mut Cpu), limit: usize) -> Cpu {
+        let mut interpreted = Cpu::new_blank();
+        let mut translated = Cpu::new_blank();
+        for cpu in [&mut interpreted, &mut translated] {
+            load(cpu, program);
+            setup(cpu);
+        }
+        translated.translate_blocks(true);
+        let mut interrupted = false;
+        for _ in 0..limit {
+            match interpreted.clock() {
+                UnknownCpu::Ok(cpu) => interpreted = cpu,
+                UnknownCpu::Inter(cpu) => {
+                    interpreted = cpu;
+                    interrupted = true;
+                    break;
+                }
+            }
+        }
+        let result = translated.run_translated(limit);
+        assert_eq!(interrupted, matches!(result, UnknownCpu::Inter(_)));
+        let translated = result.into_cpu();
+        assert_eq!(interpreted.snapshot(), translated.snapshot());
+        assert_eq!(interpreted.cycles, translated.cycles);
+        assert_eq!(interpreted.fault, translated.fault);
+        assert_eq!(interpreted.memory.cache_stats(), translated.memory.cache_stats());
+        assert_eq!(interpreted.history().map(|history| history.len()), translated.history().map(|history| history.len()));
+        translated
+    }
+
+    #[test]
+    fn blocks_end_at_jumps() {
+        let mut cpu = Cpu::new_blank();
+        load(&mut cpu, &[add(1, 1), add(2, 1), jump(0), add(3, 1)]);
+        let block = Block::translate(&cpu, 4).unwrap();
+        assert_eq!((4, 12), (block.start, block.end()));
+        assert!(Block::translate(&cpu, 256).is_none());
+    }
+
+    #[test]
+    fn runs_loops_like_the_interpreter() {
+        let mut greater = Flags::new();
+        greater.greater = true;
+        let program = [
+            add(1, 5),
+            Instruction::new(Flags::new(), Op::SubRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() }),
+            Instruction::new(Flags::new(), Op::Store8Bo { t: 1, base: 0, off: 100 }),
+            Instruction::new(greater, Op::JumpI { target: Imm22::from_value(4).unwrap() }),
+            Instruction::new(Flags::new(), Op::Interrupt { i: Imm22::from_value(0).unwrap() }),
+        ];
+        let cpu = matches_interpreter(&program, |_| {}, 100);
+        assert_eq!(0, cpu.read(1));
+        let translation = cpu.translation().unwrap();
+        assert_eq!(3, translation.translated);
+        assert!(translation.entered > 0);
+    }
+
+    #[test]
+    fn stops_at_the_limit() {
+        matches_interpreter(&[add(1, 1), add(1, 2), add(1, 3), jump(0)], |_| {}, 6);
+    }
+
+    #[test]
+    fn faults_like_the_interpreter() {
+        let program = [add(1, 1), Instruction::new(Flags::new(), Op::Load32Bo { t: 1, base: 0, off: 252 }), add(1, 1)];
+        let cpu = matches_interpreter(&program, |_| {}, 10);
+        assert_eq!(Some(Fault::Unmapped(255)), cpu.fault);
+        // Runs off the end of memory
+        matches_interpreter(&[add(1, 1), jump(248)], |cpu| cpu.load_instruction(248, &add(2, 1)).unwrap(), 10);
+    }
+
+    #[test]
+    fn sees_its_own_code_change() {
+        // The store at 4 overwrites the add at 8, later in the same block
+        let sub = Instruction::new(Flags::new(), Op::SubRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() });
+        let program = [
+            add(1, 1),
+            Instruction::new(Flags::new(), Op::Store32Bo { t: 2, base: 0, off: 8 }),
+            add(1, 10),
+            jump(0),
+        ];
+        let setup = |cpu: &mut Cpu| {
+            for (register, byte) in (2..).zip(sub.encode().to_le_bytes()) {
+                cpu.write(register, byte);
+            }
+        };
+        let cpu = matches_interpreter(&program, setup, 8);
+        assert_eq!(0, cpu.read(1));
+    }
+
+    #[test]
+    fn fetches_through_the_instruction_cache() {
+        let program = [add(1, 1), add(2, 1), add(3, 1), add(4, 1), add(5, 1), jump(0)];
+        let setup = |cpu: &mut Cpu| {
+            let backing = std::mem::replace(&mut cpu.memory, Box::new(SimpleMemory::new_blank()));
+            let cached = CachedMemory::new(backing)
+                .with_instruction_cache(CacheConfig::new(16, 8, 1)).unwrap();
+            cpu.memory = Box::new(cached);
+        };
+        let cpu = matches_interpreter(&program, setup, 40);
+        assert!(cpu.memory.cache_stats()[0].1.misses > 0);
+    }
+
+    #[test]
+    fn runs_blocks_while_tracing_and_recording() {
+        let program = [add(1, 1), add(2, 1), jump(0)];
+        let setup = |cpu: &mut Cpu| {
+            cpu.trace = true;
+            cpu.record_history(4);
+        };
+        let cpu = matches_interpreter(&program, setup, 9);
+        assert_eq!(2, cpu.translation().unwrap().entered);
+    }
+}
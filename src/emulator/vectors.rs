@@ -0,0 +1,224 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::emulator::{Cpu, Fault, Flags, UnknownCpu};
+use crate::emulator::breakpoint::{Access, Location};
+
+/// Writes packed into one line of a hex file
+pub const SLOTS: usize = 4;
+
+/// What one instruction did, for checking a hardware implementation
+/// against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vector {
+    pub address: u32,
+    /// The raw instruction word, 0 if it couldn't be fetched
+    pub word: u32,
+    /// False if its flags skipped it or it couldn't be fetched
+    pub ran: bool,
+    /// (register, value) for each register written, in order
+    pub registers: Vec<(u8, u8)>,
+    /// (address, value) for each byte written, in order
+    pub memory: Vec<(u8, u8)>,
+    /// Flags after the instruction
+    pub flags: Flags,
+    /// The cpu's cycle count after the instruction
+    pub cycles: u64,
+    pub fault: Option<Fault>,
+}
+
+/// Expected state after each instruction of a run, exported as
+/// `$readmemh` files and CSV.
+///
+/// The hex files have a line per instruction:
+/// - `pc.hex`, 8 digits
+/// - `instruction.hex`, 8 digits
+/// - `flags.hex`, 1 digit, carry 8, greater 4, zero 2, less 1
+/// - `cycles.hex`, 16 digits
+/// - `registers.hex` and `memory.hex`, 17 digits, the number of writes
+///   then 4 slots of 2 digit register or address and 2 digit value, the
+///   first write in the leftmost slot and unused slots zero
+///
+/// Writes past the 4th, which only an extension could make, are left out
+/// of the hex files but kept in the CSV.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Vectors {
+    pub vectors: Vec<Vector>,
+}
+
+impl Vectors {
+    /// Runs up to `limit` instructions, with a vector for each
+    pub fn record(cpu: Cpu, limit: usize) -> (UnknownCpu, Vectors) {
+        let mut vectors = Vectors::default();
+        let cpu = cpu.run_with(limit, |cpu| vectors.clock(cpu));
+        (cpu, vectors)
+    }
+
+    /// Clocks the cpu once, recording the instruction it runs. A fetch that
+    /// traps is recorded too, with its fault.
+    pub fn clock(&mut self, mut cpu: Cpu) -> UnknownCpu {
+        let address = cpu.program_counter;
+        let instruction = cpu.fetch(address).ok();
+        let word = instruction.and_then(|instruction| instruction.try_encode().ok()).unwrap_or(0);
+        let ran = instruction.is_some_and(|instruction| Flags::instruction_can_run(&cpu.flags, &instruction.flags));
+        cpu.accesses = Some(Vec::new());
+
+        let mut result = cpu.clock();
+        let cpu = result.cpu_mut();
+        let mut vector = Vector {
+            address,
+            word,
+            ran,
+            registers: Vec::new(),
+            memory: Vec::new(),
+            flags: cpu.flags,
+            cycles: cpu.cycles,
+            fault: cpu.fault,
+        };
+        for (location, access, value) in cpu.accesses.take().unwrap_or_default() {
+            match (location, access) {
+                (Location::Register(register), Access::Write) => vector.registers.push((register, value)),
+                (Location::Memory(address), Access::Write) => vector.memory.push((address, value)),
+                _ => (),
+            }
+        }
+        self.vectors.push(vector);
+        result
+    }
+
+    /// Each hex file's name and contents
+    pub fn readmemh(&self) -> Vec<(&'static str, String)> {
+        let mut files = [
+            ("pc.hex", String::new()),
+            ("instruction.hex", String::new()),
+            ("flags.hex", String::new()),
+            ("cycles.hex", String::new()),
+            ("registers.hex", String::new()),
+            ("memory.hex", String::new()),
+        ];
+        for vector in &self.vectors {
+            writeln!(files[0].1, "{:08X}", vector.address).unwrap();
+            writeln!(files[1].1, "{:08X}", vector.word).unwrap();
+            writeln!(files[2].1, "{:X}", vector.flags.nibble()).unwrap();
+            writeln!(files[3].1, "{:016X}", vector.cycles).unwrap();
+            writeln!(files[4].1, "{}", slots(&vector.registers)).unwrap();
+            writeln!(files[5].1, "{}", slots(&vector.memory)).unwrap();
+        }
+        files.into()
+    }
+
+    /// Writes the hex files into `directory`, which must exist
+    pub fn write_readmemh(&self, directory: impl AsRef<Path>) -> io::Result<()> {
+        for (name, contents) in self.readmemh() {
+            fs::write(directory.as_ref().join(name), contents)?;
+        }
+        Ok(())
+    }
+
+    /// A row per instruction, writes as space separated `where=value` in
+    /// hex
+    pub fn csv(&self) -> String {
+        let mut out = String::from("step,pc,instruction,ran,flags,cycles,registers,memory,fault\n");
+        for (step, vector) in self.vectors.iter().enumerate() {
+            let registers: Vec<String> = vector.registers.iter().map(|(register, value)| format!("r{register}={value:02X}")).collect();
+            let memory: Vec<String> = vector.memory.iter().map(|(address, value)| format!("{address:02X}={value:02X}")).collect();
+            let fault = vector.fault.map(|fault| fault.to_string()).unwrap_or_default();
+            writeln!(out, "{step},{:08X},{:08X},{},{},{},{},{},{}", vector.address, vector.word, vector.ran as u8,
+                vector.flags, vector.cycles, registers.join(" "), memory.join(" "), fault).unwrap();
+        }
+        out
+    }
+}
+
+/// The number of writes then a 2 digit location and value for each slot
+fn slots(writes: &[(u8, u8)]) -> String {
+    let mut out = format!("{:X}", writes.len().min(SLOTS));
+    for slot in 0..SLOTS {
+        let (location, value) = writes.get(slot).copied().unwrap_or_default();
+        write!(out, "{location:02X}{value:02X}").unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Instruction, Op};
+    use crate::emulator::op::{Imm12, Imm22};
+
+    /// Puts 5 in r1, stores it at 64, loads 64..67 into r2..r5, skips an
+    /// add and interrupts
+    fn recorded() -> Vectors {
+        let mut cpu = Cpu::new_blank();
+        cpu.trace = false;
+        let mut zero = Flags::new();
+        zero.zero = true;
+        let program = [
+            Instruction::new(Flags::new(), Op::AddRi { d: 1, x: 0, i: Imm12::from_value(5).unwrap() }),
+            Instruction::new(Flags::new(), Op::Store8Bo { t: 1, base: 0, off: 64 }),
+            Instruction::new(Flags::new(), Op::Load32Bo { t: 2, base: 0, off: 64 }),
+            Instruction::new(zero, Op::AddRi { d: 1, x: 1, i: Imm12::from_value(1).unwrap() }),
+            Instruction::new(Flags::new(), Op::Interrupt { i: Imm22::from_value(0).unwrap() }),
+        ];
+        for (address, instruction) in (0..).step_by(4).zip(&program) {
+            cpu.load_instruction(address, instruction).unwrap();
+        }
+        let (_, vectors) = Vectors::record(cpu, 10);
+        vectors
+    }
+
+    #[test]
+    fn records_writes() {
+        let vectors = recorded().vectors;
+        assert_eq!(5, vectors.len());
+        assert_eq!(vec![(1, 5)], vectors[0].registers);
+        assert_eq!(vec![(64, 5)], vectors[1].memory);
+        assert_eq!(vec![(2, 5), (3, 0), (4, 0), (5, 0)], vectors[2].registers);
+        assert!(!vectors[3].ran && vectors[3].registers.is_empty());
+        assert_eq!(16, vectors[4].address);
+        assert!(vectors.windows(2).all(|pair| pair[0].cycles < pair[1].cycles));
+    }
+
+    #[test]
+    fn records_fetch_traps() {
+        let mut cpu = Cpu::new_blank();
+        cpu.trace = false;
+        cpu.program_counter = 252;
+        let (cpu, vectors) = Vectors::record(cpu, 10);
+        assert_eq!(vec![Vector {
+            address: 252,
+            word: 0,
+            ran: false,
+            registers: Vec::new(),
+            memory: Vec::new(),
+            flags: Flags::new(),
+            cycles: cpu.cpu().cycles,
+            fault: Some(Fault::Unmapped(252)),
+        }], vectors.vectors);
+    }
+
+    #[test]
+    fn hex_files() {
+        let files = recorded().readmemh();
+        let file = |name| files.iter().find(|(file, _)| *file == name).unwrap().1.lines().collect::<Vec<_>>();
+        assert_eq!(["00000000", "00000004", "00000008", "0000000C", "00000010"], file("pc.hex")[..]);
+        assert_eq!("05C20040", file("instruction.hex")[1]);
+        assert_eq!("4", file("flags.hex")[0]);
+        assert_eq!(format!("{:016X}", recorded().vectors[0].cycles), file("cycles.hex")[0]);
+        assert_eq!("10105000000000000", file("registers.hex")[0]);
+        assert_eq!("40205030004000500", file("registers.hex")[2]);
+        assert_eq!("14005000000000000", file("memory.hex")[1]);
+        assert_eq!("00000000000000000", file("memory.hex")[0]);
+    }
+
+    #[test]
+    fn csv_rows() {
+        let csv = recorded().csv();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(6, rows.len());
+        let cycles = recorded().vectors[1].cycles;
+        assert_eq!(format!("1,00000004,05C20040,1,-G--,{cycles},,40=05,"), rows[2]);
+        assert!(rows[4].contains(",0,"), "{}", rows[4]);
+    }
+}
@@ -0,0 +1,500 @@
+//! C ABI over the emulator, so an RTL testbench can step it as the golden
+//! model after every retired instruction, and scripts can drive it through
+//! `python/etd3200.py`. Declared in `include/etd3200.h`.
+//!
+//! Functions returning `i32` give one of the `ETD_` codes. Pointers must be
+//! null or come from this library, and buffers must hold `len` bytes.
+//! Functions are only added, and `ETD_ABI_VERSION` goes up when they are.
+use std::ffi::{c_char, CStr};
+use std::ptr;
+use crate::emulator::{Cpu, Extensions, Fault, Flags, Handle, Instruction, IsaProfile, Stepped};
+use crate::program_loader::parse_machine_code;
+
+pub const ETD_ABI_VERSION: u32 = 1;
+
+pub const ETD_OK: i32 = 0;
+/// The instruction interrupted the cpu, by a fault or an interrupt
+pub const ETD_INTERRUPTED: i32 = 1;
+pub const ETD_NULL: i32 = -1;
+/// An address or register outside the machine
+pub const ETD_OUT_OF_RANGE: i32 = -2;
+/// Text that isn't valid machine code, or an instruction that can't be
+/// encoded
+pub const ETD_INVALID: i32 = -3;
+/// The emulator panicked while stepping and the cpu was lost with it. Every
+/// later call on that cpu gives this, it can only be freed.
+pub const ETD_PANICKED: i32 = -4;
+
+/// Architectural state as a testbench sees it
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EtdState {
+    pub pc: u32,
+    /// Carry 8, greater 4, zero 2, less 1
+    pub flags: u8,
+    /// Indexed by register number, register 0 is always zero
+    pub registers: [u8; 32],
+}
+
+/// A cpu owned by the caller
+pub struct EtdCpu {
+    handle: Handle,
+}
+
+impl EtdCpu {
+    fn cpu(&self) -> Result<&Cpu, i32> {
+        self.handle.cpu().ok_or(ETD_PANICKED)
+    }
+
+    fn cpu_mut(&mut self) -> Result<&mut Cpu, i32> {
+        self.handle.cpu_mut().ok_or(ETD_PANICKED)
+    }
+}
+
+fn code_of(stepped: Stepped) -> i32 {
+    match stepped {
+        Stepped::Ran => ETD_OK,
+        Stepped::Interrupted => ETD_INTERRUPTED,
+        Stepped::Lost => ETD_PANICKED,
+    }
+}
+
+fn state_of(cpu: &Cpu) -> EtdState {
+    let mut state = EtdState { pc: cpu.program_counter, flags: cpu.flags().nibble(), registers: [0; 32] };
+    for (register, value) in (0..).zip(&mut state.registers) {
+        *value = cpu.read(register);
+    }
+    state
+}
+
+#[no_mangle]
+pub extern "C" fn etd_abi_version() -> u32 {
+    ETD_ABI_VERSION
+}
+
+/// A zeroed cpu with tracing off, free it with `etd_cpu_free`
+#[no_mangle]
+pub extern "C" fn etd_cpu_new() -> *mut EtdCpu {
+    Box::into_raw(Box::new(EtdCpu { handle: Handle::new(Cpu::new_blank()) }))
+}
+
+/// # Safety
+/// `cpu` must be null or from `etd_cpu_new`, and not used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_free(cpu: *mut EtdCpu) {
+    if !cpu.is_null() {
+        drop(Box::from_raw(cpu));
+    }
+}
+
+/// Writes `count` instruction words into memory from `address`
+///
+/// # Safety
+/// `words` must point to `count` words
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_load(cpu: *mut EtdCpu, address: u32, words: *const u32, count: usize) -> i32 {
+    let (Some(cpu), false) = (cpu.as_mut(), words.is_null()) else { return ETD_NULL };
+    let words = std::slice::from_raw_parts(words, count);
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let Ok(cpu) = cpu.cpu_mut() else { return ETD_PANICKED };
+    write_memory(cpu, address, &bytes)
+}
+
+/// Loads machine code text, a line of 32 bits per instruction, into memory
+/// from `address`. The number of instructions goes in `count` if it isn't
+/// null.
+///
+/// # Safety
+/// `text` must be a NUL terminated string, `count` null or writable
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_load_machine_code(cpu: *mut EtdCpu, address: u32, text: *const c_char, count: *mut usize) -> i32 {
+    let (Some(cpu), false) = (cpu.as_mut(), text.is_null()) else { return ETD_NULL };
+    let Ok(text) = CStr::from_ptr(text).to_str() else { return ETD_INVALID };
+    let Ok(instructions) = parse_machine_code(text.to_string()) else { return ETD_INVALID };
+    let Ok(words) = instructions.iter().map(Instruction::try_encode).collect::<Result<Vec<u32>, _>>() else { return ETD_INVALID };
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let Ok(cpu) = cpu.cpu_mut() else { return ETD_PANICKED };
+    let code = write_memory(cpu, address, &bytes);
+    if let (ETD_OK, Some(count)) = (code, count.as_mut()) {
+        *count = instructions.len();
+    }
+    code
+}
+
+/// # Safety
+/// `bytes` must point to `len` bytes
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_write_memory(cpu: *mut EtdCpu, address: u32, bytes: *const u8, len: usize) -> i32 {
+    let (Some(cpu), false) = (cpu.as_mut(), bytes.is_null()) else { return ETD_NULL };
+    let Ok(cpu) = cpu.cpu_mut() else { return ETD_PANICKED };
+    write_memory(cpu, address, std::slice::from_raw_parts(bytes, len))
+}
+
+fn write_memory(cpu: &mut Cpu, address: u32, bytes: &[u8]) -> i32 {
+    if address as u64 + bytes.len() as u64 > u8::MAX as u64 + 1 {
+        return ETD_OUT_OF_RANGE;
+    }
+    // Through the cpu so cached decodes and blocks see the new code
+    match cpu.load_bytes(address, bytes) {
+        Ok(()) => ETD_OK,
+        Err(_) => ETD_OUT_OF_RANGE,
+    }
+}
+
+/// Runs one instruction, giving `ETD_PANICKED` rather than unwinding into
+/// the caller if the emulator panics
+///
+/// # Safety
+/// `cpu` must be null or from `etd_cpu_new`
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_step(cpu: *mut EtdCpu) -> i32 {
+    let Some(cpu) = cpu.as_mut() else { return ETD_NULL };
+    code_of(cpu.handle.step())
+}
+
+/// Steps until the cpu is interrupted or has spent `budget` cycles, giving
+/// `ETD_OK` if the budget ran out. The instructions run go in `steps` if it
+/// isn't null.
+///
+/// # Safety
+/// `cpu` must be null or from `etd_cpu_new`, `steps` null or writable
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_run(cpu: *mut EtdCpu, budget: u64, steps: *mut u64) -> i32 {
+    let Some(cpu) = cpu.as_mut() else { return ETD_NULL };
+    let (stepped, ran) = cpu.handle.run(budget);
+    if let Some(steps) = steps.as_mut() {
+        *steps = ran;
+    }
+    code_of(stepped)
+}
+
+/// Cycles taken by every step so far, 0 if `cpu` is null or was lost to a
+/// panic
+///
+/// # Safety
+/// `cpu` must be null or from `etd_cpu_new`
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_cycles(cpu: *const EtdCpu) -> u64 {
+    cpu.as_ref().and_then(|cpu| cpu.cpu().ok()).map_or(0, |cpu| cpu.cycles)
+}
+
+/// # Safety
+/// `cpu` must be null or from `etd_cpu_new`
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_set_pc(cpu: *mut EtdCpu, pc: u32) -> i32 {
+    let Some(cpu) = cpu.as_mut() else { return ETD_NULL };
+    let Ok(cpu) = cpu.cpu_mut() else { return ETD_PANICKED };
+    cpu.program_counter = pc;
+    ETD_OK
+}
+
+/// Why the last step was interrupted: 0 for none or a software interrupt,
+/// 1 invalid register, 2 unmapped, 3 misaligned, 4 unknown opcode. The
+/// register, address or opcode goes in `detail` if it isn't null.
+///
+/// # Safety
+/// `cpu` must be null or from `etd_cpu_new`, `detail` null or writable
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_fault(cpu: *const EtdCpu, detail: *mut u32) -> i32 {
+    let Some(cpu) = cpu.as_ref() else { return ETD_NULL };
+    let Ok(cpu) = cpu.cpu() else { return ETD_PANICKED };
+    let (code, value) = match cpu.fault {
+        None => (0, 0),
+        Some(Fault::InvalidRegister(register)) => (1, register as u32),
+        Some(Fault::Unmapped(address)) => (2, address),
+        Some(Fault::Misaligned { address, .. }) => (3, address),
+        Some(Fault::UnknownOpcode(opcode)) => (4, opcode as u32),
+    };
+    if let Some(detail) = detail.as_mut() {
+        *detail = value;
+    }
+    code
+}
+
+/// # Safety
+/// `cpu` must be null or from `etd_cpu_new`, `value` writable
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_read_register(cpu: *const EtdCpu, register: u8, value: *mut u8) -> i32 {
+    let (Some(cpu), Some(value)) = (cpu.as_ref(), value.as_mut()) else { return ETD_NULL };
+    let Ok(cpu) = cpu.cpu() else { return ETD_PANICKED };
+    if register > 31 {
+        return ETD_OUT_OF_RANGE;
+    }
+    *value = cpu.read(register);
+    ETD_OK
+}
+
+/// Writes to register 0 and the reserved register are ignored
+///
+/// # Safety
+/// `cpu` must be null or from `etd_cpu_new`
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_write_register(cpu: *mut EtdCpu, register: u8, value: u8) -> i32 {
+    let Some(cpu) = cpu.as_mut() else { return ETD_NULL };
+    let Ok(cpu) = cpu.cpu_mut() else { return ETD_PANICKED };
+    if register > 31 {
+        return ETD_OUT_OF_RANGE;
+    }
+    cpu.write(register, value);
+    ETD_OK
+}
+
+/// Copies `len` bytes of memory from `address` into `out`
+///
+/// # Safety
+/// `cpu` must be null or from `etd_cpu_new`, `out` must hold `len` bytes
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_read_memory(cpu: *const EtdCpu, address: u32, out: *mut u8, len: usize) -> i32 {
+    let (Some(cpu), false) = (cpu.as_ref(), out.is_null()) else { return ETD_NULL };
+    let Ok(cpu) = cpu.cpu() else { return ETD_PANICKED };
+    let bytes: Option<Vec<u8>> = (address as u64..address as u64 + len as u64)
+        .map(|address| u8::try_from(address).ok().and_then(|address| cpu.memory.peek(address)))
+        .collect();
+    match bytes {
+        Some(bytes) => {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), out, len);
+            ETD_OK
+        }
+        _ => ETD_OUT_OF_RANGE,
+    }
+}
+
+/// # Safety
+/// `cpu` must be null or from `etd_cpu_new`, `state` writable
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_state(cpu: *const EtdCpu, state: *mut EtdState) -> i32 {
+    let (Some(cpu), Some(state)) = (cpu.as_ref(), state.as_mut()) else { return ETD_NULL };
+    let Ok(cpu) = cpu.cpu() else { return ETD_PANICKED };
+    *state = state_of(cpu);
+    ETD_OK
+}
+
+/// Compares the cpu with a state from the hardware. 0 if they match,
+/// otherwise the first difference: 1 pc, 2 flags or 0x100 plus the
+/// register number.
+///
+/// # Safety
+/// `cpu` must be null or from `etd_cpu_new`, `state` readable
+#[no_mangle]
+pub unsafe extern "C" fn etd_cpu_compare(cpu: *const EtdCpu, state: *const EtdState) -> i32 {
+    let (Some(cpu), Some(state)) = (cpu.as_ref(), state.as_ref()) else { return ETD_NULL };
+    let Ok(cpu) = cpu.cpu() else { return ETD_PANICKED };
+    let expected = state_of(cpu);
+    if expected.pc != state.pc {
+        return 1;
+    }
+    if expected.flags != state.flags {
+        return 2;
+    }
+    match (0..32).find(|&register| expected.registers[register] != state.registers[register]) {
+        Some(register) => 0x100 + register as i32,
+        None => ETD_OK,
+    }
+}
+
+/// Packs flags (as in `EtdState`), a 6 bit opcode and 22 operand bits
+/// into an instruction word
+///
+/// # Safety
+/// `word` must be writable
+#[no_mangle]
+pub unsafe extern "C" fn etd_instruction_encode(flags: u8, opcode: u8, operands: u32, word: *mut u32) -> i32 {
+    let Some(word) = word.as_mut() else { return ETD_NULL };
+    if operands > 0x3FFFFF || flags > 0xF {
+        return ETD_INVALID;
+    }
+    let mut instruction = Instruction::decode(operands);
+    instruction.opcode = opcode;
+    instruction.flags = Flags::from_nibble(flags);
+    match instruction.try_encode() {
+        Ok(encoded) => {
+            *word = encoded;
+            ETD_OK
+        }
+        Err(_) => ETD_INVALID,
+    }
+}
+
+/// Splits an instruction word into the fields `etd_instruction_encode`
+/// takes. Any that are null are skipped.
+///
+/// # Safety
+/// Each pointer must be null or writable
+#[no_mangle]
+pub unsafe extern "C" fn etd_instruction_decode(word: u32, flags: *mut u8, opcode: *mut u8, operands: *mut u32) -> i32 {
+    let instruction = Instruction::decode(word);
+    if let Some(flags) = flags.as_mut() {
+        *flags = instruction.flags.nibble();
+    }
+    if let Some(opcode) = opcode.as_mut() {
+        *opcode = instruction.opcode;
+    }
+    if let Some(operands) = operands.as_mut() {
+        *operands = instruction.operands();
+    }
+    ETD_OK
+}
+
+/// Writes the instruction's assembly into `out` as a NUL terminated string,
+/// cut short if it doesn't fit. Gives the length of the whole text, like
+/// `snprintf`.
+///
+/// # Safety
+/// `out` must be null or hold `len` bytes
+#[no_mangle]
+pub unsafe extern "C" fn etd_instruction_disassemble(word: u32, out: *mut c_char, len: usize) -> i32 {
+    let instruction = Instruction::decode(word);
+    let text = IsaProfile::v0_1_0().disassemble(&instruction, &Extensions::new()).to_string();
+    if !out.is_null() && len > 0 {
+        let copied = text.len().min(len - 1);
+        ptr::copy_nonoverlapping(text.as_ptr().cast(), out, copied);
+        *out.add(copied) = 0;
+    }
+    text.len() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Instruction, Op};
+    use crate::emulator::op::{Imm12, Imm22};
+
+    fn program() -> Vec<u32> {
+        [
+            Op::AddRi { d: 1, x: 0, i: Imm12::from_value(7).unwrap() },
+            Op::Store8Bo { t: 1, base: 0, off: 100 },
+            Op::Interrupt { i: Imm22::from_value(0).unwrap() },
+        ].into_iter().map(|op| Instruction::new(Flags::new(), op).encode()).collect()
+    }
+
+    #[test]
+    fn steps_a_program() {
+        let words = program();
+        unsafe {
+            let cpu = etd_cpu_new();
+            assert_eq!(ETD_OK, etd_cpu_load(cpu, 0, words.as_ptr(), words.len()));
+            let mut state = EtdState { pc: 1, flags: 1, registers: [1; 32] };
+            assert_eq!(ETD_OK, etd_cpu_state(cpu, &mut state));
+            assert_eq!(EtdState { pc: 0, flags: 0, registers: [0; 32] }, state);
+            assert_eq!(ETD_OK, etd_cpu_step(cpu));
+            assert_eq!(ETD_OK, etd_cpu_step(cpu));
+            assert_eq!(ETD_INTERRUPTED, etd_cpu_step(cpu));
+
+            let mut value = 0;
+            assert_eq!(ETD_OK, etd_cpu_read_register(cpu, 1, &mut value));
+            assert_eq!(7, value);
+            let mut memory = [0; 2];
+            assert_eq!(ETD_OK, etd_cpu_read_memory(cpu, 100, memory.as_mut_ptr(), 2));
+            assert_eq!([7, 0], memory);
+            assert_eq!(ETD_OUT_OF_RANGE, etd_cpu_read_memory(cpu, 254, memory.as_mut_ptr(), 2));
+            assert_eq!(0, etd_cpu_fault(cpu, ptr::null_mut()));
+            etd_cpu_free(cpu);
+        }
+    }
+
+    #[test]
+    fn compares_state() {
+        let words = program();
+        unsafe {
+            let cpu = etd_cpu_new();
+            etd_cpu_load(cpu, 0, words.as_ptr(), words.len());
+            etd_cpu_step(cpu);
+            let mut state = EtdState { pc: 0, flags: 0, registers: [0; 32] };
+            assert_eq!(ETD_OK, etd_cpu_state(cpu, &mut state));
+            assert_eq!((4, 4, 7), (state.pc, state.flags, state.registers[1]));
+            assert_eq!(ETD_OK, etd_cpu_compare(cpu, &state));
+            state.registers[3] = 1;
+            assert_eq!(0x103, etd_cpu_compare(cpu, &state));
+            state.pc = 8;
+            assert_eq!(1, etd_cpu_compare(cpu, &state));
+            etd_cpu_free(cpu);
+        }
+    }
+
+    #[test]
+    fn reports_faults() {
+        let words = [Instruction::new(Flags::new(), Op::Load8Bo { t: 1, base: 0, off: 300 }).encode(), 0];
+        unsafe {
+            let cpu = etd_cpu_new();
+            etd_cpu_load(cpu, 0, words.as_ptr(), 1);
+            assert_eq!(ETD_INTERRUPTED, etd_cpu_step(cpu));
+            let mut address = 0;
+            assert_eq!(2, etd_cpu_fault(cpu, &mut address));
+            assert_eq!(300, address);
+            assert_eq!(ETD_NULL, etd_cpu_step(ptr::null_mut()));
+            assert_eq!(ETD_OUT_OF_RANGE, etd_cpu_load(cpu, 252, words.as_ptr(), 2));
+            etd_cpu_free(cpu);
+        }
+    }
+
+    #[test]
+    fn catches_panics() {
+        let mut cpu = Cpu::new_blank();
+        cpu.register_extension(40, "panics", |_, _| panic!("Extension panicked")).unwrap();
+        unsafe {
+            let mut word = 0;
+            etd_instruction_encode(0, 40, 0, &mut word);
+            let cpu = Box::into_raw(Box::new(EtdCpu { handle: Handle::new(cpu) }));
+            etd_cpu_load(cpu, 0, &word, 1);
+            assert_eq!(ETD_PANICKED, etd_cpu_step(cpu));
+            assert_eq!(ETD_PANICKED, etd_cpu_step(cpu));
+            assert_eq!(ETD_PANICKED, etd_cpu_run(cpu, 10, ptr::null_mut()));
+            assert_eq!(ETD_PANICKED, etd_cpu_set_pc(cpu, 0));
+            assert_eq!(0, etd_cpu_cycles(cpu));
+            etd_cpu_free(cpu);
+        }
+    }
+
+    #[test]
+    fn runs_machine_code_to_a_budget() {
+        // Puts 5 in r1 then jumps back to itself
+        let text = c"0000-001100-00001 00000 00000 0000101\n0000-011000-00000 00000 00000 0000000\n";
+        unsafe {
+            let cpu = etd_cpu_new();
+            let mut count = 0;
+            assert_eq!(ETD_OK, etd_cpu_load_machine_code(cpu, 0, text.as_ptr(), &mut count));
+            assert_eq!(2, count);
+            assert_eq!(ETD_INVALID, etd_cpu_load_machine_code(cpu, 0, c"0101".as_ptr(), ptr::null_mut()));
+            let mut steps = 0;
+            assert_eq!(ETD_OK, etd_cpu_run(cpu, 10, &mut steps));
+            assert!(etd_cpu_cycles(cpu) >= 10);
+            assert!(steps > 1 && steps < 10, "{steps}");
+            let mut value = 0;
+            etd_cpu_read_register(cpu, 1, &mut value);
+            assert_eq!(5, value);
+
+            assert_eq!(ETD_OK, etd_cpu_write_register(cpu, 2, 9));
+            etd_cpu_read_register(cpu, 2, &mut value);
+            assert_eq!(9, value);
+            assert_eq!(ETD_OK, etd_cpu_write_memory(cpu, 200, [1, 2].as_ptr(), 2));
+            let mut memory = [0; 2];
+            etd_cpu_read_memory(cpu, 200, memory.as_mut_ptr(), 2);
+            assert_eq!([1, 2], memory);
+            assert_eq!(ETD_OUT_OF_RANGE, etd_cpu_write_memory(cpu, 254, [1, 2].as_ptr(), 2));
+            etd_cpu_free(cpu);
+        }
+    }
+
+    #[test]
+    fn encodes_and_decodes() {
+        let word = program()[0];
+        let (mut flags, mut opcode, mut operands) = (0, 0, 0);
+        let mut encoded = 0;
+        unsafe {
+            etd_instruction_decode(word | 1 << 31, &mut flags, &mut opcode, &mut operands);
+            assert_eq!(8, flags);
+            assert_eq!(ETD_OK, etd_instruction_encode(flags, opcode, operands, &mut encoded));
+            assert_eq!(word | 1 << 31, encoded);
+            assert_eq!(ETD_INVALID, etd_instruction_encode(0, 64, 0, &mut encoded));
+            assert_eq!(ETD_INVALID, etd_instruction_encode(0, 0, 1 << 22, &mut encoded));
+
+            let expected = IsaProfile::v0_1_0().disassemble(&Instruction::decode(word), &Extensions::new()).to_string();
+            let mut text = [0 as c_char; 64];
+            assert_eq!(expected.len() as i32, etd_instruction_disassemble(word, text.as_mut_ptr(), text.len()));
+            assert_eq!(expected, CStr::from_ptr(text.as_ptr()).to_str().unwrap());
+            let mut short = [0 as c_char; 4];
+            etd_instruction_disassemble(word, short.as_mut_ptr(), short.len());
+            assert_eq!(&expected[..3], CStr::from_ptr(short.as_ptr()).to_str().unwrap());
+        }
+    }
+}
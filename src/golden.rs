@@ -0,0 +1,306 @@
+//! Runs machine code programs and checks them against a sidecar file of
+//! expected results, so a program test is a `.mc` file and a `.expect`
+//! file next to it:
+//! ```text
+//! # Comments and blank lines are skipped
+//! start 0            # program counter to start at, 0 if left out
+//! limit 200          # most instructions to run, 1000 if left out
+//! stops interrupt    # or limit or fault
+//! fault none         # or the fault's message
+//! pc 44
+//! registers r1=75 r2=11
+//! memory 64 0 1 2 3  # values from address 64 on
+//! cycles 100..300    # inclusive bounds, or an exact count
+//! ```
+//! Numbers are decimal. Only the lines given are checked. Registers start
+//! zeroed and memory random, from `ETD_SEED` if it's set, so a program
+//! can't lean on memory it didn't write.
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use rand::Rng;
+use crate::emulator::{Cpu, CpuBuilder, UnknownCpu};
+use crate::emulator::builder::{seed_from_env, Init};
+use crate::emulator::memory::{entropy, MEMORY_SIZE};
+use crate::program_loader::parse_machine_code;
+
+const DEFAULT_LIMIT: usize = 1000;
+
+/// Why a run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Interrupt,
+    Fault,
+    Limit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expectation {
+    pub start: u32,
+    pub limit: usize,
+    pub stops: Option<Stop>,
+    /// The fault's message, None for no fault
+    pub fault: Option<Option<String>>,
+    pub program_counter: Option<u32>,
+    pub registers: Vec<(u8, u8)>,
+    /// (address, value) for every byte given
+    pub memory: Vec<(u8, u8)>,
+    pub cycles: Option<RangeInclusive<u64>>,
+}
+
+impl Default for Expectation {
+    fn default() -> Self {
+        Expectation {
+            start: 0,
+            limit: DEFAULT_LIMIT,
+            stops: None,
+            fault: None,
+            program_counter: None,
+            registers: Vec::new(),
+            memory: Vec::new(),
+            cycles: None,
+        }
+    }
+}
+
+impl Expectation {
+    pub fn parse(text: &str) -> Result<Expectation, &'static str> {
+        let mut expectation = Expectation::default();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "start" => expectation.start = number(value)?,
+                "limit" => expectation.limit = number(value)?,
+                "stops" => expectation.stops = Some(match value {
+                    "interrupt" => Stop::Interrupt,
+                    "fault" => Stop::Fault,
+                    "limit" => Stop::Limit,
+                    _ => return Err("stops must be interrupt, fault or limit"),
+                }),
+                "fault" => expectation.fault = Some((value != "none").then(|| value.to_string())),
+                "pc" => expectation.program_counter = Some(number(value)?),
+                "registers" => for register in value.split_whitespace() {
+                    let (register, value) = register.strip_prefix('r')
+                        .and_then(|register| register.split_once('='))
+                        .ok_or("Registers are written r1=10")?;
+                    let register = number(register)?;
+                    if register > 31 {
+                        return Err("Registers are numbered 0 to 31");
+                    }
+                    expectation.registers.push((register, number(value)?));
+                },
+                "memory" => {
+                    let mut values = value.split_whitespace();
+                    let start: u8 = number(values.next().ok_or("Memory line without an address")?)?;
+                    for (offset, value) in values.enumerate() {
+                        let address = start as usize + offset;
+                        if address >= MEMORY_SIZE as usize {
+                            return Err("Memory values run past the end of memory");
+                        }
+                        expectation.memory.push((address as u8, number(value)?));
+                    }
+                }
+                "cycles" => expectation.cycles = Some(match value.split_once("..") {
+                    Some((low, high)) => number(low)?..=number(high)?,
+                    None => number(value)?..=number(value)?,
+                }),
+                "uart" => return Err("UART output can't be checked, the emulator has no UART"),
+                _ => return Err("Unknown expectation line"),
+            }
+        }
+        Ok(expectation)
+    }
+
+    /// Runs `program` over memory drawn from `seed` and lists every way the
+    /// result differs from this
+    pub fn check(&self, program: &str, seed: u64) -> Result<Vec<String>, &'static str> {
+        let mut cpu = CpuBuilder::new().seed(seed).registers(Init::Zero).build()?;
+        cpu.trace = false;
+        cpu.program_counter = self.start;
+        let instructions = parse_machine_code(program.to_string())?;
+        if self.start >= MEMORY_SIZE as u32 {
+            return Err("Program doesn't start in memory");
+        }
+        for (i, instruction) in instructions.iter().enumerate() {
+            // The last byte of each word has to be in memory too
+            let address = self.start as usize + i * 4;
+            if address + 4 > MEMORY_SIZE as usize {
+                return Err("Program doesn't fit in memory");
+            }
+            cpu.load_instruction(address as u8, instruction)?;
+        }
+
+        let mut stop = Stop::Limit;
+        for _ in 0..self.limit {
+            match cpu.clock() {
+                UnknownCpu::Ok(next) => cpu = next,
+                UnknownCpu::Inter(next) => {
+                    stop = if next.fault.is_some() { Stop::Fault } else { Stop::Interrupt };
+                    cpu = next;
+                    break;
+                }
+            }
+        }
+        Ok(self.differences(&cpu, stop))
+    }
+
+    fn differences(&self, cpu: &Cpu, stop: Stop) -> Vec<String> {
+        let mut differences = Vec::new();
+        let mut differ = |what: String, expected: String, actual: String| {
+            if expected != actual {
+                differences.push(format!("{what}: expected {expected}, got {actual}"));
+            }
+        };
+        if let Some(stops) = self.stops {
+            differ("stopped on".to_string(), format!("{stops:?}"), format!("{stop:?}"));
+        }
+        if let Some(fault) = &self.fault {
+            let actual = cpu.fault.map(|fault| fault.to_string());
+            differ("fault".to_string(), message(fault), message(&actual));
+        }
+        if let Some(pc) = self.program_counter {
+            differ("pc".to_string(), pc.to_string(), cpu.program_counter.to_string());
+        }
+        for &(register, value) in &self.registers {
+            differ(format!("r{register}"), value.to_string(), cpu.read(register).to_string());
+        }
+        for &(address, value) in &self.memory {
+            let actual = cpu.memory.peek(address).map_or(String::from("unmapped"), |value| value.to_string());
+            differ(format!("memory {address}"), value.to_string(), actual);
+        }
+        if let Some(cycles) = &self.cycles {
+            if !cycles.contains(&cpu.cycles) {
+                let expected = if cycles.start() == cycles.end() {
+                    cycles.start().to_string()
+                } else {
+                    format!("{}..{}", cycles.start(), cycles.end())
+                };
+                differ("cycles".to_string(), expected, cpu.cycles.to_string());
+            }
+        }
+        differences
+    }
+}
+
+fn message(fault: &Option<String>) -> String {
+    fault.clone().unwrap_or_else(|| String::from("none"))
+}
+
+fn number<T: std::str::FromStr>(text: &str) -> Result<T, &'static str> {
+    text.trim().parse().map_err(|_| "Invalid number")
+}
+
+/// How one program did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub program: PathBuf,
+    /// What memory was drawn from
+    pub seed: u64,
+    /// Each difference from the expectation, or why it couldn't be run
+    pub result: Result<Vec<String>, String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.result.as_ref().is_ok_and(Vec::is_empty)
+    }
+
+    /// The differences as a readable report, empty if it passed
+    pub fn report(&self) -> String {
+        match &self.result {
+            Ok(differences) if differences.is_empty() => String::new(),
+            Ok(differences) => format!("{} (seed {})\n  {}\n", self.program.display(), self.seed, differences.join("\n  ")),
+            Err(error) => format!("{} (seed {})\n  {error}\n", self.program.display(), self.seed),
+        }
+    }
+}
+
+/// Runs every `.mc` program in `directory` against its `.expect` file, in
+/// name order, all with the seed from `ETD_SEED` or a random one
+pub fn run_dir(directory: impl AsRef<Path>) -> io::Result<Vec<Outcome>> {
+    let seed = seed_from_env().unwrap_or_else(|| entropy().gen());
+    let mut programs: Vec<PathBuf> = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    programs.retain(|path| path.extension().is_some_and(|extension| extension == "mc"));
+    programs.sort();
+    programs.into_iter()
+        .map(|program| {
+            let result = run_file(&program, seed);
+            Ok(Outcome { program, seed, result })
+        })
+        .collect()
+}
+
+fn run_file(program: &Path, seed: u64) -> Result<Vec<String>, String> {
+    let expectation = fs::read_to_string(program.with_extension("expect"))
+        .map_err(|error| format!("Can't read its expectation: {error}"))?;
+    let expectation = Expectation::parse(&expectation)?;
+    let program = fs::read_to_string(program).map_err(|error| error.to_string())?;
+    Ok(expectation.check(&program, seed)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Puts 5 in r1 then interrupts, 5 cycles by the default costs
+    const PROGRAM: &str = "0000-001100-00001 00000 00000 0000101\n0000-100000-00000 00000 00000 0000000";
+
+    #[test]
+    fn parses_expectations() {
+        let text = "# Comment\nlimit 50\nstops interrupt\nregisters r1=5 r2=0\nmemory 10 1 2\ncycles 2..4\nfault none";
+        let expectation = Expectation::parse(text).unwrap();
+        assert_eq!(50, expectation.limit);
+        assert_eq!(Some(Stop::Interrupt), expectation.stops);
+        assert_eq!(vec![(1, 5), (2, 0)], expectation.registers);
+        assert_eq!(vec![(10, 1), (11, 2)], expectation.memory);
+        assert_eq!(Some(2..=4), expectation.cycles);
+        assert_eq!(Some(None), expectation.fault);
+        assert!(Expectation::parse("registers 1=5").is_err());
+        assert!(Expectation::parse("registers r31=0").is_ok());
+        assert!(Expectation::parse("registers r32=0").is_err());
+        assert_eq!(vec![(253, 1), (254, 2)], Expectation::parse("memory 253 1 2").unwrap().memory);
+        assert!(Expectation::parse("memory 253 1 2 3").is_err());
+        assert!(Expectation::parse("memory 255 1").is_err());
+        assert!(Expectation::parse("uart hello").is_err());
+    }
+
+    #[test]
+    fn passes_matching_programs() {
+        let expectation = Expectation::parse("stops interrupt\npc 4\nregisters r1=5\ncycles 5").unwrap();
+        assert_eq!(Ok(vec![]), expectation.check(PROGRAM, 0));
+    }
+
+    #[test]
+    fn lists_differences() {
+        let expectation = Expectation::parse("stops limit\nregisters r1=6 r2=0\ncycles 10..20").unwrap();
+        assert_eq!(Ok(vec![
+            String::from("stopped on: expected Limit, got Interrupt"),
+            String::from("r1: expected 6, got 5"),
+            String::from("cycles: expected 10..20, got 5"),
+        ]), expectation.check(PROGRAM, 0));
+    }
+
+    #[test]
+    fn reports_the_seed() {
+        let outcome = Outcome { program: PathBuf::from("a.mc"), seed: 7, result: Ok(vec![String::from("pc: expected 1, got 2")]) };
+        assert_eq!("a.mc (seed 7)\n  pc: expected 1, got 2\n", outcome.report());
+    }
+
+    #[test]
+    fn rejects_programs_past_memory() {
+        let fits = Expectation::parse("start 244\nlimit 1").unwrap();
+        assert_eq!(Ok(vec![]), fits.check(PROGRAM, 0));
+        for start in [248, 252, 253, 255, 300] {
+            let expectation = Expectation::parse(&format!("start {start}")).unwrap();
+            assert!(expectation.check(PROGRAM, 0).is_err(), "start {start}");
+        }
+    }
+}
@@ -1,2 +1,8 @@
+// Binary literals are grouped by instruction field rather than by nibble
+#![allow(clippy::unusual_byte_groupings)]
 pub mod emulator;
 pub mod program_loader;
+pub mod golden;
+pub mod ffi;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
@@ -1,29 +1,50 @@
 #![allow(dead_code)]
 use crate::emulator::Instruction;
-use rand;
-use rand::prelude::*;
 
-pub fn parse_machine_code(program:String) -> Vec<Instruction> {
-    let result =
-        program
+/// The bits of machine code on a line, empty for comments and labels
+fn code_bits(line: &str) -> String {
+    line.chars()
+        .take_while(|c| *c != '#')
+        .take_while(|c| *c != ':')
+        .filter(|c| *c == '1' || *c == '0')
+        .collect::<String>()
+}
+
+/// Fails if a line doesn't hold exactly 32 bits of machine code
+pub fn parse_machine_code(program:String) -> Result<Vec<Instruction>, &'static str> {
+    program
         .lines()
-        .map(|line| {
-            println!("{}", line);
-                line.chars()
-                .take_while(|c| *c != '#')
-                .take_while(|c| *c != ':')
-                .filter(|c| *c == '1' || *c == '0')
-                .collect::<String>()
-        })
+        .map(code_bits)
     .filter(|result| !result.is_empty())
-    .map(|num| if num.len() == 32 {num} else {panic!("Machine Code length wrong {}", num.len())})
-    .map(|num| {println!("||{}||", num); num})
-    .map(|value| u32::from_str_radix(&value, 2))
-    .filter(|result| result.is_ok())
-    .map(|bi| bi.unwrap())
-    .map(|bi| Instruction::decode(bi)) 
-    .collect();
-    return result;
+    .map(|num| if num.len() == 32 {Ok(num)} else {Err("Machine Code length wrong")})
+    .map(|num| u32::from_str_radix(&num?, 2).map_err(|_| "Machine Code isn't binary"))
+    .map(|value| value.map(Instruction::decode))
+    .collect()
+}
+
+/// The line, counting from 1, each instruction of the program is on
+pub fn parse_source_lines(program: &str) -> Vec<usize> {
+    program.lines()
+        .enumerate()
+        .filter(|(_, line)| !code_bits(line).is_empty())
+        .map(|(number, _)| number + 1)
+        .collect()
+}
+
+/// Each `:Label` line and the offset from the start of the program of the
+/// instruction after it
+pub fn parse_labels(program: &str) -> Vec<(String, u32)> {
+    let mut offset = 0;
+    let mut labels = Vec::new();
+    for line in program.lines() {
+        let code = line.split('#').next().unwrap_or("").trim();
+        if let Some(label) = code.strip_prefix(':') {
+            labels.push((label.trim().to_string(), offset));
+        } else if !code_bits(line).is_empty() {
+            offset += 4;
+        }
+    }
+    labels
 }
 
 
@@ -31,40 +52,60 @@ pub fn parse_machine_code(program:String) -> Vec<Instruction> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use rand::prelude::*;
 
     #[test]
     fn skips_comments() {
         let program = String::from("#This is a comment");
-        let output = parse_machine_code(program);
+        let output = parse_machine_code(program).unwrap();
         assert!(output.is_empty());
     }
 
     #[test]
     fn skips_comments_at_end_of_lies() {
         let program = String::from("00001000000000000000000000000000 # What is this comment?");
-        let output = parse_machine_code(program);
+        let output = parse_machine_code(program).unwrap();
         assert_eq!(1, output.len());
     }
 
     #[test]
     fn can_handle_empty_lines() {
         let program = String::from("00001000000000000000000000000000 # What is this comment?\n\n");
-        let output = parse_machine_code(program);
+        let output = parse_machine_code(program).unwrap();
         assert_eq!(1, output.len());
     }
 
     #[test]
     fn can_handle_labels() {
         let program = String::from(":Label01\n00001000000000000000000000000000 # What is this comment?\n\n");
-        let output = parse_machine_code(program);
+        let output = parse_machine_code(program).unwrap();
         assert_eq!(1, output.len());
     }
 
+    #[test]
+    fn rejects_wrong_length() {
+        let program = String::from("00001000000000000000000000000000\n0000100000000000");
+        assert_eq!(Err("Machine Code length wrong"), parse_machine_code(program));
+    }
+
+    #[test]
+    fn finds_labels() {
+        let program = "# Start\n00001000000000000000000000000000\n\n:Label01\n:Label02 # Same place\n00001000000000000000000000000000\n:End";
+        let labels = parse_labels(program);
+        assert_eq!(vec![(String::from("Label01"), 4), (String::from("Label02"), 4), (String::from("End"), 8)], labels);
+    }
+
+    #[test]
+    fn finds_source_lines() {
+        let program = "# Start\n00001000000000000000000000000000\n\n:Label01\n00001000000000000000000000000000 # Two";
+        assert_eq!(vec![2, 5], parse_source_lines(program));
+    }
+
     #[test]
     fn does_load_single_instruction() {
         // Simple interrupt instruction
         let program = String::from("00001000000000000000000000000000");
-        let output = parse_machine_code(program);
+        let output = parse_machine_code(program).unwrap();
         assert_eq!(1, output.len());
     }
 
@@ -73,22 +114,19 @@ mod test {
         // Simple interrupt instruction
         let instruction = Instruction::decode(0b00001000000000000000000000000000);
         let program = String::from("00001000000000000000000000000000");
-        let output = parse_machine_code(program);
+        let output = parse_machine_code(program).unwrap();
 
         assert_eq!(Some(&instruction), output.first());
     }
 
     #[test]
     fn does_test_all_instructions() {
-        let mut instruction = Instruction::decode(0);
-        let mut program = format!("0");
-        let mut output = Vec::new();
         let mut rng = rand::thread_rng();
         for _ in 0..1000 {
             let i = rng.gen();
-            instruction = Instruction::decode(i);
-            program = format!("{i:032b}");
-            output = parse_machine_code(program);
+            let instruction = Instruction::decode(i);
+            let program = format!("{i:032b}");
+            let output = parse_machine_code(program).unwrap();
             assert_eq!(Some(&instruction), output.first());
         }
     }
@@ -97,65 +135,64 @@ mod test {
     fn loads_simple_program() {
         let simple_program = String::from(SIMPLE_PROGRAM);
         println!(";;{:?}", simple_program);
-        let decoded = parse_machine_code(simple_program);
+        let decoded = parse_machine_code(simple_program).unwrap();
         println!(";;{:?}", decoded);
         assert_eq!(9, decoded.len());
         for i in decoded {
             println!("{}", i);
         }
-        return ();
     }
 
     #[test]
     fn loads_simple_program_with_correct_instructions() {
         let simple_program = String::from(SIMPLE_PROGRAM);
         println!(";;{:?}", simple_program);
-        let decoded = parse_machine_code(simple_program);
+        let decoded = parse_machine_code(simple_program).unwrap();
         println!(";;{:?}", decoded);
         assert_eq!(9, decoded.len());
         let mut i10 = Instruction::from_opcode(11);
         const TARGET:u8 = 1;
         i10.r_x_set(0);
         i10.i_y_set(10);
-        i10.r_dest_set(TARGET);
+        i10.r_dest_set(TARGET).unwrap();
 
         let mut i20 = Instruction::from_opcode(11);
         const VALUE:u8 = 2;
         i20.r_x_set(0);
         i20.i_y_set(0);
-        i20.r_dest_set(VALUE);
+        i20.r_dest_set(VALUE).unwrap();
 
         //put 11 in [3] for MAX
         let mut i30 = Instruction::from_opcode(11);
         const MAX:u8 = 3;
         i30.r_x_set(0);
         i30.i_y_set(11);
-        i30.r_dest_set(MAX);
+        i30.r_dest_set(MAX).unwrap();
 
         //:Loop
         // put VALUE in (TARGET)
         let mut i35 = Instruction::from_opcode(23);
-        i35.r_target_set(TARGET);
+        i35.r_target_set(TARGET).unwrap();
         i35.r_base_set(VALUE);
-        i35.i_offset_set(0);
+        i35.i_offset_set(0).unwrap();
 
         //ADD 1 to VALUE
         let mut i40 = Instruction::from_opcode(12);
         i40.r_x_set(VALUE);
         i40.i_y_set(1);
-        i40.r_dest_set(VALUE);
+        i40.r_dest_set(VALUE).unwrap();
 
         //ADD 1 to TARGET
         let mut i50 = Instruction::from_opcode(12);
         i50.r_x_set(TARGET);
         i50.i_y_set(1);
-        i50.r_dest_set(TARGET);
+        i50.r_dest_set(TARGET).unwrap();
 
         // SUB VALUE from MAX
         let mut i60 = Instruction::from_opcode(13);
         i60.r_x_set(MAX);
         i60.r_y_set(VALUE);
-        i60.r_dest_set(0);
+        i60.r_dest_set(0).unwrap();
         //Jump if GREATER than zero to Loop
         let mut i70 = Instruction::from_opcode(31);
         i70.i_set(12);
@@ -177,12 +214,11 @@ mod test {
         for (i, j) in all_instructions.iter().zip(decoded) {
             assert_eq!(*i, j, "\n{}\n{}", i, j);
         }
-        return ();
     }
 
 
 /// Testing Programs
-    const SIMPLE_PROGRAM:&'static str = "# Put numbers 0-10 into memory 10-20
+    const SIMPLE_PROGRAM:&str = "# Put numbers 0-10 into memory 10-20
 # [#] Register
 # (#) Memory 
 
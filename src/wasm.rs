@@ -0,0 +1,122 @@
+//! JavaScript API for running the emulator in a web page, built with
+//! `cargo build --target wasm32-unknown-unknown` and wasm-bindgen.
+use wasm_bindgen::prelude::*;
+use crate::emulator::{Cpu, CpuBuilder, Extensions, Handle, Instruction, IsaProfile, Stepped};
+use crate::program_loader::parse_machine_code;
+
+/// A cpu for JavaScript to load, step and look at
+#[wasm_bindgen]
+pub struct Emulator {
+    handle: Handle,
+}
+
+impl Emulator {
+    // A panic aborts a wasm module, so the cpu can't be lost
+    fn cpu(&self) -> &Cpu {
+        self.handle.cpu().expect("Cpu lost while running")
+    }
+
+    fn cpu_mut(&mut self) -> &mut Cpu {
+        self.handle.cpu_mut().expect("Cpu lost while running")
+    }
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// A zeroed cpu
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Emulator {
+        Emulator { handle: Handle::new(Cpu::new_blank()) }
+    }
+
+    /// A cpu with registers and memory filled from `seed`, the same for
+    /// the same seed
+    pub fn seeded(seed: u64) -> Emulator {
+        let cpu = CpuBuilder::new().seed(seed).build().expect("The default profile can be run");
+        Emulator { handle: Handle::new(cpu) }
+    }
+
+    /// Loads machine code text from `address`, giving the number of
+    /// instructions
+    #[wasm_bindgen(js_name = loadMachineCode)]
+    pub fn load_machine_code(&mut self, text: &str, address: u32) -> Result<usize, JsError> {
+        let instructions = parse_machine_code(text.to_string()).map_err(JsError::new)?;
+        let words = instructions.iter().map(Instruction::try_encode).collect::<Result<Vec<u32>, _>>().map_err(JsError::new)?;
+        self.load_words(&words, address)?;
+        Ok(instructions.len())
+    }
+
+    #[wasm_bindgen(js_name = loadWords)]
+    pub fn load_words(&mut self, words: &[u32], address: u32) -> Result<(), JsError> {
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        self.cpu_mut().load_bytes(address, &bytes).map_err(|fault| JsError::new(&fault.to_string()))
+    }
+
+    /// Runs one instruction, true if it interrupted the cpu
+    pub fn step(&mut self) -> bool {
+        self.handle.step() != Stepped::Ran
+    }
+
+    /// Steps until interrupted or `budget` cycles have been spent, true if
+    /// it was interrupted
+    pub fn run(&mut self, budget: u64) -> bool {
+        self.handle.run(budget).0 != Stepped::Ran
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pc(&self) -> u32 {
+        self.cpu().program_counter
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_pc(&mut self, pc: u32) {
+        self.cpu_mut().program_counter = pc;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn cycles(&self) -> u64 {
+        self.cpu().cycles
+    }
+
+    /// Written CGZL, with a dash for each flag that's clear
+    #[wasm_bindgen(getter)]
+    pub fn flags(&self) -> String {
+        self.cpu().flags().to_string()
+    }
+
+    /// Why the last step was interrupted, undefined for a software
+    /// interrupt
+    #[wasm_bindgen(getter)]
+    pub fn fault(&self) -> Option<String> {
+        self.cpu().fault.map(|fault| fault.to_string())
+    }
+
+    /// Registers 0 to 31
+    pub fn registers(&self) -> Vec<u8> {
+        (0..32).map(|register| self.cpu().read(register)).collect()
+    }
+
+    /// Every byte of memory, unmapped addresses read as 0
+    pub fn memory(&self) -> Vec<u8> {
+        (0..=u8::MAX).map(|address| self.cpu().memory.peek(address).unwrap_or(0)).collect()
+    }
+
+    /// The instruction at `address` as assembly
+    pub fn disassemble(&self, address: u32) -> Result<String, JsError> {
+        let cpu = self.cpu();
+        let instruction = cpu.fetch(address).map_err(|fault| JsError::new(&fault.to_string()))?;
+        Ok(cpu.profile().disassemble(&instruction, &cpu.extensions).to_string())
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Emulator::new()
+    }
+}
+
+/// An instruction word as assembly
+#[wasm_bindgen]
+pub fn disassemble(word: u32) -> String {
+    IsaProfile::v0_1_0().disassemble(&Instruction::decode(word), &Extensions::new()).to_string()
+}
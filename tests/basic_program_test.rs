@@ -1,39 +1,29 @@
 use etd3200 as e;
 use std::fs;
-use std::io::Read;
 use e::emulator::UnknownCpu;
+use e::emulator::IsaProfile;
 
 #[test]
 fn can_make_cpu() {
-    let cpu = e::emulator::Cpu::new();
+    let _cpu = e::emulator::Cpu::new();
 }
 
 #[test]
-fn can_load_program() {
-    let mut cpu = e::emulator::Cpu::new_blank();
-    cpu.program_counter = 0;
-    let program = e::program_loader::parse_machine_code(
-        fs::read_to_string("sample_code/1-10.mc").unwrap()
-        );
+fn can_profile_program() {
+    let mut cpu = e::emulator::Cpu::with_profile(IsaProfile::v0_1_0()).unwrap();
+    let source = fs::read_to_string("sample_code/1-10.mc").unwrap();
+    let labels = e::program_loader::parse_labels(&source);
+    let program = e::program_loader::parse_machine_code(source).unwrap();
     for (i, instruction) in program.iter().enumerate() {
-        cpu.load_instruction((i*4) as u8, instruction);
+        cpu.load_instruction((i*4) as u8, instruction).unwrap();
     }
-    let mut limit = 100;
-    let icpu = loop {
-        limit -= 1;
-        if limit == 0 {
-            panic!("Cpu stuck in a loop {cpu}");
-        }
-        //println!("{}", cpu);
-        cpu = match cpu.clock() {
-            UnknownCpu::Ok(cpu) => cpu,
-            UnknownCpu::Inter(cpu) => break cpu,
-        }
-    };
-    println!("{}", icpu);
-    let memory = icpu.memory;
-    for i in 0..11 {
-        assert_eq!(i, memory.read(i + 64).unwrap());
+    let mut profiler = e::emulator::profiler::Profiler::with_labels(labels);
+    match profiler.run(cpu, 100) {
+        UnknownCpu::Ok(cpu) => panic!("Cpu stuck in a loop {cpu}"),
+        UnknownCpu::Inter(_) => (),
     }
-
+    let jump = profiler.counts(28).unwrap();
+    assert_eq!((10, 1), (jump.executed, jump.skipped));
+    assert_eq!(Some("Loop"), profiler.label(28));
+    assert_eq!(Some(28), profiler.hotspots(1).first().map(|(address, _)| *address));
 }
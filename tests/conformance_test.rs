@@ -0,0 +1,421 @@
+//! Property tests of each opcode against a reference model written from the
+//! ETD32 v0.1.0 tables rather than from the emulator's code.
+//!
+//! The v0.1.0 spec has no numbered sections or tables to cite, so each
+//! property is tagged with the spec topic it checks: an opcode group from
+//! its opcode listing, the zero register or the condition flags. Each runs
+//! `CASES` random cases from its own seed, offset from `ETD_SEED` if it's
+//! set, so a failure names the tag, seed and case to replay.
+use etd3200 as e;
+use std::ops::RangeInclusive;
+use rand::prelude::*;
+use e::emulator::{Cpu, Fault, Flags, Instruction, Op, Snapshot, UnknownCpu};
+use e::emulator::builder::{seed_from_env, SEED_VARIABLE};
+use e::emulator::op::{Imm12, Imm22};
+
+const CASES: usize = 500;
+/// Registers 1 to 29 hold values, 30 is valid but reads as zero
+const GENERAL_PURPOSE: u8 = 29;
+const MEMORY: usize = 255;
+
+/// Machine state as the spec describes it
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    pc: u32,
+    flags: Flags,
+    /// Indexed by register number, only 1 to 29 are kept
+    registers: [u8; 32],
+    memory: Vec<u8>,
+    fault: Option<Fault>,
+    interrupted: bool,
+}
+
+impl State {
+    fn random(rng: &mut StdRng) -> State {
+        let mut registers = [0; 32];
+        for register in &mut registers[1..=GENERAL_PURPOSE as usize] {
+            *register = rng.gen();
+        }
+        State {
+            pc: rng.gen_range(0..62) * 4,
+            flags: random_flags(rng),
+            registers,
+            memory: (0..MEMORY).map(|_| rng.gen()).collect(),
+            fault: None,
+            interrupted: false,
+        }
+    }
+
+    fn read(&self, register: u8) -> u8 {
+        match register {
+            1..=GENERAL_PURPOSE => self.registers[register as usize],
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        if (1..=GENERAL_PURPOSE).contains(&register) {
+            self.registers[register as usize] = value;
+        }
+    }
+
+    fn fault(mut self, fault: Fault) -> State {
+        self.fault = Some(fault);
+        self.pc += 4;
+        self.interrupted = true;
+        self
+    }
+
+    /// A cpu in this state with `instruction` at the program counter
+    fn cpu(&mut self, instruction: &Instruction) -> Cpu {
+        let at = self.pc as usize;
+        self.memory[at..at + 4].copy_from_slice(&instruction.encode().to_le_bytes());
+        let mut cpu = Cpu::new_blank();
+        cpu.trace = false;
+        let snapshot = Snapshot {
+            isa: String::from("0.1.0"),
+            cycles: 0,
+            program_counter: self.pc,
+            stack_pointer: 0,
+            flags: self.flags,
+            registers: self.registers[1..=GENERAL_PURPOSE as usize].to_vec(),
+            memory: (0..).zip(self.memory.iter().copied()).collect(),
+        };
+        cpu.restore(&snapshot).unwrap();
+        cpu
+    }
+
+    fn of(result: UnknownCpu) -> State {
+        let interrupted = matches!(result, UnknownCpu::Inter(_));
+        let cpu = result.into_cpu();
+        let snapshot = cpu.snapshot();
+        let mut registers = [0; 32];
+        registers[1..=GENERAL_PURPOSE as usize].copy_from_slice(&snapshot.registers);
+        State {
+            pc: snapshot.program_counter,
+            flags: snapshot.flags,
+            registers,
+            memory: snapshot.memory.iter().map(|(_, value)| *value).collect(),
+            fault: cpu.fault,
+            interrupted,
+        }
+    }
+}
+
+fn random_flags(rng: &mut StdRng) -> Flags {
+    Flags { carry: rng.gen(), greater: rng.gen(), zero: rng.gen(), less: rng.gen() }
+}
+
+/// Conditional execution: every flag set on the instruction must be set
+/// on the cpu
+fn runs(cpu: &Flags, instruction: &Flags) -> bool {
+    (!instruction.carry || cpu.carry) && (!instruction.greater || cpu.greater)
+        && (!instruction.zero || cpu.zero) && (!instruction.less || cpu.less)
+}
+
+/// ALU flags under v0.1.0's unsigned results, less is left alone
+fn set_flags(state: &mut State, result: u8, carry: bool) {
+    state.flags.carry = carry;
+    state.flags.zero = result == 0;
+    state.flags.greater = result > 0;
+}
+
+/// Shifts past the width clear the value, negative ones do nothing
+fn shift(value: u8, by: i32, left: bool) -> u8 {
+    match by {
+        by if by < 0 => value,
+        by if by >= 8 => 0,
+        by if left => value << by,
+        by => value >> by,
+    }
+}
+
+/// Result and carry of an ALU operation on `x` and `y`, the immediate
+/// forms using the immediate's two's complement low byte
+fn alu(opcode: u8, x: u8, y: i32) -> (u8, bool) {
+    let byte = y as u8;
+    match opcode {
+        0 | 1 => (shift(x, y, true), false),
+        2 | 3 => (shift(x, y, false), false),
+        4 | 5 => (x & byte, false),
+        6 | 7 => (x | byte, false),
+        8 | 9 => (x ^ byte, false),
+        10 => (!x, false),
+        11 | 12 => x.overflowing_add(byte),
+        13 | 14 => x.overflowing_sub(byte),
+        15 | 16 => x.overflowing_mul(byte),
+        _ => unreachable!("Not an ALU opcode"),
+    }
+}
+
+fn load(mut state: State, t: u8, address: u32, width: u8) -> State {
+    let mut values = Vec::new();
+    for address in address..address + width as u32 {
+        match state.memory.get(address as usize) {
+            Some(value) => values.push(*value),
+            None => return state.fault(Fault::Unmapped(address)),
+        }
+    }
+    let last = t + width - 1;
+    if last > GENERAL_PURPOSE + 1 {
+        return state.fault(Fault::InvalidRegister(last));
+    }
+    for (register, value) in (t..).zip(values) {
+        state.write(register, value);
+    }
+    state.pc += 4;
+    state
+}
+
+fn store(mut state: State, t: u8, address: u32, width: u8) -> State {
+    if let Some(register) = (t..t + width).find(|register| *register > GENERAL_PURPOSE + 1) {
+        return state.fault(Fault::InvalidRegister(register));
+    }
+    if let Some(address) = (address..address + width as u32).find(|address| *address as usize >= MEMORY) {
+        return state.fault(Fault::Unmapped(address));
+    }
+    for (offset, register) in (t..t + width).enumerate() {
+        state.memory[address as usize + offset] = state.read(register);
+    }
+    state.pc += 4;
+    state
+}
+
+/// Jump targets must be word aligned with a whole instruction in memory
+fn jump(mut state: State, target: i64) -> State {
+    let Ok(target) = u32::try_from(target) else {
+        return state.fault(Fault::Unmapped(target as u32));
+    };
+    if target % 4 != 0 {
+        return state.fault(Fault::Misaligned { address: target, width: 4 });
+    }
+    if target as usize + 4 > MEMORY {
+        return state.fault(Fault::Unmapped(target));
+    }
+    state.pc = target;
+    state
+}
+
+/// The state after running `op` with condition `flags`
+fn model(state: &State, flags: &Flags, op: Op) -> State {
+    let mut state = state.clone();
+    if !runs(&state.flags, flags) {
+        state.pc += 4;
+        return state;
+    }
+    let opcode = (u32::from(op) >> 22) as u8;
+    match op {
+        Op::LslRd { d, x, y, .. } | Op::LsrRd { d, x, y, .. } | Op::AndRd { d, x, y, .. }
+        | Op::OrRd { d, x, y, .. } | Op::XorRd { d, x, y, .. } | Op::NotRd { d, x, y, .. }
+        | Op::AddRd { d, x, y, .. } | Op::SubRd { d, x, y, .. } | Op::MulRd { d, x, y, .. } => {
+            let (result, carry) = alu(opcode, state.read(x), state.read(y) as i32);
+            set_flags(&mut state, result, carry);
+            state.write(d, result);
+            state.pc += 4;
+            state
+        }
+        Op::LslRi { d, x, i } | Op::LsrRi { d, x, i } | Op::AndRi { d, x, i } | Op::OrRi { d, x, i }
+        | Op::XorRi { d, x, i } | Op::AddRi { d, x, i } | Op::SubRi { d, x, i } | Op::MulRi { d, x, i } => {
+            let (result, carry) = alu(opcode, state.read(x), i.value());
+            set_flags(&mut state, result, carry);
+            state.write(d, result);
+            state.pc += 4;
+            state
+        }
+        Op::Load8Bo { t, base, off } => { let a = state.read(base) as u32 + off as u32; load(state, t, a, 1) }
+        Op::Load16Bo { t, base, off } => { let a = state.read(base) as u32 + off as u32; load(state, t, a, 2) }
+        Op::Load32Bo { t, base, off } => { let a = state.read(base) as u32 + off as u32; load(state, t, a, 4) }
+        Op::Load8Bi { t, base, index, .. } => { let a = state.read(base) as u32 + state.read(index) as u32; load(state, t, a, 1) }
+        Op::Load16Bi { t, base, index, .. } => { let a = state.read(base) as u32 + state.read(index) as u32; load(state, t, a, 2) }
+        Op::Load32Bi { t, base, index, .. } => { let a = state.read(base) as u32 + state.read(index) as u32; load(state, t, a, 4) }
+        Op::Store8Bo { t, base, off } => { let a = state.read(base) as u32 + off as u32; store(state, t, a, 1) }
+        Op::Store16Bo { t, base, off } => { let a = state.read(base) as u32 + off as u32; store(state, t, a, 2) }
+        Op::Store32Bo { t, base, off } => { let a = state.read(base) as u32 + off as u32; store(state, t, a, 4) }
+        Op::Store8Bi { t, base, index, .. } => { let a = state.read(base) as u32 + state.read(index) as u32; store(state, t, a, 1) }
+        Op::Store16Bi { t, base, index, .. } => { let a = state.read(base) as u32 + state.read(index) as u32; store(state, t, a, 2) }
+        Op::Store32Bi { t, base, index, .. } => { let a = state.read(base) as u32 + state.read(index) as u32; store(state, t, a, 4) }
+        Op::JumpOffset { offset } => { let target = state.pc as i64 + offset.value() as i64; jump(state, target) }
+        Op::JumpRd { d, .. } => { let target = state.read(d) as i64; jump(state, target) }
+        Op::JumpI { target } => jump(state, target.value() as u32 as i64),
+        Op::Interrupt { .. } => {
+            state.interrupted = true;
+            state
+        }
+        Op::Reserved { opcode, .. } => state.fault(Fault::UnknownOpcode(opcode)),
+    }
+}
+
+/// Mostly registers that hold values, sometimes the zero register or the
+/// ones above the register file
+fn register(rng: &mut StdRng) -> u8 {
+    match rng.gen_range(0..10) {
+        0 => 0,
+        1 => rng.gen_range(30..32),
+        _ => rng.gen_range(1..=GENERAL_PURPOSE),
+    }
+}
+
+fn imm12(rng: &mut StdRng) -> Imm12 {
+    match rng.gen_range(0..4) {
+        0 => Imm12::from_raw(rng.gen()),
+        _ => Imm12::from_value(rng.gen_range(-12..12)).unwrap(),
+    }
+}
+
+/// An offset that usually keeps the access inside memory
+fn offset(rng: &mut StdRng) -> u16 {
+    match rng.gen_range(0..4) {
+        0 => rng.gen_range(0..0x1000),
+        _ => rng.gen_range(0..8),
+    }
+}
+
+fn imm22(rng: &mut StdRng, near: i32) -> Imm22 {
+    match rng.gen_range(0..3) {
+        0 => Imm22::from_raw(rng.gen()),
+        _ => Imm22::from_value(near + rng.gen_range(-64..64)).unwrap(),
+    }
+}
+
+/// A random instruction with `opcode`, using the canonical v0.1.0 numbering
+fn random_op(rng: &mut StdRng, opcode: u8) -> Op {
+    let (d, x, y) = (register(rng), register(rng), register(rng));
+    let pad = rng.gen_range(0..0x80);
+    let raw = match Op::from((opcode as u32) << 22) {
+        Op::LslRd { .. } | Op::LsrRd { .. } | Op::AndRd { .. } | Op::OrRd { .. } | Op::XorRd { .. }
+        | Op::NotRd { .. } | Op::AddRd { .. } | Op::SubRd { .. } | Op::MulRd { .. }
+        | Op::Load8Bi { .. } | Op::Load16Bi { .. } | Op::Load32Bi { .. }
+        | Op::Store8Bi { .. } | Op::Store16Bi { .. } | Op::Store32Bi { .. } =>
+            (d as u32) << 17 | (x as u32) << 12 | (y as u32) << 7 | pad,
+        Op::LslRi { .. } | Op::LsrRi { .. } | Op::AndRi { .. } | Op::OrRi { .. } | Op::XorRi { .. }
+        | Op::AddRi { .. } | Op::SubRi { .. } | Op::MulRi { .. } =>
+            (d as u32) << 17 | (x as u32) << 12 | imm12(rng).raw(),
+        Op::Load8Bo { .. } | Op::Load16Bo { .. } | Op::Load32Bo { .. }
+        | Op::Store8Bo { .. } | Op::Store16Bo { .. } | Op::Store32Bo { .. } =>
+            (d as u32) << 17 | (x as u32) << 12 | offset(rng) as u32,
+        Op::JumpOffset { .. } => imm22(rng, 0).raw(),
+        Op::JumpI { .. } => imm22(rng, 128).raw(),
+        Op::JumpRd { .. } => (d as u32) << 17 | rng.gen_range(0..1 << 17),
+        Op::Interrupt { .. } | Op::Reserved { .. } => rng.gen_range(0..1 << 22),
+    };
+    Op::from((opcode as u32) << 22 | raw)
+}
+
+/// A group of opcodes checked against the model, tagged with the spec
+/// topic it covers
+struct Property {
+    tag: &'static str,
+    opcodes: RangeInclusive<u8>,
+    /// Changes a random state and instruction to focus the property
+    focus: fn(&mut StdRng, &mut State, &mut Flags, &mut Op),
+}
+
+fn unfocused(_: &mut StdRng, _: &mut State, _: &mut Flags, _: &mut Op) {}
+
+/// Runs with the condition flags clear unless a property sets them
+fn unconditional(_: &mut StdRng, _: &mut State, flags: &mut Flags, _: &mut Op) {
+    *flags = Flags::new();
+}
+
+/// Points one of the operands at the zero register
+fn zero_register(rng: &mut StdRng, _: &mut State, flags: &mut Flags, op: &mut Op) {
+    *flags = Flags::new();
+    let bits = u32::from(*op);
+    let field = [17, 12, 7][rng.gen_range(0..3)];
+    *op = Op::from(bits & !(0x1F << field));
+}
+
+fn predicated(rng: &mut StdRng, _: &mut State, flags: &mut Flags, _: &mut Op) {
+    while *flags == Flags::new() {
+        *flags = random_flags(rng);
+    }
+}
+
+const PROPERTIES: &[Property] = &[
+    Property { tag: "opcodes/shifts", opcodes: 0..=3, focus: unconditional },
+    Property { tag: "opcodes/logic", opcodes: 4..=10, focus: unconditional },
+    Property { tag: "opcodes/arithmetic", opcodes: 11..=16, focus: unconditional },
+    Property { tag: "opcodes/loads", opcodes: 17..=22, focus: unconditional },
+    Property { tag: "opcodes/stores", opcodes: 23..=28, focus: unconditional },
+    Property { tag: "opcodes/jumps", opcodes: 29..=31, focus: unconditional },
+    Property { tag: "opcodes/interrupt", opcodes: 32..=32, focus: unconditional },
+    Property { tag: "opcodes/reserved", opcodes: 33..=63, focus: unconditional },
+    Property { tag: "registers/zero", opcodes: 0..=28, focus: zero_register },
+    Property { tag: "flags/conditional-execution", opcodes: 0..=32, focus: predicated },
+    Property { tag: "flags/any", opcodes: 0..=63, focus: unfocused },
+];
+
+/// Checks one property, returning the first failing case
+fn check(property: &Property, seed: u64) -> Result<(), String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for case in 0..CASES {
+        let opcode = rng.gen_range(property.opcodes.clone());
+        let mut state = State::random(&mut rng);
+        let mut flags = random_flags(&mut rng);
+        let mut op = random_op(&mut rng, opcode);
+        (property.focus)(&mut rng, &mut state, &mut flags, &mut op);
+
+        let instruction = Instruction::new(flags, op);
+        let cpu = state.cpu(&instruction);
+        let expected = model(&state, &flags, op);
+        let actual = State::of(cpu.clock());
+        if expected != actual {
+            return Err(format!("{} failed case {case} of seed {seed}: {op:?} with condition {flags}, cpu flags {}\n{}",
+                property.tag, state.flags, difference(&expected, &actual)));
+        }
+    }
+    Ok(())
+}
+
+fn difference(expected: &State, actual: &State) -> String {
+    let mut out = String::new();
+    if (expected.pc, expected.flags, expected.fault, expected.interrupted) != (actual.pc, actual.flags, actual.fault, actual.interrupted) {
+        out += &format!("expected pc {} flags {} fault {:?} interrupted {}\n", expected.pc, expected.flags, expected.fault, expected.interrupted);
+        out += &format!("got      pc {} flags {} fault {:?} interrupted {}\n", actual.pc, actual.flags, actual.fault, actual.interrupted);
+    }
+    for register in 0..32 {
+        let (a, b) = (expected.registers[register], actual.registers[register]);
+        if a != b {
+            out += &format!("r{register}: expected {a}, got {b}\n");
+        }
+    }
+    for (address, (a, b)) in expected.memory.iter().zip(&actual.memory).enumerate() {
+        if a != b {
+            out += &format!("memory {address}: expected {a}, got {b}\n");
+        }
+    }
+    out
+}
+
+#[test]
+fn conforms_to_the_model() {
+    let base = seed_from_env().unwrap_or(0);
+    let failures: Vec<String> = PROPERTIES.iter()
+        .enumerate()
+        .filter_map(|(index, property)| check(property, base.wrapping_add(index as u64)).err())
+        .collect();
+    assert!(failures.is_empty(), "{}\nReplay with {SEED_VARIABLE}={base}", failures.join("\n"));
+}
+
+#[test]
+fn covers_every_opcode() {
+    for opcode in 0..=32u8 {
+        let tags: Vec<&str> = PROPERTIES.iter()
+            .filter(|property| property.opcodes.contains(&opcode) && property.tag.starts_with("opcodes/"))
+            .map(|property| property.tag)
+            .collect();
+        assert_eq!(1, tags.len(), "Opcode {opcode} is covered by {tags:?}");
+    }
+}
+
+#[test]
+fn model_matches_known_results() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut state = State::random(&mut rng);
+    state.registers[1] = 200;
+    let add = Op::AddRi { d: 2, x: 1, i: Imm12::from_value(100).unwrap() };
+    let after = model(&state, &Flags::new(), add);
+    assert_eq!((44, true, true), (after.registers[2], after.flags.carry, after.flags.greater));
+    let load = Op::Load32Bo { t: 28, base: 0, off: 0 };
+    assert_eq!(Some(Fault::InvalidRegister(31)), model(&state, &Flags::new(), load).fault);
+}
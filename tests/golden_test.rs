@@ -0,0 +1,19 @@
+//! Runs every `.mc` program with a `.expect` file, see `src/golden.rs`
+use etd3200::golden::run_dir;
+
+fn check(directory: &str) {
+    let outcomes = run_dir(directory).unwrap();
+    assert!(!outcomes.is_empty(), "No programs in {directory}");
+    let failures: String = outcomes.iter().map(|outcome| outcome.report()).collect();
+    assert!(failures.is_empty(), "\n{failures}");
+}
+
+#[test]
+fn programs_match_expectations() {
+    check("tests/programs");
+}
+
+#[test]
+fn samples_match_expectations() {
+    check("sample_code");
+}
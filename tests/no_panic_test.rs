@@ -0,0 +1,59 @@
+//! The invariants the fuzz targets in `fuzz/` check, run over random
+//! inputs and the sample code so a panic shows up without cargo-fuzz
+use etd3200 as e;
+use std::fs;
+use rand::prelude::*;
+use e::emulator::{Cpu, Differential, Engine, Instruction, IsaProfile};
+use e::emulator::builder::{seed_from_env, Seed};
+
+/// Inputs drawn from `ETD_SEED`, or the same seed every run. The seed is
+/// printed if the test fails while it's held.
+fn seeded() -> (StdRng, Seed) {
+    let seed = seed_from_env().unwrap_or(43);
+    (StdRng::seed_from_u64(seed), Seed(seed))
+}
+
+#[test]
+fn decodes_any_word() {
+    let (mut rng, _seed) = seeded();
+    let profile = IsaProfile::v0_1_0();
+    for _ in 0..10_000 {
+        let word = rng.gen();
+        let instruction = Instruction::decode(word);
+        assert_eq!(Ok(word), instruction.try_encode());
+        let _ = profile.disassemble(&instruction, &Default::default()).to_string();
+    }
+}
+
+#[test]
+fn runs_any_program() {
+    let (mut rng, _seed) = seeded();
+    for _ in 0..200 {
+        let pc = if rng.gen_bool(0.9) { rng.gen_range(0..64) * 4 } else { rng.gen() };
+        let image: Vec<u8> = (0..256).map(|_| rng.gen()).collect();
+        let mut differential = Differential::new(&Engine::all(), || Cpu::with_image(pc, &image)).unwrap();
+        if let Err(divergence) = differential.run(200) {
+            panic!("{divergence}");
+        }
+    }
+}
+
+#[test]
+fn loads_any_text() {
+    let (mut rng, _seed) = seeded();
+    let alphabet = ['0', '1', '#', ':', ' ', '\n', '-', 'a'];
+    let sample = fs::read_to_string("sample_code/1-10.mc").unwrap();
+    assert!(e::program_loader::parse_machine_code(sample.clone()).is_ok());
+    for _ in 0..1000 {
+        let mut text: String = (0..rng.gen_range(0..200))
+            .map(|_| *alphabet.choose(&mut rng).unwrap())
+            .collect();
+        if rng.gen_bool(0.5) {
+            let at = rng.gen_range(0..sample.len());
+            text = format!("{}{}", &sample[..at], text);
+        }
+        let _ = e::program_loader::parse_machine_code(text.clone());
+        let _ = e::program_loader::parse_labels(&text);
+        let _ = e::program_loader::parse_source_lines(&text);
+    }
+}
@@ -0,0 +1,50 @@
+//! The JavaScript API, run headlessly in Node with
+//! `cargo test --target wasm32-unknown-unknown --test wasm_test`
+#![cfg(target_arch = "wasm32")]
+use etd3200::wasm::{disassemble, Emulator};
+use wasm_bindgen_test::*;
+
+/// Puts 5 in r1 then interrupts
+const PROGRAM: &str = "0000-001100-00001 00000 00000 0000101\n0000-100000-00000 00000 00000 0000000";
+
+fn loaded() -> Emulator {
+    let mut emulator = Emulator::new();
+    assert_eq!(2, emulator.load_machine_code(PROGRAM, 0).unwrap());
+    emulator
+}
+
+#[wasm_bindgen_test]
+fn steps_a_program() {
+    let mut emulator = loaded();
+    assert!(!emulator.step());
+    assert_eq!(5, emulator.registers()[1]);
+    assert_eq!(4, emulator.pc());
+    assert!(emulator.step());
+    assert_eq!(None, emulator.fault());
+    assert_eq!(5, emulator.cycles());
+}
+
+#[wasm_bindgen_test]
+fn runs_to_a_budget() {
+    let mut emulator = loaded();
+    assert!(emulator.run(1000));
+    let mut emulator = loaded();
+    assert!(!emulator.run(1));
+    assert_eq!(4, emulator.pc());
+}
+
+#[wasm_bindgen_test]
+fn inspects_memory() {
+    let emulator = loaded();
+    let memory = emulator.memory();
+    assert_eq!(255 + 1, memory.len());
+    assert_eq!(0b101, memory[0]);
+    assert_eq!(disassemble(u32::from_le_bytes(memory[..4].try_into().unwrap())), emulator.disassemble(0).unwrap());
+    assert!(emulator.disassemble(300).is_err());
+}
+
+#[wasm_bindgen_test]
+fn seeds_repeat() {
+    assert_eq!(Emulator::seeded(7).memory(), Emulator::seeded(7).memory());
+    assert_eq!(Emulator::seeded(7).registers(), Emulator::seeded(7).registers());
+}